@@ -0,0 +1,72 @@
+// Compares the nonce search loop hashing the full header with double_hash and comparing
+// BigUint numbers against the midstate HeaderHasher comparing fixed [u8; 32] numbers.
+// The target is unreachable so every nonce of the range gets hashed.
+// Run with: cargo bench --bench header_hashing
+
+use code_challenge_f321x::mining::compact_target::CompactTarget;
+use code_challenge_f321x::mining::header_hasher::{hash_meets_target, HeaderHasher};
+use code_challenge_f321x::mining::BlockTemplateConfig;
+use code_challenge_f321x::validation::utils::double_hash;
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use num_bigint::BigUint;
+
+const NONCES: u32 = 10_000;
+
+// header of the exercise block template without nonce, with a fixed merkle root and time
+fn header_without_nonce() -> Vec<u8> {
+    let config = BlockTemplateConfig::default();
+    let mut block_header: Vec<u8> = Vec::new();
+    block_header.extend(config.version.to_le_bytes());
+    block_header.extend(config.previous_block_hash_bytes());
+    block_header.extend(double_hash(b"merkle root"));
+    block_header.extend(1_713_000_000_u32.to_le_bytes());
+    block_header.extend(config.bits.to_le_bytes());
+    block_header
+}
+
+// nonce search loop hashing the header + nonce with double_hash (allocating) and comparing as BigUint
+fn search_double_hash(block_header: &[u8], target: &BigUint) -> Option<u32> {
+    let mut candidate = block_header.to_vec();
+    candidate.extend(0_u32.to_le_bytes());
+    for nonce in 0..NONCES {
+        candidate[76..].copy_from_slice(&nonce.to_le_bytes());
+        if BigUint::from_bytes_le(&double_hash(&candidate)) <= *target {
+            return Some(nonce);
+        }
+    }
+    None
+}
+
+// nonce search loop hashing from the midstate and comparing as [u8; 32]
+fn search_midstate(block_header: &[u8], target: &[u8; 32]) -> Option<u32> {
+    let hasher = HeaderHasher::new(block_header);
+    (0..NONCES).find(|nonce| hash_meets_target(&hasher.hash(*nonce), target))
+}
+
+fn header_hashing(c: &mut Criterion) {
+    let block_header = header_without_nonce();
+    let unreachable_bits = CompactTarget(0x03000001);
+    let target = unreachable_bits.to_target().expect("valid bits");
+    let target_bytes = unreachable_bits.to_target_bytes().expect("valid bits");
+
+    // both loops have to produce the same hashes
+    let mut candidate = block_header.clone();
+    candidate.extend(7_u32.to_le_bytes());
+    assert_eq!(
+        HeaderHasher::new(&block_header).hash(7).to_vec(),
+        double_hash(&candidate)
+    );
+
+    let mut group = c.benchmark_group("nonce_search");
+    group.throughput(Throughput::Elements(NONCES as u64));
+    group.bench_function("double_hash_biguint", |b| {
+        b.iter(|| search_double_hash(black_box(&block_header), black_box(&target)))
+    });
+    group.bench_function("midstate_fixed_bytes", |b| {
+        b.iter(|| search_midstate(black_box(&block_header), black_box(&target_bytes)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, header_hashing);
+criterion_main!(benches);
@@ -0,0 +1,8 @@
+// Library part of the block construction program. Holds transaction parsing, validation
+// and block construction ("mining") so they can be used by the binary and the tests.
+pub mod mining;
+pub mod parsing;
+pub mod validation;
+
+#[cfg(test)]
+mod test_utils;
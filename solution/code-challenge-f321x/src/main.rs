@@ -1,15 +1,15 @@
-pub mod mining;
-pub mod parsing;
 mod utils_main;
-pub mod validation;
 
-use mining::{mine_block, Block};
-use parsing::{parse_transactions_from_dir, transaction_structs::Transaction};
+use code_challenge_f321x::mining::{mine_block, Block, BlockTemplateConfig};
+use code_challenge_f321x::parsing::{
+    parse_transactions_from_dir, transaction_structs::Transaction,
+};
+use code_challenge_f321x::validation::{validate_block::validate_block_file, ValidationResult};
 use std::collections::HashSet;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::prelude::*;
+use std::path::Path;
 use utils_main::remove_invalid_transactions;
-use validation::ValidationResult;
 
 // writes the final content stored in the Block struct to the passed output_path
 // as output.txt formatted according to the exercise specification
@@ -29,6 +29,16 @@ fn output_block(mined_block: &Block, output_path: &str) {
     }
 }
 
+// writes the serialized block as hex (block.hex) and binary (block.bin) file into the
+// passed directory, e.g. to submit it to a regtest node or load it in a block parser
+fn output_raw_block(mined_block: &Block, output_dir: &str) {
+    let raw_block = mined_block.serialize();
+    let path = Path::new(output_dir);
+
+    fs::write(path.join("block.hex"), hex::encode(&raw_block)).expect("Unable to write block.hex");
+    fs::write(path.join("block.bin"), &raw_block).expect("Unable to write block.bin");
+}
+
 // calls validate() on each Transaction in the passed Vec of Transaction
 // returns: HashSet(txid as hex String) of all invalid and untested transactions
 fn validate_transactions(parsed_transactions: &mut Vec<Transaction>) -> HashSet<String> {
@@ -46,6 +56,44 @@ fn validate_transactions(parsed_transactions: &mut Vec<Transaction>) -> HashSet<
 }
 
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    // --validate-block <file> validates an output.txt, block.hex or block.bin against the mempool
+    if let Some(index) = args.iter().position(|arg| arg == "--validate-block") {
+        let block_path = args
+            .get(index + 1)
+            .expect("--validate-block needs a block file");
+        match validate_block_file(block_path, "../../mempool") {
+            ValidationResult::Valid => println!("Block valid"),
+            ValidationResult::Invalid(reason) => {
+                println!("Block invalid: {}", reason);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    // --raw-block additionally writes the serialized block as block.hex and block.bin
+    let write_raw_block = args.iter().any(|arg| arg == "--raw-block");
+
+    // block template parameters from --block-config <file> and single value flags
+    let config = match BlockTemplateConfig::from_args(&args) {
+        Ok(config) => config,
+        Err(err) => {
+            println!("Invalid block template config: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    // confirmations of the spent outputs to evaluate relative locks (BIP68)
+    let utxo_confirmations = match config.load_utxo_confirmations() {
+        Ok(utxo_confirmations) => utxo_confirmations,
+        Err(err) => {
+            println!("Invalid utxo confirmations: {}", err);
+            std::process::exit(1);
+        }
+    };
+
     // parses all json transactions in a Vec of Transaction structs
     let mut parsed_transactions = parse_transactions_from_dir("../../mempool");
 
@@ -57,12 +105,32 @@ fn main() {
     remove_invalid_transactions(parsed_transactions, invalid_transactions);
 
     // returns a Block struckt containing header, coinbase and final transaction list
-    let block: Block = mine_block(&mut valid_transactions);
+    let block: Block = match mine_block(&mut valid_transactions, &config, &utxo_confirmations) {
+        Ok(block) => block,
+        Err(err) => {
+            println!("Mining failed: {}", err);
+            std::process::exit(1);
+        }
+    };
 
     // writes blockfile to output.txt according to exercise specification
     output_block(&block, "../../output.txt");
+    if write_raw_block {
+        output_raw_block(&block, "../../");
+    }
+    let stats = &block.mining_stats;
+    println!(
+        "\nDone. Number of mined transactions: {}, fees: {} sat\n",
+        &block.txids_hex.len(),
+        block.fees
+    );
     println!(
-        "\nDone. Number of mined transactions: {}\n",
-        &block.txids_hex.len()
+        "Mined with {} threads: {} hashes in {:.2}s ({:.2} MH/s), extranonce {}, nTime rolls {}\n",
+        stats.threads,
+        stats.hashes,
+        stats.duration.as_secs_f64(),
+        stats.hashrate() / 1_000_000.0,
+        stats.extranonce,
+        stats.time_rolls
     );
 }
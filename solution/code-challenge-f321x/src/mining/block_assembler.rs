@@ -0,0 +1,316 @@
+use super::block_weight::BlockWeightBudget;
+use super::packet_weight::MempoolRelatives;
+use crate::parsing::transaction_structs::Transaction;
+use crate::validation::sigops::MAX_BLOCK_SIGOPS_COST;
+use crate::validation::validate_block::MAX_BLOCK_WEIGHT;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+
+// sigop cost reserved for the coinbase transaction
+pub(super) const RESERVED_SIGOPS_COST: u64 = 400;
+// give up filling the block after this many packages in a row didn't fit an almost full block
+const MAX_CONSECUTIVE_FAILURES: u64 = 1000;
+// a block with less free weight counts as almost full
+const ALMOST_FULL_FREE_WEIGHT: u64 = 4000;
+
+// fee, weight, sigop-adjusted vsize, sigop cost and count of a transaction together with all
+// its ancestors that are not in the block yet ("modified" ancestor state of core)
+#[derive(Clone, Copy, Default)]
+struct AncestorState {
+    fee: u64,
+    weight: u64,
+    size: u64,
+    sigops_cost: u64,
+    count: usize,
+}
+
+impl AncestorState {
+    // returns: state with the transaction added
+    fn add(self, tx: &Transaction) -> AncestorState {
+        AncestorState {
+            fee: self.fee + tx.meta.fee,
+            weight: self.weight + tx.meta.weight,
+            size: self.size + tx.sigop_adjusted_vsize(),
+            sigops_cost: self.sigops_cost + tx.meta.sigops_cost,
+            count: self.count + 1,
+        }
+    }
+
+    // returns: state with the transaction removed
+    fn remove(self, tx: &Transaction) -> AncestorState {
+        AncestorState {
+            fee: self.fee - tx.meta.fee,
+            weight: self.weight - tx.meta.weight,
+            size: self.size - tx.sigop_adjusted_vsize(),
+            sigops_cost: self.sigops_cost - tx.meta.sigops_cost,
+            count: self.count - 1,
+        }
+    }
+}
+
+// key of the candidate set, ordered by ancestor feerate (fee / sigop-adjusted vsize like
+// the ancestor score of core), ties by index
+#[derive(PartialEq, Eq)]
+pub(super) struct AncestorScore {
+    pub(super) fee: u64,
+    pub(super) size: u64,
+    pub(super) index: usize,
+}
+
+impl Ord for AncestorScore {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let feerate = self.fee as u128 * other.size as u128;
+        let other_feerate = other.fee as u128 * self.size as u128;
+        feerate
+            .cmp(&other_feerate)
+            .then_with(|| other.index.cmp(&self.index))
+    }
+}
+
+impl PartialOrd for AncestorScore {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Selects the block transactions like addPackageTxs of bitcoin core: the transaction with the
+// highest ancestor feerate (by sigop-adjusted vsize) is added together with all its ancestors
+// not in the block yet (each ancestor counted once). Afterwards the ancestor state of all
+// descendants of the added transactions is reduced by them, so their score reflects only the
+// ancestors they still need.
+// Packages exceeding the remaining weight or sigop budget are skipped.
+pub struct BlockAssembler<'a> {
+    transactions: Vec<&'a Transaction>,
+    relatives: MempoolRelatives,
+    states: Vec<AncestorState>,
+    candidates: BTreeSet<AncestorScore>,
+    in_block: Vec<bool>,
+    block: Vec<usize>,
+    budget: BlockWeightBudget,
+    transactions_weight: u64, // summed up weight of the transactions in the block
+    block_sigops_cost: u64,
+}
+
+impl<'a> BlockAssembler<'a> {
+    // builds the transaction graph from the parents set by assign_mempool_parents and
+    // calculates the ancestor states of all transactions
+    // returns: BlockAssembler
+    pub fn new(
+        txid_tx_map: &'a HashMap<String, Transaction>,
+        budget: BlockWeightBudget,
+    ) -> BlockAssembler<'a> {
+        let relatives = MempoolRelatives::new(txid_tx_map);
+        let transactions = relatives.indexed_transactions(txid_tx_map);
+        let states: Vec<AncestorState> = (0..transactions.len())
+            .map(|index| {
+                relatives
+                    .ancestor_indices(index)
+                    .iter()
+                    .chain(std::iter::once(&index))
+                    .fold(AncestorState::default(), |state, &member| {
+                        state.add(transactions[member])
+                    })
+            })
+            .collect();
+
+        let mut block_assembler = BlockAssembler {
+            in_block: vec![false; transactions.len()],
+            transactions,
+            relatives,
+            states,
+            candidates: BTreeSet::new(),
+            block: Vec::new(),
+            budget,
+            transactions_weight: 0,
+            block_sigops_cost: RESERVED_SIGOPS_COST,
+        };
+        for index in 0..block_assembler.transactions.len() {
+            let score = block_assembler.score(index);
+            block_assembler.candidates.insert(score);
+        }
+        block_assembler
+    }
+
+    // returns: candidate set key of the transaction with its current ancestor state
+    fn score(&self, index: usize) -> AncestorScore {
+        AncestorScore {
+            fee: self.states[index].fee,
+            size: self.states[index].size,
+            index,
+        }
+    }
+
+    // returns: true if the package fits in the remaining weight and sigop budget (TestPackage)
+    fn test_package(&self, state: &AncestorState) -> bool {
+        self.budget.fits(
+            self.transactions_weight + state.weight,
+            self.block.len() + state.count,
+        ) && self.block_sigops_cost + state.sigops_cost < MAX_BLOCK_SIGOPS_COST
+    }
+
+    // adds the transaction to the block and removes it from the candidates
+    fn add_to_block(&mut self, index: usize) {
+        let score = self.score(index);
+        self.candidates.remove(&score);
+        self.in_block[index] = true;
+        self.block.push(index);
+        self.transactions_weight += self.transactions[index].meta.weight;
+        self.block_sigops_cost += self.transactions[index].meta.sigops_cost;
+    }
+
+    // removes the added transactions from the ancestor state of all their descendants
+    // not in the block and re-sorts them in the candidate set (UpdatePackagesForAdded)
+    fn update_packages_for_added(&mut self, added: &[usize]) {
+        for &added_index in added {
+            let tx = self.transactions[added_index];
+            for &descendant in self.relatives.descendant_indices(added_index) {
+                if self.in_block[descendant] {
+                    continue;
+                }
+                // skipped (failed) packages aren't candidates anymore and stay removed
+                let score = self.score(descendant);
+                let was_candidate = self.candidates.remove(&score);
+                self.states[descendant] = self.states[descendant].remove(tx);
+                if was_candidate {
+                    let score = self.score(descendant);
+                    self.candidates.insert(score);
+                }
+            }
+        }
+    }
+
+    // selects packages by ancestor feerate till the block is full or no candidates are left
+    // returns: selected transactions in block order (parents before children)
+    pub fn add_package_txs(mut self) -> Vec<Transaction> {
+        let mut consecutive_failures: u64 = 0;
+
+        while let Some(best) = self.candidates.pop_last() {
+            let state = self.states[best.index];
+
+            if !self.test_package(&state) {
+                consecutive_failures += 1;
+                if consecutive_failures > MAX_CONSECUTIVE_FAILURES
+                    && self
+                        .budget
+                        .block_weight(self.transactions_weight, self.block.len())
+                        > MAX_BLOCK_WEIGHT - ALMOST_FULL_FREE_WEIGHT
+                {
+                    break; // block is almost full
+                }
+                continue;
+            }
+
+            // ancestors not yet in the block, sorted by ancestor count to put parents first
+            let mut package: Vec<usize> = self
+                .relatives
+                .ancestor_indices(best.index)
+                .iter()
+                .copied()
+                .filter(|&ancestor| !self.in_block[ancestor])
+                .chain(std::iter::once(best.index))
+                .collect();
+            package.sort_by_key(|&member| (self.relatives.ancestor_indices(member).len(), member));
+
+            for &member in &package {
+                self.add_to_block(member);
+            }
+            self.update_packages_for_added(&package);
+            consecutive_failures = 0;
+        }
+
+        self.block
+            .iter()
+            .map(|&index| self.transactions[index].clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mining::BlockTemplateConfig;
+    use crate::test_utils::{block_txids, mempool, package_tx as tx, parents_first, txid};
+
+    const WEIGHT: u64 = 400; // 100 vbyte
+
+    // returns: block transactions selected by ancestor score
+    fn select(transactions: Vec<Transaction>) -> Vec<Transaction> {
+        let txid_tx_map = mempool(transactions);
+        let budget = BlockWeightBudget::new(&BlockTemplateConfig::default());
+        BlockAssembler::new(&txid_tx_map, budget).add_package_txs()
+    }
+
+    #[test]
+    fn child_pays_for_parent() {
+        let block = select(vec![
+            tx(1, &[], 100, WEIGHT, 0),
+            tx(2, &[1], 10_000, WEIGHT, 0),
+            tx(3, &[], 2000, WEIGHT, 0),
+        ]);
+        assert!(parents_first(&block));
+        assert_eq!(block_txids(&block), vec![txid(1), txid(2), txid(3)]);
+    }
+
+    #[test]
+    fn diamond_counts_shared_ancestor_once() {
+        // 4 spends 2 and 3, both spend 1. With 1 counted once the package of 4 has 35.25 sat/vbyte,
+        // counted twice 28.4 sat/vbyte, below the 30 sat/vbyte of 5
+        let block = select(vec![
+            tx(1, &[], 100, WEIGHT, 0),
+            tx(2, &[1], 1000, WEIGHT, 0),
+            tx(3, &[1], 3000, WEIGHT, 0),
+            tx(4, &[2, 3], 10_000, WEIGHT, 0),
+            tx(5, &[], 3000, WEIGHT, 0),
+        ]);
+        assert!(parents_first(&block));
+        assert_eq!(
+            block_txids(&block),
+            vec![txid(1), txid(2), txid(3), txid(4), txid(5)]
+        );
+    }
+
+    #[test]
+    fn sigop_heavy_transactions() {
+        // scored by the sigop-adjusted vsize: 400 sigop cost count as 2000 vbyte
+        let block = select(vec![
+            tx(1, &[], 5000, WEIGHT, 400),
+            tx(2, &[], 1000, WEIGHT, 0),
+        ]);
+        assert_eq!(block_txids(&block), vec![txid(2), txid(1)]);
+
+        // 2 would reach the sigop limit with the cost reserved for the coinbase
+        let block = select(vec![
+            tx(1, &[], 1_000_000, WEIGHT, 40_000),
+            tx(2, &[], 900_000, WEIGHT, 39_600),
+            tx(3, &[], 800_000, WEIGHT, 39_599),
+        ]);
+        assert_eq!(block_txids(&block), vec![txid(1), txid(3)]);
+        let sigops_cost: u64 = block.iter().map(|tx| tx.meta.sigops_cost).sum();
+        assert!(sigops_cost + RESERVED_SIGOPS_COST < MAX_BLOCK_SIGOPS_COST);
+    }
+
+    #[test]
+    fn almost_full_block() {
+        // 1 leaves 1000 weight units free in a block of three transactions, 2 doesn't fit
+        // anymore but the lower feerate 3 fills the block
+        let budget = BlockWeightBudget::new(&BlockTemplateConfig::default());
+        let filler_weight = MAX_BLOCK_WEIGHT - budget.block_weight(0, 3) - 1000;
+        let block = select(vec![
+            tx(1, &[], filler_weight, filler_weight, 0),
+            tx(2, &[], 1500, 2000, 0),
+            tx(3, &[], 500, 1000, 0),
+        ]);
+        assert_eq!(block_txids(&block), vec![txid(1), txid(3)]);
+        let weight: u64 = block.iter().map(|tx| tx.meta.weight).sum();
+        assert_eq!(budget.block_weight(weight, block.len()), MAX_BLOCK_WEIGHT);
+
+        // the package of 3 doesn't fit, 3 alone would but can't be added without its parent
+        let block = select(vec![
+            tx(1, &[], filler_weight, filler_weight, 0),
+            tx(2, &[], 100, 800, 0),
+            tx(3, &[2], 1000, 400, 0),
+        ]);
+        assert!(parents_first(&block));
+        assert_eq!(block_txids(&block), vec![txid(1), txid(2)]);
+    }
+}
@@ -0,0 +1,233 @@
+use super::compact_target::CompactTarget;
+use crate::validation::finality::{load_utxo_confirmations, UtxoConfirmations};
+use crate::validation::utils::{encode_script_num, serialize_push};
+use crate::validation::validate_block::get_block_subsidy;
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+// Parameters of the mined block that are not derived from the mempool. Defaults to the
+// values of the exercise, can be loaded from a toml or json file and overwritten with
+// command line flags:
+// version = 0x20000000
+// previous_block_hash = "00000000000000000001901b9f3b6c7a0c34b20b29b950d0d8ffa36c63979c1c"
+// bits = 0x1f00ffff
+// height = 839653
+// payout_scriptpubkey = "001435f6de260c9f3bdee47524c473a6016c0c055cb9"
+// coinbase_message = "CypherpunkFuture"
+// selection = "ancestor_score"
+// median_time_past and utxo_confirmations are optional
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct BlockTemplateConfig {
+    pub version: i32,
+    pub previous_block_hash: String, // hex in display (rpc) byte order
+    pub bits: u32,
+    pub height: u64,
+    pub payout_scriptpubkey: String, // hex
+    pub coinbase_message: String,    // pushed after the height in the coinbase scriptsig
+    pub selection: SelectionStrategy,
+    pub median_time_past: Option<u32>, // of the previous block, time locks non-final if not set
+    pub utxo_confirmations: Option<String>, // json file with the confirmations of spent outputs
+}
+
+// How the block transactions are selected from the mempool
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SelectionStrategy {
+    // BlockAssembler, ancestor feerate packages like addPackageTxs of bitcoin core
+    #[default]
+    AncestorScore,
+    // calculate_packet_weights + sort_transactions + cut_size
+    PacketSort,
+    // chunks of the linearized mempool clusters merged by feerate
+    ClusterLinearization,
+}
+
+impl SelectionStrategy {
+    // returns: SelectionStrategy of the name used in the config or Err(reason)
+    fn from_name(name: &str) -> Result<SelectionStrategy, String> {
+        match name {
+            "ancestor_score" => Ok(SelectionStrategy::AncestorScore),
+            "packet_sort" => Ok(SelectionStrategy::PacketSort),
+            "cluster_linearization" => Ok(SelectionStrategy::ClusterLinearization),
+            _ => Err(format!(
+                "--selection {}: has to be ancestor_score, packet_sort or cluster_linearization",
+                name
+            )),
+        }
+    }
+}
+
+impl Default for BlockTemplateConfig {
+    fn default() -> Self {
+        BlockTemplateConfig {
+            version: 0x20000000, // not signaling updates
+            previous_block_hash: "00000000000000000001901b9f3b6c7a0c34b20b29b950d0d8ffa36c63979c1c"
+                .to_string(),
+            bits: 0x1f00ffff,
+            height: 839653,
+            payout_scriptpubkey: "001435f6de260c9f3bdee47524c473a6016c0c055cb9".to_string(),
+            coinbase_message: "CypherpunkFuture".to_string(),
+            selection: SelectionStrategy::AncestorScore,
+            median_time_past: None,
+            utxo_confirmations: None,
+        }
+    }
+}
+
+// parses a command line number as decimal or, with 0x prefix, as hex
+// returns: u64 or Err(reason)
+fn parse_number(flag: &str, value: &str) -> Result<u64, String> {
+    let parsed = match value.strip_prefix("0x") {
+        Some(hex_value) => u64::from_str_radix(hex_value, 16),
+        None => value.parse::<u64>(),
+    };
+    parsed.map_err(|err| format!("{} {}: {}", flag, value, err))
+}
+
+// parses a command line number like parse_number and checks that it fits in 32 bits
+// returns: u32 or Err(reason)
+fn parse_u32(flag: &str, value: &str) -> Result<u32, String> {
+    u32::try_from(parse_number(flag, value)?)
+        .map_err(|_| format!("{} {}: has to fit in 32 bits", flag, value))
+}
+
+impl BlockTemplateConfig {
+    // loads the config from a .toml or .json file, missing fields keep their default
+    // returns: BlockTemplateConfig or Err(reason)
+    pub fn from_file(path: &str) -> Result<BlockTemplateConfig, String> {
+        let content =
+            fs::read_to_string(path).map_err(|err| format!("Reading {} failed: {}", path, err))?;
+        let config: BlockTemplateConfig = match Path::new(path).extension() {
+            Some(extension) if extension == "toml" => {
+                toml::from_str(&content).map_err(|err| format!("{}: {}", path, err))?
+            }
+            Some(extension) if extension == "json" => {
+                serde_json::from_str(&content).map_err(|err| format!("{}: {}", path, err))?
+            }
+            _ => return Err(format!("{}: config has to be a .toml or .json file", path)),
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    // builds the config from the command line flags: --block-config <file> is loaded first,
+    // then --block-version, --prev-block, --bits, --height, --payout-script, --coinbase-message,
+    // --selection, --median-time-past and --utxo-confirmations overwrite single values
+    // (numbers as decimal or 0x hex)
+    // returns: BlockTemplateConfig or Err(reason)
+    pub fn from_args(args: &[String]) -> Result<BlockTemplateConfig, String> {
+        let flag_value = |flag: &str| -> Result<Option<&String>, String> {
+            match args.iter().position(|arg| arg == flag) {
+                Some(index) => match args.get(index + 1) {
+                    Some(value) => Ok(Some(value)),
+                    None => Err(format!("{} needs a value", flag)),
+                },
+                None => Ok(None),
+            }
+        };
+        let mut config = match flag_value("--block-config")? {
+            Some(path) => BlockTemplateConfig::from_file(path)?,
+            None => BlockTemplateConfig::default(),
+        };
+
+        if let Some(value) = flag_value("--block-version")? {
+            config.version = parse_u32("--block-version", value)? as i32;
+        }
+        if let Some(value) = flag_value("--prev-block")? {
+            config.previous_block_hash = value.clone();
+        }
+        if let Some(value) = flag_value("--bits")? {
+            config.bits = parse_u32("--bits", value)?;
+        }
+        if let Some(value) = flag_value("--height")? {
+            config.height = parse_number("--height", value)?;
+        }
+        if let Some(value) = flag_value("--payout-script")? {
+            config.payout_scriptpubkey = value.clone();
+        }
+        if let Some(value) = flag_value("--coinbase-message")? {
+            config.coinbase_message = value.clone();
+        }
+        if let Some(value) = flag_value("--selection")? {
+            config.selection = SelectionStrategy::from_name(value)?;
+        }
+        if let Some(value) = flag_value("--median-time-past")? {
+            config.median_time_past = Some(parse_u32("--median-time-past", value)?);
+        }
+        if let Some(value) = flag_value("--utxo-confirmations")? {
+            config.utxo_confirmations = Some(value.clone());
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    // checks the hex fields, the target bits and the coinbase scriptsig size limit (2 - 100 bytes)
+    // returns: Ok(()) or Err(reason)
+    pub fn validate(&self) -> Result<(), String> {
+        match hex::decode(&self.previous_block_hash) {
+            Ok(hash) if hash.len() == 32 => {}
+            _ => return Err("previous_block_hash has to be 32 bytes hex".to_string()),
+        }
+        if CompactTarget(self.bits).to_target().is_none() {
+            return Err(format!(
+                "bits {:#010x} encode a negative, zero or overflowing target",
+                self.bits
+            ));
+        }
+        if hex::decode(&self.payout_scriptpubkey).is_err() {
+            return Err("payout_scriptpubkey has to be hex".to_string());
+        }
+        let scriptsig_len = self.coinbase_scriptsig(0).len();
+        if !(2..=100).contains(&scriptsig_len) {
+            return Err(format!(
+                "Coinbase scriptsig has {} bytes (height + extranonce + coinbase_message), allowed are 2 - 100",
+                scriptsig_len
+            ));
+        }
+        Ok(())
+    }
+
+    // loads the confirmations of the spent outputs from the configured file
+    // returns: UtxoConfirmations (empty if no file is configured) or Err(reason)
+    pub fn load_utxo_confirmations(&self) -> Result<UtxoConfirmations, String> {
+        match &self.utxo_confirmations {
+            Some(path) => load_utxo_confirmations(path),
+            None => Ok(UtxoConfirmations::new()),
+        }
+    }
+
+    // returns: block subsidy of the configured height in satoshi (halving every 210000 blocks)
+    pub fn subsidy(&self) -> u64 {
+        get_block_subsidy(self.height)
+    }
+
+    // returns: previous block hash in natural byte order as used in the header
+    pub fn previous_block_hash_bytes(&self) -> Vec<u8> {
+        let mut hash = hex::decode(&self.previous_block_hash).expect("validated hex");
+        hash.reverse();
+        hash
+    }
+
+    // returns: payout scriptpubkey bytes
+    pub fn payout_scriptpubkey_bytes(&self) -> Vec<u8> {
+        hex::decode(&self.payout_scriptpubkey).expect("validated hex")
+    }
+
+    // returns: coinbase scriptsig, the height push (BIP34, OP_0 - OP_16 for heights up to 16)
+    // followed by the 4 byte extranonce push (fixed size, rolling it keeps the tx weight)
+    // and the message push
+    pub fn coinbase_scriptsig(&self, extranonce: u32) -> Vec<u8> {
+        let mut scriptsig = match self.height {
+            0 => vec![0x00],
+            1..=16 => vec![0x50 + self.height as u8],
+            _ => serialize_push(&encode_script_num(self.height as i64)),
+        };
+        scriptsig.extend(serialize_push(&extranonce.to_le_bytes()));
+        if !self.coinbase_message.is_empty() {
+            scriptsig.extend(serialize_push(self.coinbase_message.as_bytes()));
+        }
+        scriptsig
+    }
+}
@@ -0,0 +1,36 @@
+use super::block_template::BlockTemplateConfig;
+use super::construct_coinbase::get_coinbase_weight;
+use crate::validation::utils::varint;
+use crate::validation::validate_block::MAX_BLOCK_WEIGHT;
+
+// 80 byte block header, no witness discount
+const HEADER_WEIGHT: u64 = 80 * 4;
+
+// Weight of the block besides the selected transactions: header, transaction count varint and
+// the coinbase transaction assembled from the config. The coinbase weight doesn't depend on the
+// selected transactions (fixed size reward amount, witness commitment and extranonce push).
+#[derive(Clone, Copy, Debug)]
+pub struct BlockWeightBudget {
+    coinbase_weight: u64,
+}
+
+impl BlockWeightBudget {
+    // returns: BlockWeightBudget with the weight of the coinbase of the config
+    pub fn new(config: &BlockTemplateConfig) -> BlockWeightBudget {
+        BlockWeightBudget {
+            coinbase_weight: get_coinbase_weight(config),
+        }
+    }
+
+    // returns: weight of the whole block with the passed transactions (summed up weight and
+    // count without the coinbase)
+    pub fn block_weight(&self, transactions_weight: u64, transaction_count: usize) -> u64 {
+        let count_varint_len = varint(transaction_count as u128 + 1).len() as u64;
+        HEADER_WEIGHT + count_varint_len * 4 + self.coinbase_weight + transactions_weight
+    }
+
+    // returns: true if the block with the passed transactions respects the block weight limit
+    pub fn fits(&self, transactions_weight: u64, transaction_count: usize) -> bool {
+        self.block_weight(transactions_weight, transaction_count) <= MAX_BLOCK_WEIGHT
+    }
+}
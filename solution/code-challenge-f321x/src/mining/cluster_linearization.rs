@@ -0,0 +1,503 @@
+use super::block_assembler::RESERVED_SIGOPS_COST;
+use super::block_weight::BlockWeightBudget;
+use super::packet_weight::MempoolRelatives;
+use crate::parsing::transaction_structs::Transaction;
+use crate::validation::sigops::MAX_BLOCK_SIGOPS_COST;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+// maximum amount of search steps for the highest feerate subset of a cluster, if it is
+// reached the best subset found so far is used (at least as good as the best ancestor set)
+const MAX_SEARCH_ITERATIONS: u64 = 10_000;
+
+// summed up fee, weight and sigop-adjusted vsize of a set of transactions. Feerates are
+// compared by the sigop-adjusted vsize like in core, the weight is used for the block limit.
+#[derive(Clone, Copy, Default)]
+struct FeeWeight {
+    fee: u64,
+    weight: u64,
+    size: u64,
+}
+
+impl FeeWeight {
+    fn add(self, other: FeeWeight) -> FeeWeight {
+        FeeWeight {
+            fee: self.fee + other.fee,
+            weight: self.weight + other.weight,
+            size: self.size + other.size,
+        }
+    }
+
+    // returns: ordering of the feerates (fee / size), compared by cross multiplication
+    fn cmp_feerate(&self, other: &FeeWeight) -> Ordering {
+        (self.fee as u128 * other.size as u128).cmp(&(other.fee as u128 * self.size as u128))
+    }
+}
+
+// set of transactions of a cluster as bitset of the cluster indices
+#[derive(Clone)]
+struct TxSet(Vec<u64>);
+
+impl TxSet {
+    fn new(size: usize) -> TxSet {
+        TxSet(vec![0; size.div_ceil(64)])
+    }
+
+    fn insert(&mut self, index: usize) {
+        self.0[index / 64] |= 1 << (index % 64);
+    }
+
+    fn union(&self, other: &TxSet) -> TxSet {
+        TxSet(self.0.iter().zip(&other.0).map(|(a, b)| a | b).collect())
+    }
+
+    fn intersection(&self, other: &TxSet) -> TxSet {
+        TxSet(self.0.iter().zip(&other.0).map(|(a, b)| a & b).collect())
+    }
+
+    fn difference(&self, other: &TxSet) -> TxSet {
+        TxSet(self.0.iter().zip(&other.0).map(|(a, b)| a & !b).collect())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.iter().all(|word| *word == 0)
+    }
+
+    fn count(&self) -> u32 {
+        self.0.iter().map(|word| word.count_ones()).sum()
+    }
+
+    // returns: iterator over the contained indices in ascending order
+    fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.0.iter().enumerate().flat_map(|(word_index, word)| {
+            (0..64)
+                .filter(move |bit| word & (1 << bit) != 0)
+                .map(move |bit| word_index * 64 + bit)
+        })
+    }
+}
+
+// connected component of the mempool graph. Transactions are addressed by their index in
+// members, ancestor and descendant sets include the transaction itself.
+struct Cluster {
+    members: Vec<usize>, // indices of the indexed mempool transactions
+    fee_weights: Vec<FeeWeight>,
+    ancestors: Vec<TxSet>,
+    descendants: Vec<TxSet>,
+}
+
+impl Cluster {
+    fn new(
+        members: Vec<usize>,
+        transactions: &[&Transaction],
+        relatives: &MempoolRelatives,
+    ) -> Cluster {
+        let positions: HashMap<usize, usize> = members
+            .iter()
+            .enumerate()
+            .map(|(position, &member)| (member, position))
+            .collect();
+        let to_set = |position: usize, related: &[usize]| {
+            let mut set = TxSet::new(members.len());
+            set.insert(position);
+            for related in related {
+                set.insert(positions[related]);
+            }
+            set
+        };
+
+        Cluster {
+            fee_weights: members
+                .iter()
+                .map(|&member| FeeWeight {
+                    fee: transactions[member].meta.fee,
+                    weight: transactions[member].meta.weight,
+                    size: transactions[member].sigop_adjusted_vsize(),
+                })
+                .collect(),
+            ancestors: (0..members.len())
+                .map(|position| to_set(position, relatives.ancestor_indices(members[position])))
+                .collect(),
+            descendants: (0..members.len())
+                .map(|position| to_set(position, relatives.descendant_indices(members[position])))
+                .collect(),
+            members,
+        }
+    }
+
+    // returns: summed up fee, weight and size of the set
+    fn fee_weight(&self, set: &TxSet) -> FeeWeight {
+        set.iter().fold(FeeWeight::default(), |sum, position| {
+            sum.add(self.fee_weights[position])
+        })
+    }
+
+    // upper bound for the feerate of included extended by any subset of undecided: adds the
+    // undecided transactions by their own feerate as long as they increase the feerate
+    // returns: FeeWeight of the bound
+    fn feerate_bound(&self, included: &TxSet, undecided: &TxSet) -> FeeWeight {
+        let mut candidates: Vec<FeeWeight> = undecided
+            .iter()
+            .map(|position| self.fee_weights[position])
+            .collect();
+        candidates.sort_by(|a, b| b.cmp_feerate(a));
+
+        let mut bound = self.fee_weight(included);
+        for candidate in candidates {
+            if bound.size > 0 && candidate.cmp_feerate(&bound) != Ordering::Greater {
+                break;
+            }
+            bound = bound.add(candidate);
+        }
+        bound
+    }
+
+    // searches the topologically closed subset (containing all ancestors of its transactions)
+    // of remaining with the highest feerate. Starts with the best ancestor set and branches on
+    // including (with ancestors) or excluding (with descendants) the undecided transaction of
+    // the highest own feerate, pruning branches whose feerate bound can't beat the best subset.
+    // returns: best subset found within MAX_SEARCH_ITERATIONS
+    fn find_best_subset(&self, remaining: &TxSet) -> TxSet {
+        let mut best: TxSet = remaining
+            .iter()
+            .map(|position| self.ancestors[position].intersection(remaining))
+            .max_by(|a, b| self.fee_weight(a).cmp_feerate(&self.fee_weight(b)))
+            .expect("find_best_subset called with remaining transactions");
+        let mut best_fee_weight = self.fee_weight(&best);
+
+        let mut stack: Vec<(TxSet, TxSet)> =
+            vec![(TxSet::new(self.members.len()), remaining.clone())];
+        let mut iterations: u64 = 0;
+        while let Some((included, undecided)) = stack.pop() {
+            if iterations >= MAX_SEARCH_ITERATIONS {
+                break;
+            }
+            iterations += 1;
+            if undecided.is_empty()
+                || self
+                    .feerate_bound(&included, &undecided)
+                    .cmp_feerate(&best_fee_weight)
+                    != Ordering::Greater
+            {
+                continue;
+            }
+
+            let split = undecided
+                .iter()
+                .max_by(|&a, &b| {
+                    self.fee_weights[a]
+                        .cmp_feerate(&self.fee_weights[b])
+                        .then_with(|| b.cmp(&a))
+                })
+                .expect("undecided is not empty");
+            let with_split = included.union(&self.ancestors[split].intersection(remaining));
+            let with_split_fee_weight = self.fee_weight(&with_split);
+            if with_split_fee_weight.cmp_feerate(&best_fee_weight) == Ordering::Greater {
+                best = with_split.clone();
+                best_fee_weight = with_split_fee_weight;
+            }
+
+            stack.push((included, undecided.difference(&self.descendants[split])));
+            let undecided_with_split = undecided.difference(&with_split);
+            stack.push((with_split, undecided_with_split));
+        }
+        best
+    }
+
+    // linearizes the cluster by repeatedly moving its best subset to the end of the order,
+    // each subset sorted by ancestor count so parents come first
+    // returns: cluster positions in linearization order
+    fn linearize(&self) -> Vec<usize> {
+        let mut remaining = TxSet::new(self.members.len());
+        for position in 0..self.members.len() {
+            remaining.insert(position);
+        }
+
+        let mut linearization: Vec<usize> = Vec::new();
+        while !remaining.is_empty() {
+            let best = self.find_best_subset(&remaining);
+            let mut subset: Vec<usize> = best.iter().collect();
+            subset.sort_by_key(|&position| (self.ancestors[position].count(), position));
+            linearization.extend(subset);
+            remaining = remaining.difference(&best);
+        }
+        linearization
+    }
+
+    // splits the linearization into chunks of decreasing feerate: a transaction is merged
+    // into the previous chunk as long as it raises the chunk's feerate above the previous one
+    // returns: chunks as (cluster positions, FeeWeight)
+    fn chunk(&self, linearization: &[usize]) -> Vec<(Vec<usize>, FeeWeight)> {
+        let mut chunks: Vec<(Vec<usize>, FeeWeight)> = Vec::new();
+
+        for &position in linearization {
+            chunks.push((vec![position], self.fee_weights[position]));
+            while chunks.len() > 1
+                && chunks[chunks.len() - 1]
+                    .1
+                    .cmp_feerate(&chunks[chunks.len() - 2].1)
+                    == Ordering::Greater
+            {
+                let (positions, fee_weight) = chunks.pop().expect("more than one chunk");
+                let previous = chunks.last_mut().expect("more than one chunk");
+                previous.0.extend(positions);
+                previous.1 = previous.1.add(fee_weight);
+            }
+        }
+        chunks
+    }
+}
+
+// partitions the transaction graph into connected components
+// returns: clusters as sorted transaction indices
+fn find_clusters(relatives: &MempoolRelatives, transaction_count: usize) -> Vec<Vec<usize>> {
+    let mut visited = vec![false; transaction_count];
+    let mut clusters: Vec<Vec<usize>> = Vec::new();
+
+    for start in 0..transaction_count {
+        if visited[start] {
+            continue;
+        }
+        visited[start] = true;
+        let mut cluster: Vec<usize> = Vec::new();
+        let mut stack: Vec<usize> = vec![start];
+        while let Some(index) = stack.pop() {
+            cluster.push(index);
+            for &related in relatives
+                .parent_indices(index)
+                .iter()
+                .chain(relatives.child_indices(index))
+            {
+                if !visited[related] {
+                    visited[related] = true;
+                    stack.push(related);
+                }
+            }
+        }
+        cluster.sort_unstable();
+        clusters.push(cluster);
+    }
+    clusters
+}
+
+// a chunk of a linearized cluster, merged with the chunks of all clusters by feerate
+struct Chunk {
+    cluster: usize,
+    position: usize, // position of the chunk in the chunking of its cluster
+    transactions: Vec<usize>,
+    fee_weight: FeeWeight,
+}
+
+// Selects the block transactions by cluster linearization: the mempool graph (parents set by
+// assign_mempool_parents) is split into connected clusters, each cluster is linearized by
+// repeatedly taking its highest feerate subset and the resulting chunks of all clusters are
+// merged by feerate. The chunks are added in this order as long as they fit the weight and
+// sigop budget, after a chunk didn't fit the remaining chunks of its cluster are skipped.
+// returns: selected transactions in block order (parents before children)
+pub fn select_by_cluster_linearization(
+    txid_tx_map: &HashMap<String, Transaction>,
+    budget: BlockWeightBudget,
+) -> Vec<Transaction> {
+    let relatives = MempoolRelatives::new(txid_tx_map);
+    let transactions = relatives.indexed_transactions(txid_tx_map);
+    let clusters = find_clusters(&relatives, transactions.len());
+    let cluster_count = clusters.len();
+
+    let mut chunks: Vec<Chunk> = Vec::new();
+    for (cluster_index, members) in clusters.into_iter().enumerate() {
+        let cluster = Cluster::new(members, &transactions, &relatives);
+        let linearization = cluster.linearize();
+        for (position, (positions, fee_weight)) in
+            cluster.chunk(&linearization).into_iter().enumerate()
+        {
+            chunks.push(Chunk {
+                cluster: cluster_index,
+                position,
+                transactions: positions
+                    .into_iter()
+                    .map(|position| cluster.members[position])
+                    .collect(),
+                fee_weight,
+            });
+        }
+    }
+    // chunk feerates of a cluster are decreasing, equal feerates keep the cluster order
+    chunks.sort_by(|a, b| {
+        b.fee_weight
+            .cmp_feerate(&a.fee_weight)
+            .then_with(|| (a.cluster, a.position).cmp(&(b.cluster, b.position)))
+    });
+
+    let mut block: Vec<Transaction> = Vec::new();
+    let mut transactions_weight: u64 = 0;
+    let mut block_sigops_cost: u64 = RESERVED_SIGOPS_COST;
+    let mut skipped_clusters: Vec<bool> = vec![false; cluster_count];
+    for chunk in chunks {
+        if skipped_clusters[chunk.cluster] {
+            continue;
+        }
+        let sigops_cost: u64 = chunk
+            .transactions
+            .iter()
+            .map(|&index| transactions[index].meta.sigops_cost)
+            .sum();
+        if !budget.fits(
+            transactions_weight + chunk.fee_weight.weight,
+            block.len() + chunk.transactions.len(),
+        ) || block_sigops_cost + sigops_cost >= MAX_BLOCK_SIGOPS_COST
+        {
+            skipped_clusters[chunk.cluster] = true;
+            continue;
+        }
+        transactions_weight += chunk.fee_weight.weight;
+        block_sigops_cost += sigops_cost;
+        block.extend(
+            chunk
+                .transactions
+                .iter()
+                .map(|&index| transactions[index].clone()),
+        );
+    }
+    block
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mining::{block_assembler::BlockAssembler, BlockTemplateConfig};
+    use crate::test_utils::{block_txids, mempool, package_tx as tx, parents_first, txid};
+    use crate::validation::validate_block::MAX_BLOCK_WEIGHT;
+
+    const WEIGHT: u64 = 400; // 100 vbyte
+
+    fn budget() -> BlockWeightBudget {
+        BlockWeightBudget::new(&BlockTemplateConfig::default())
+    }
+
+    // returns: block transactions selected by cluster linearization
+    fn select(transactions: Vec<Transaction>) -> Vec<Transaction> {
+        select_by_cluster_linearization(&mempool(transactions), budget())
+    }
+
+    // returns: summed up fee of the block transactions
+    fn fees(block: &[Transaction]) -> u64 {
+        block.iter().map(|tx| tx.meta.fee).sum()
+    }
+
+    #[test]
+    fn child_pays_for_parent() {
+        let block = select(vec![
+            tx(1, &[], 100, WEIGHT, 0),
+            tx(2, &[1], 10_000, WEIGHT, 0),
+            tx(3, &[], 2000, WEIGHT, 0),
+        ]);
+        assert!(parents_first(&block));
+        assert_eq!(block_txids(&block), vec![txid(1), txid(2), txid(3)]);
+    }
+
+    #[test]
+    fn diamond() {
+        // 4 spends 2 and 3, both spend 1. The whole diamond has 35.25 sat/vbyte, above 5
+        let block = select(vec![
+            tx(1, &[], 100, WEIGHT, 0),
+            tx(2, &[1], 1000, WEIGHT, 0),
+            tx(3, &[1], 3000, WEIGHT, 0),
+            tx(4, &[2, 3], 10_000, WEIGHT, 0),
+            tx(5, &[], 3000, WEIGHT, 0),
+        ]);
+        assert!(parents_first(&block));
+        assert_eq!(
+            block_txids(&block),
+            vec![txid(1), txid(2), txid(3), txid(4), txid(5)]
+        );
+
+        // without the fee of 4 the diamond is split into chunks: 1 and 3 with 15.5 sat/vbyte,
+        // 2 with 10 sat/vbyte and 4 with 1 sat/vbyte, 5 with 7 sat/vbyte goes in between
+        let block = select(vec![
+            tx(1, &[], 100, WEIGHT, 0),
+            tx(2, &[1], 1000, WEIGHT, 0),
+            tx(3, &[1], 3000, WEIGHT, 0),
+            tx(4, &[2, 3], 100, WEIGHT, 0),
+            tx(5, &[], 700, WEIGHT, 0),
+        ]);
+        assert!(parents_first(&block));
+        assert_eq!(
+            block_txids(&block),
+            vec![txid(1), txid(3), txid(2), txid(5), txid(4)]
+        );
+    }
+
+    #[test]
+    fn sigop_heavy_transactions() {
+        // chunk feerates use the sigop-adjusted vsize: 400 sigop cost count as 2000 vbyte
+        let block = select(vec![
+            tx(1, &[], 5000, WEIGHT, 400),
+            tx(2, &[], 1000, WEIGHT, 0),
+        ]);
+        assert_eq!(block_txids(&block), vec![txid(2), txid(1)]);
+
+        // 2 would reach the sigop limit with the cost reserved for the coinbase
+        let block = select(vec![
+            tx(1, &[], 1_000_000, WEIGHT, 40_000),
+            tx(2, &[], 900_000, WEIGHT, 39_600),
+            tx(3, &[], 800_000, WEIGHT, 39_599),
+        ]);
+        assert_eq!(block_txids(&block), vec![txid(1), txid(3)]);
+        let sigops_cost: u64 = block.iter().map(|tx| tx.meta.sigops_cost).sum();
+        assert!(sigops_cost + RESERVED_SIGOPS_COST < MAX_BLOCK_SIGOPS_COST);
+    }
+
+    #[test]
+    fn almost_full_block() {
+        // 1 leaves 1000 weight units free in a block of three transactions, 2 doesn't fit
+        // anymore but the lower feerate 3 fills the block
+        let filler_weight = MAX_BLOCK_WEIGHT - budget().block_weight(0, 3) - 1000;
+        let block = select(vec![
+            tx(1, &[], filler_weight, filler_weight, 0),
+            tx(2, &[], 1500, 2000, 0),
+            tx(3, &[], 500, 1000, 0),
+        ]);
+        assert_eq!(block_txids(&block), vec![txid(1), txid(3)]);
+        let weight: u64 = block.iter().map(|tx| tx.meta.weight).sum();
+        assert_eq!(budget().block_weight(weight, block.len()), MAX_BLOCK_WEIGHT);
+
+        // after the chunk of 2 and 3 didn't fit, the later chunk of 4 is skipped as well
+        let block = select(vec![
+            tx(1, &[], filler_weight, filler_weight, 0),
+            tx(2, &[], 100, 800, 0),
+            tx(3, &[2], 1000, 400, 0),
+            tx(4, &[3], 10, 400, 0),
+        ]);
+        assert_eq!(block_txids(&block), vec![txid(1)]);
+    }
+
+    #[test]
+    fn beats_ancestor_score() {
+        // 2 and 3 both spend 1 (no fee). The best ancestor set 1 + 2 has 5 sat/vbyte and loses
+        // against 4 with 6 sat/vbyte, the whole cluster has 6.67 sat/vbyte. After the filler
+        // 5 with 40 sat/vbyte there is space for three transactions.
+        let filler_weight = MAX_BLOCK_WEIGHT - budget().block_weight(0, 4) - 3 * WEIGHT;
+        let transactions = vec![
+            tx(1, &[], 0, WEIGHT, 0),
+            tx(2, &[1], 1000, WEIGHT, 0),
+            tx(3, &[1], 1000, WEIGHT, 0),
+            tx(4, &[], 600, WEIGHT, 0),
+            tx(5, &[], filler_weight * 10, filler_weight, 0),
+        ];
+        let txid_tx_map = mempool(transactions);
+
+        let by_ancestor_score = BlockAssembler::new(&txid_tx_map, budget()).add_package_txs();
+        assert_eq!(
+            block_txids(&by_ancestor_score),
+            vec![txid(5), txid(4), txid(1), txid(2)]
+        );
+
+        let by_cluster = select_by_cluster_linearization(&txid_tx_map, budget());
+        assert!(parents_first(&by_cluster));
+        assert_eq!(
+            block_txids(&by_cluster),
+            vec![txid(5), txid(1), txid(2), txid(3)]
+        );
+        assert_eq!(fees(&by_cluster), fees(&by_ancestor_score) + 400);
+    }
+}
@@ -0,0 +1,208 @@
+use num_bigint::BigUint;
+use num_traits::{ToPrimitive, Zero};
+
+// The proof of work target in the compact "nBits" representation of the block header:
+// 1 byte exponent (size of the target in bytes) and 3 byte mantissa, with the highest
+// mantissa bit used as sign bit. Conversion is done like in bitcoin core (arith_uint256).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompactTarget(pub u32);
+
+// bits of the difficulty 1 target, used as reference for the difficulty
+const DIFFICULTY_1_BITS: u32 = 0x1d00ffff;
+
+impl CompactTarget {
+    // decodes the bits like SetCompact of bitcoin core
+    // returns: (target, negative, overflow), negative and overflow encodings are invalid targets
+    pub fn decode(self) -> (BigUint, bool, bool) {
+        let size = self.0 >> 24;
+        let mut word = self.0 & 0x007fffff;
+        let target = if size <= 3 {
+            word >>= 8 * (3 - size);
+            BigUint::from(word)
+        } else {
+            BigUint::from(word) << (8 * (size - 3))
+        };
+        let negative = word != 0 && (self.0 & 0x00800000) != 0;
+        let overflow =
+            word != 0 && (size > 34 || (word > 0xff && size > 33) || (word > 0xffff && size > 32));
+        (target, negative, overflow)
+    }
+
+    // returns: target or None if the bits encode a negative, zero or overflowing target
+    pub fn to_target(self) -> Option<BigUint> {
+        let (target, negative, overflow) = self.decode();
+        if negative || overflow || target.is_zero() {
+            return None;
+        }
+        Some(target)
+    }
+
+    // returns: target as 32 byte little endian number (like a header hash in natural byte
+    // order) or None for invalid bits
+    pub fn to_target_bytes(self) -> Option<[u8; 32]> {
+        let target = self.to_target()?;
+        let mut target_bytes = [0u8; 32];
+        let le_bytes = target.to_bytes_le();
+        target_bytes[..le_bytes.len()].copy_from_slice(&le_bytes);
+        Some(target_bytes)
+    }
+
+    // encodes the target in compact form like GetCompact of bitcoin core (loses precision
+    // below the 3 most significant bytes)
+    // returns: CompactTarget
+    pub fn from_target(target: &BigUint) -> CompactTarget {
+        let mut size = (target.bits() as u32).div_ceil(8);
+        let mut compact: u32 = if size <= 3 {
+            target.to_u32().expect("target fits in 3 bytes") << (8 * (3 - size))
+        } else {
+            (target >> (8 * (size - 3)))
+                .to_u32()
+                .expect("3 most significant bytes fit in u32")
+        };
+        // the highest mantissa bit is the sign bit, use one more byte to keep it positive
+        if compact & 0x00800000 != 0 {
+            compact >>= 8;
+            size += 1;
+        }
+        CompactTarget(compact | (size << 24))
+    }
+
+    // returns: true if the HASH256 of a header (little endian number) is below or
+    // equal the target, false for invalid bits
+    pub fn is_met_by(self, hash: &[u8]) -> bool {
+        match self.to_target() {
+            Some(target) => BigUint::from_bytes_le(hash) <= target,
+            None => false,
+        }
+    }
+
+    // calculates the difficulty (difficulty 1 target / target) like GetDifficulty of core
+    // returns: difficulty as f64 or None for a zero mantissa (division by zero)
+    pub fn difficulty(self) -> Option<f64> {
+        let mantissa = self.0 & 0x00ffffff;
+        if mantissa == 0 {
+            return None;
+        }
+        let mut shift = (self.0 >> 24) & 0xff;
+        let mut difficulty = (DIFFICULTY_1_BITS & 0x00ffffff) as f64 / mantissa as f64;
+
+        while shift < 29 {
+            difficulty *= 256.0;
+            shift += 1;
+        }
+        while shift > 29 {
+            difficulty /= 256.0;
+            shift -= 1;
+        }
+        Some(difficulty)
+    }
+
+    // calculates the expected number of hashes to find a block: 2^256 / (target + 1)
+    // returns: work as BigUint, zero for invalid bits
+    pub fn work(self) -> BigUint {
+        match self.to_target() {
+            Some(target) => (BigUint::from(1u32) << 256) / (target + 1u32),
+            None => BigUint::zero(),
+        }
+    }
+}
+
+// returns: chainwork, the summed up work of the blocks with the passed bits
+pub fn chainwork(block_bits: &[CompactTarget]) -> BigUint {
+    block_bits.iter().map(|bits| bits.work()).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // returns: the target of SetCompact as u128 (the test targets fit)
+    fn decode(bits: u32) -> (u128, bool, bool) {
+        let (target, negative, overflow) = CompactTarget(bits).decode();
+        (target.to_u128().unwrap(), negative, overflow)
+    }
+
+    // vectors of the SetCompact/GetCompact test of bitcoin core's arith_uint256_tests
+    #[test]
+    fn core_compact_vectors() {
+        for bits in [
+            0, 0x00123456, 0x01003456, 0x02000056, 0x03000000, 0x04000000, 0x00923456, 0x01803456,
+            0x02800056, 0x03800000, 0x04800000,
+        ] {
+            assert_eq!(decode(bits), (0, false, false), "{:08x}", bits);
+            assert_eq!(CompactTarget(bits).to_target(), None);
+        }
+        for (bits, target, compact) in [
+            (0x01123456, 0x12, 0x01120000),
+            (0x02123456, 0x1234, 0x02123400),
+            (0x03123456, 0x123456, 0x03123456),
+            (0x04123456, 0x12345600, 0x04123456),
+            (0x05009234, 0x92340000, 0x05009234),
+        ] {
+            assert_eq!(decode(bits), (target, false, false), "{:08x}", bits);
+            let target = CompactTarget(bits).to_target().unwrap();
+            assert_eq!(CompactTarget::from_target(&target), CompactTarget(compact));
+        }
+        let target = CompactTarget(0x20123456).to_target().unwrap();
+        assert_eq!(target, BigUint::from(0x123456u32) << (8 * 29));
+        assert_eq!(
+            CompactTarget::from_target(&target),
+            CompactTarget(0x20123456)
+        );
+        // the sign bit is moved to the next byte, as with 0x05009234
+        assert_eq!(
+            CompactTarget::from_target(&BigUint::from(0x80u32)),
+            CompactTarget(0x02008000)
+        );
+    }
+
+    #[test]
+    fn negative_encodings() {
+        assert_eq!(decode(0x01fedcba), (0x7e, true, false));
+        assert_eq!(decode(0x04923456), (0x12345600, true, false));
+        assert_eq!(CompactTarget(0x04923456).to_target(), None);
+        assert!(!CompactTarget(0x04923456).is_met_by(&[0u8; 32]));
+        // the sign bit of a zero mantissa is ignored, but the target stays invalid
+        assert_eq!(decode(0x04800000), (0, false, false));
+    }
+
+    #[test]
+    fn overflow_encodings() {
+        for bits in [0xff123456, 0x23000001, 0x22000100, 0x21010000] {
+            let (_, negative, overflow) = CompactTarget(bits).decode();
+            assert!(overflow && !negative, "{:08x}", bits);
+            assert_eq!(CompactTarget(bits).to_target(), None);
+            assert_eq!(CompactTarget(bits).work(), BigUint::zero());
+        }
+        // the largest encodings of each size without overflow
+        for bits in [0x22000001, 0x210000ff, 0x2000ffff] {
+            assert!(!CompactTarget(bits).decode().2, "{:08x}", bits);
+        }
+    }
+
+    #[test]
+    fn zero_encodings() {
+        for bits in [0, 0x1d000000, 0x1d800000, 0x01003456] {
+            assert_eq!(CompactTarget(bits).to_target(), None);
+            assert_eq!(CompactTarget(bits).to_target_bytes(), None);
+            assert!(!CompactTarget(bits).is_met_by(&[0u8; 32]));
+        }
+        assert_eq!(CompactTarget(0x1d000000).difficulty(), None);
+        assert_eq!(CompactTarget(0).difficulty(), None);
+    }
+
+    #[test]
+    fn difficulty() {
+        // expected values of bitcoin core's blockchain_tests (6 decimals)
+        for (bits, expected) in [
+            (0x1d00ffff, 1.0),
+            (0x1f111111, 0.000001),
+            (0x1ef88f6f, 0.000016),
+            (0x1df88f6f, 0.004023),
+            (0x1cf88f6f, 1.029916),
+        ] {
+            let difficulty = CompactTarget(bits).difficulty().unwrap();
+            assert!((difficulty - expected).abs() < 0.000001, "{:08x}", bits);
+        }
+    }
+}
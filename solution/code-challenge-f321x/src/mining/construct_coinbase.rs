@@ -1,3 +1,4 @@
+use super::block_template::BlockTemplateConfig;
 use crate::validation::utils::{double_hash, varint};
 use crate::{parsing::transaction_structs::Transaction, validation::validate_parsing::get_txid};
 use hex_literal::hex as hexlit;
@@ -18,7 +19,7 @@ pub fn get_merkle_root(block_txs: &[Vec<u8>]) -> Vec<u8> {
     }
 
     while merkle_tree.len() > 1 {
-        if merkle_tree.len() % 2 != 0 {
+        if !merkle_tree.len().is_multiple_of(2) {
             let last: Vec<u8> = merkle_tree.last().unwrap().clone();
             merkle_tree.push(last);
         }
@@ -75,11 +76,18 @@ fn count_fees(block_txs: &Vec<Transaction>) -> u64 {
 }
 
 // serializes the coinbase transaction as Vec<u8>. If is_segwit is true it will include marker, flag
-// and the witness reserved value.
-fn serialize_coinbase_transaction(block_txs: &Vec<Transaction>, is_segwit: bool) -> Vec<u8> {
+// and the witness reserved value. Height, message, payout script and subsidy come from the config,
+// the extranonce is rolled by the miner when the header nonce space is exhausted.
+fn serialize_coinbase_transaction(
+    block_txs: &Vec<Transaction>,
+    config: &BlockTemplateConfig,
+    extranonce: u32,
+    is_segwit: bool,
+) -> Vec<u8> {
     let mut coinbase_transaction: Vec<u8> = Vec::new();
     let wtxid_commitment_scriptpubkey: Vec<u8> = calc_wtxid_commitment_scriptpubkey(block_txs);
-    let reward: u64 = count_fees(block_txs) + 625000000;
+    let reward: u64 = count_fees(block_txs) + config.subsidy();
+    let payout_scriptpubkey = config.payout_scriptpubkey_bytes();
 
     coinbase_transaction.extend(hexlit!("01000000")); // version
     if is_segwit {
@@ -88,18 +96,14 @@ fn serialize_coinbase_transaction(block_txs: &Vec<Transaction>, is_segwit: bool)
     coinbase_transaction.extend(hexlit!(
         "010000000000000000000000000000000000000000000000000000000000000000ffffffff"
     )); // input count + input + index
-    let mut scriptsig = varint(varint(839653).len() as u128); //pushbytes len blockheight
-    scriptsig.extend(varint(839653)); // blockheight
-    scriptsig.extend(hexlit!("1043797068657270756E6B467574757265")); // this is 16 + secret ascii message :)
+    let scriptsig = config.coinbase_scriptsig(extranonce); // blockheight (BIP34) + extranonce + message
     coinbase_transaction.extend(varint(scriptsig.len() as u128));
     coinbase_transaction.extend(scriptsig);
     coinbase_transaction.extend(hexlit!("ffffffff")); // sequence
     coinbase_transaction.extend(hexlit!("02")); // 2 outputs (reward and witness commitment op_return)
     coinbase_transaction.extend(reward.to_le_bytes());
-    coinbase_transaction.extend(varint(
-        hexlit!("001435f6de260c9f3bdee47524c473a6016c0c055cb9").len() as u128,
-    )); // reward p2wpkh scriptpubkey
-    coinbase_transaction.extend(hexlit!("001435f6de260c9f3bdee47524c473a6016c0c055cb9"));
+    coinbase_transaction.extend(varint(payout_scriptpubkey.len() as u128)); // reward scriptpubkey
+    coinbase_transaction.extend(payout_scriptpubkey);
     coinbase_transaction.extend(hexlit!("0000000000000000")); // witness amount
     coinbase_transaction.extend(varint(wtxid_commitment_scriptpubkey.len() as u128)); // len wtxid commitment
     coinbase_transaction.extend(wtxid_commitment_scriptpubkey);
@@ -113,10 +117,24 @@ fn serialize_coinbase_transaction(block_txs: &Vec<Transaction>, is_segwit: bool)
     coinbase_transaction
 }
 
+// calculates the weight of the coinbase transaction of the config. Only the reward amount
+// and the witness commitment depend on the block transactions, both have a fixed size.
+// returns: coinbase weight in weight units
+pub fn get_coinbase_weight(config: &BlockTemplateConfig) -> u64 {
+    let stripped_size = serialize_coinbase_transaction(&Vec::new(), config, 0, false).len();
+    let total_size = serialize_coinbase_transaction(&Vec::new(), config, 0, true).len();
+    (stripped_size * 3 + total_size) as u64
+}
+
 // entry function to assemble the coinbase transaction which is returned as CoinbasTxData struct
-pub fn assemble_coinbase_transaction(block_txs: &Vec<Transaction>) -> CoinbaseTxData {
-    let coinbase_tx_witness = serialize_coinbase_transaction(block_txs, true);
-    let coinbase_tx_no_witness = serialize_coinbase_transaction(block_txs, false);
+pub fn assemble_coinbase_transaction(
+    block_txs: &Vec<Transaction>,
+    config: &BlockTemplateConfig,
+    extranonce: u32,
+) -> CoinbaseTxData {
+    let coinbase_tx_witness = serialize_coinbase_transaction(block_txs, config, extranonce, true);
+    let coinbase_tx_no_witness =
+        serialize_coinbase_transaction(block_txs, config, extranonce, false);
 
     CoinbaseTxData {
         txid_hex: hex::encode(get_txid(&coinbase_tx_no_witness)),
@@ -1,71 +1,247 @@
-use super::construct_coinbase::{get_merkle_root, CoinbaseTxData};
-use crate::{parsing::transaction_structs::Transaction, validation::utils::double_hash};
-use hex_literal::hex as hexlit;
-use num_bigint::BigUint;
-use std::time::{SystemTime, UNIX_EPOCH};
-
-// changes the 4 byte nonce at the end of the header to change the HASH256
-// so long till the header + nonce produce a HASH256 below the specified target
-// Comparison of the hash against the target happens as BigUint integer
-// returns: nonce that produces a valid hash as u32
-fn mine_nonce(block_header: &[u8]) -> u32 {
-    let target = BigUint::from_bytes_be(&hexlit!(
-        "00000ffff0000000000000000000000000000000000000000000000000000000"
-    ));
-    let max_nonce = std::u32::MAX;
-    let mut candidate = block_header.to_vec();
-    candidate.extend(0_u32.to_le_bytes());
-
-    for nonce in 0..=max_nonce {
-        let len = candidate.len();
-        candidate[len - 4..].copy_from_slice(&u32::to_le_bytes(nonce));
-        let block_hash: Vec<u8> = double_hash(&candidate);
-
-        let block_hash_num = BigUint::from_bytes_le(&block_hash);
-
-        if block_hash_num < target {
-            return nonce;
+use super::block_template::BlockTemplateConfig;
+use super::compact_target::CompactTarget;
+use super::construct_coinbase::{assemble_coinbase_transaction, get_merkle_root, CoinbaseTxData};
+use super::header_hasher::{hash_meets_target, HeaderHasher};
+use crate::parsing::transaction_structs::Transaction;
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, Scope};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// a block time may be at most 2 hours in the future (MAX_FUTURE_BLOCK_TIME of core),
+// nTime is rolled up to this bound before the extranonce is changed
+const MAX_FUTURE_BLOCK_TIME: u32 = 2 * 60 * 60;
+
+// statistics of the nonce search for reporting
+pub struct MiningStats {
+    pub hashes: u64,
+    pub duration: Duration,
+    pub threads: usize,
+    pub extranonce: u32,
+    pub time_rolls: u32, // nTime increments of the last extranonce
+}
+
+impl MiningStats {
+    // returns: hashes per second
+    pub fn hashrate(&self) -> f64 {
+        self.hashes as f64 / self.duration.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+// hashes the header (without nonce) with all nonces of the range till the HASH256 of the
+// header + nonce is below or equal the target. Stops early if found is set by another thread.
+// Hashing starts from the midstate of the first 64 header bytes, the comparison against the
+// target happens on the 32 byte little endian numbers
+// returns: (nonce that produces a valid hash or None, amount of hashes)
+fn search_nonce_range(
+    block_header: &[u8],
+    target: &[u8; 32],
+    nonces: RangeInclusive<u32>,
+    found: &AtomicBool,
+) -> (Option<u32>, u64) {
+    let hasher = HeaderHasher::new(block_header);
+    let mut hashes: u64 = 0;
+
+    for nonce in nonces {
+        if found.load(Ordering::Relaxed) {
+            break;
+        }
+        let block_hash = hasher.hash(nonce);
+        hashes += 1;
+
+        if hash_meets_target(&block_hash, target) {
+            found.store(true, Ordering::Relaxed);
+            return (Some(nonce), hashes);
         };
     }
-    panic!("All nonces used in mining!");
+    (None, hashes)
 }
 
-// assembles the blockheader according to the specification using hardcoded previous block, version
-// and target according to the exercise
-pub fn construct_header(
-    block_transactions: &Vec<Transaction>,
-    coinbase_tx: &CoinbaseTxData,
-) -> Vec<u8> {
-    let mut block_header: Vec<u8> = Vec::new();
+// splits the 32 bit nonce space evenly across the threads
+// returns: nonce range searched by the thread with thread_index
+fn nonce_range(thread_index: usize, threads: usize) -> RangeInclusive<u32> {
+    let chunk_size = (u32::MAX as u64 + 1) / threads as u64;
+    let first = (thread_index as u64 * chunk_size) as u32;
+    let last = if thread_index == threads - 1 {
+        u32::MAX
+    } else {
+        ((thread_index as u64 + 1) * chunk_size - 1) as u32
+    };
+    first..=last
+}
 
-    block_header.extend(hexlit!("20000000")); // version not signaling updates
-    let previous_block_bytes: Vec<u8> =
-        hexlit!("00000000000000000001901b9f3b6c7a0c34b20b29b950d0d8ffa36c63979c1c")
-            .into_iter()
-            .rev()
+// worker threads that each search their part of the nonce space of every header they
+// receive. The pool lives for the whole mining so nTime rolls and extranonce changes
+// don't spawn new threads, the workers exit when the pool is dropped.
+struct NonceSearchPool<'env> {
+    jobs: Vec<Sender<Vec<u8>>>,
+    results: Receiver<(Option<u32>, u64)>,
+    found: &'env AtomicBool,
+}
+
+impl<'env> NonceSearchPool<'env> {
+    // spawns one worker per thread in the scope, searching against the target
+    // returns: NonceSearchPool
+    fn spawn<'scope>(
+        scope: &'scope Scope<'scope, 'env>,
+        threads: usize,
+        target: &'env [u8; 32],
+        found: &'env AtomicBool,
+    ) -> NonceSearchPool<'env> {
+        let (result_sender, results) = mpsc::channel();
+        let jobs = (0..threads)
+            .map(|thread_index| {
+                let (job_sender, job_receiver) = mpsc::channel::<Vec<u8>>();
+                let result_sender = result_sender.clone();
+                let nonces = nonce_range(thread_index, threads);
+                scope.spawn(move || {
+                    for block_header in job_receiver {
+                        let result =
+                            search_nonce_range(&block_header, target, nonces.clone(), found);
+                        if result_sender.send(result).is_err() {
+                            break;
+                        }
+                    }
+                });
+                job_sender
+            })
             .collect();
-    block_header.extend(previous_block_bytes); // rev bytes of previous block hash (natural order)
+        NonceSearchPool {
+            jobs,
+            results,
+            found,
+        }
+    }
 
-    let mut txids_bytes: Vec<Vec<u8>> = Vec::new();
-    txids_bytes.push(coinbase_tx.txid_natural_bytes.clone());
+    // searches the whole nonce space of the header (76 bytes without nonce) on all workers
+    // returns: (nonce or None if the whole nonce space has been searched, amount of hashes)
+    fn mine_nonce(&self, block_header: &[u8]) -> (Option<u32>, u64) {
+        self.found.store(false, Ordering::Relaxed);
+        for job in &self.jobs {
+            job.send(block_header.to_vec())
+                .expect("Mining thread stopped");
+        }
+
+        let mut result: (Option<u32>, u64) = (None, 0);
+        for _ in 0..self.jobs.len() {
+            let (nonce, hashes) = self.results.recv().expect("Mining thread panicked");
+            result.0 = result.0.or(nonce);
+            result.1 += hashes;
+        }
+        result
+    }
+}
+
+// returns: current unix time in seconds as u32
+fn get_unix_time() -> u32 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(time_sec) => time_sec.as_secs() as u32,
+        Err(_) => panic!("Error getting unix time in header construction!"),
+    }
+}
+
+// assembles the blockheader according to the specification without the nonce, using
+// version, previous block and bits of the block template config
+// returns: 76 header bytes
+fn assemble_header(config: &BlockTemplateConfig, merkle_root: &[u8], time: u32) -> Vec<u8> {
+    let mut block_header: Vec<u8> = Vec::new();
+
+    block_header.extend(config.version.to_le_bytes()); // version
+    block_header.extend(config.previous_block_hash_bytes()); // previous block hash (natural order)
+    block_header.extend(merkle_root); // merkle root
+    block_header.extend(time.to_le_bytes()); // time
+    let target_bits = u32::to_le_bytes(config.bits); // target
+    block_header.extend(target_bits);
+    block_header
+}
+
+// assembles the coinbase transaction and the block header and mines it. The nonce space is
+// searched in parallel on all cores. If it is exhausted nTime is rolled (up to 2 hours ahead),
+// then the extranonce in the coinbase scriptsig is increased, changing the merkle root.
+// returns: (header bytes, coinbase transaction with the used extranonce, MiningStats) or
+// Err(reason) if the bits encode an invalid target, the block time overflows or the search
+// space is exhausted
+pub fn construct_header(
+    block_transactions: &Vec<Transaction>,
+    config: &BlockTemplateConfig,
+) -> Result<(Vec<u8>, CoinbaseTxData, MiningStats), String> {
+    let target = CompactTarget(config.bits)
+        .to_target_bytes()
+        .ok_or_else(|| {
+            format!(
+                "bits {:#010x} encode a negative, zero or overflowing target",
+                config.bits
+            )
+        })?;
+    let start = Instant::now();
+    let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+    let found = AtomicBool::new(false);
+    let mut hashes: u64 = 0;
+
+    let mut txids_bytes: Vec<Vec<u8>> = vec![Vec::new()]; // coinbase txid set per extranonce
     for tx in block_transactions {
         let txid_bytes =
             hex::decode(&tx.meta.txid_hex).expect("construct_header: Error decoding hex ");
         let rev_txid_bytes: Vec<u8> = txid_bytes.into_iter().rev().collect();
         txids_bytes.push(rev_txid_bytes);
     }
-    block_header.extend(get_merkle_root(&txids_bytes)); // merkle root
 
-    if let Ok(time_sec) = SystemTime::now().duration_since(UNIX_EPOCH) {
-        let time_sec: u32 = time_sec.as_secs() as u32;
-        block_header.extend(time_sec.to_le_bytes());
-    } else {
-        panic!("Error getting unix time in header construction!")
-    };
+    thread::scope(|scope| {
+        let pool = NonceSearchPool::spawn(scope, threads, &target, &found);
 
-    let target_bits = u32::to_le_bytes(0x1f00ffff); // target
-    block_header.extend(target_bits);
-    let nonce: u32 = mine_nonce(&block_header);
-    block_header.extend(nonce.to_le_bytes());
-    block_header
+        for extranonce in 0..=u32::MAX {
+            let coinbase_tx = assemble_coinbase_transaction(block_transactions, config, extranonce);
+            txids_bytes[0] = coinbase_tx.txid_natural_bytes.clone();
+            let merkle_root = get_merkle_root(&txids_bytes);
+            // the block time has to be above the median time past of the previous block
+            let start_time = match config.median_time_past {
+                Some(median_time_past) => get_unix_time().max(
+                    median_time_past
+                        .checked_add(1)
+                        .ok_or("No block time above the median time past")?,
+                ),
+                None => get_unix_time(),
+            };
+
+            for time_rolls in 0..=MAX_FUTURE_BLOCK_TIME {
+                let time = start_time
+                    .checked_add(time_rolls)
+                    .ok_or("nTime overflows while rolling the block time")?;
+                let mut block_header = assemble_header(config, &merkle_root, time);
+                let (nonce, nonce_hashes) = pool.mine_nonce(&block_header);
+                hashes += nonce_hashes;
+
+                if let Some(nonce) = nonce {
+                    block_header.extend(nonce.to_le_bytes());
+                    let stats = MiningStats {
+                        hashes,
+                        duration: start.elapsed(),
+                        threads,
+                        extranonce,
+                        time_rolls,
+                    };
+                    return Ok((block_header, coinbase_tx, stats));
+                }
+            }
+        }
+        Err("Extranonce, nTime and nonce space exhausted in mining".to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn block_time_overflow() {
+        let config = BlockTemplateConfig {
+            median_time_past: Some(u32::MAX),
+            ..Default::default()
+        };
+        assert!(matches!(
+            construct_header(&Vec::new(), &config),
+            Err(reason) if reason == "No block time above the median time past"
+        ));
+    }
 }
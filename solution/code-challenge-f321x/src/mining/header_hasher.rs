@@ -0,0 +1,74 @@
+use sha2::compress256;
+use sha2::digest::generic_array::GenericArray;
+
+// initial hash values of SHA-256 (FIPS 180-4, 5.3.3)
+const SHA256_IV: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+// HASH256 of block headers with a changing nonce. The first 64 header bytes (version,
+// previous block hash and 28 bytes of the merkle root) don't change with the nonce, so
+// the SHA-256 state after compressing them (midstate) is calculated once. Per nonce only
+// the second chunk (rest of merkle root, time, bits, nonce + padding) and the second
+// SHA-256 of the 32 byte digest are compressed, without any allocation.
+pub struct HeaderHasher {
+    midstate: [u32; 8],
+    tail_chunk: [u8; 64], // header bytes 64..80 + padding for 80 byte message
+}
+
+impl HeaderHasher {
+    // takes the header with (80 bytes) or without nonce (76 bytes), the nonce is set in hash()
+    // returns: HeaderHasher
+    pub fn new(block_header: &[u8]) -> HeaderHasher {
+        assert!(
+            block_header.len() == 76 || block_header.len() == 80,
+            "Block header has to be 76 or 80 bytes"
+        );
+        let mut midstate = SHA256_IV;
+        compress256(
+            &mut midstate,
+            &[*GenericArray::from_slice(&block_header[..64])],
+        );
+
+        let mut tail_chunk = [0u8; 64];
+        tail_chunk[..12].copy_from_slice(&block_header[64..76]);
+        tail_chunk[16] = 0x80; // padding start bit
+        tail_chunk[56..].copy_from_slice(&(80u64 * 8).to_be_bytes()); // message length in bits
+        HeaderHasher {
+            midstate,
+            tail_chunk,
+        }
+    }
+
+    // returns: HASH256 of the header with the passed nonce, in natural byte order
+    // like double_hash(header)
+    pub fn hash(&self, nonce: u32) -> [u8; 32] {
+        let mut tail_chunk = self.tail_chunk;
+        tail_chunk[12..16].copy_from_slice(&nonce.to_le_bytes());
+        let mut state = self.midstate;
+        compress256(&mut state, &[tail_chunk.into()]);
+
+        // second SHA-256 over the 32 byte digest, fits in a single padded chunk
+        let mut digest_chunk = [0u8; 64];
+        for (word, bytes) in state.iter().zip(digest_chunk.chunks_exact_mut(4)) {
+            bytes.copy_from_slice(&word.to_be_bytes());
+        }
+        digest_chunk[32] = 0x80;
+        digest_chunk[56..].copy_from_slice(&(32u64 * 8).to_be_bytes());
+        let mut state = SHA256_IV;
+        compress256(&mut state, &[digest_chunk.into()]);
+
+        let mut hash = [0u8; 32];
+        for (word, bytes) in state.iter().zip(hash.chunks_exact_mut(4)) {
+            bytes.copy_from_slice(&word.to_be_bytes());
+        }
+        hash
+    }
+}
+
+// compares a header hash against the target, both as 32 byte little endian numbers
+// (hash in natural byte order as returned by HeaderHasher::hash)
+// returns: true if the hash is below or equal the target
+pub fn hash_meets_target(hash: &[u8; 32], target: &[u8; 32]) -> bool {
+    hash.iter().rev().le(target.iter().rev())
+}
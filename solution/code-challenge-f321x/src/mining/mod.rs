@@ -1,66 +1,139 @@
 mod assign_parents;
+pub mod block_assembler;
+pub mod block_template;
+pub mod block_weight;
+pub mod cluster_linearization;
+pub mod compact_target;
 mod construct_coinbase;
 mod header;
-mod packet_weight;
+pub mod header_hasher;
+pub mod packet_weight;
+mod remove_non_final;
+mod resolve_conflicts;
 mod transaction_sorting;
 
+pub use self::block_template::{BlockTemplateConfig, SelectionStrategy};
+pub use self::construct_coinbase::get_merkle_root;
+pub use self::header::MiningStats;
 use self::{
     assign_parents::assign_mempool_parents,
-    construct_coinbase::{assemble_coinbase_transaction, CoinbaseTxData},
+    block_assembler::BlockAssembler,
+    block_weight::BlockWeightBudget,
+    cluster_linearization::select_by_cluster_linearization,
+    construct_coinbase::CoinbaseTxData,
     header::construct_header,
     packet_weight::calculate_packet_weights,
+    remove_non_final::remove_non_final_transactions,
+    resolve_conflicts::resolve_conflicts,
     transaction_sorting::{cut_size, sort_transactions},
 };
 use crate::parsing::transaction_structs::Transaction;
+use crate::validation::{
+    finality::UtxoConfirmations, utils::varint, validate_parsing::serialize_transaction,
+};
 use std::collections::HashMap;
 
 pub struct Block {
     pub header_hex: String,
     pub coinbase_tx_hex: String,
     pub txids_hex: Vec<String>,
+    pub transactions_bytes: Vec<Vec<u8>>, // serialized transactions following the coinbase
+    pub fees: u64,                        // sum of the fees of all transactions in satoshi
+    pub mining_stats: MiningStats,
+}
+
+impl Block {
+    // serializes the block in consensus format: header, varint tx count, coinbase
+    // (with witness) and all transactions in witness serialization
+    // returns: Vec<u8> of the raw block, e.g. for submitblock on a regtest node
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut block: Vec<u8> = hex::decode(&self.header_hex).expect("Header hex invalid");
+
+        block.extend(varint(self.transactions_bytes.len() as u128 + 1));
+        block.extend(hex::decode(&self.coinbase_tx_hex).expect("Coinbase hex invalid"));
+        for tx_bytes in &self.transactions_bytes {
+            block.extend(tx_bytes);
+        }
+        block
+    }
 }
 
-// hex encodes header and coinbase tx and creates a Vec<hex txid String> including
-// the coinbase txid and returns it as Block struct for use in writing the output.txt
+// hex encodes header and coinbase tx, creates a Vec<hex txid String> including
+// the coinbase txid and serializes the transactions. Returns it as Block struct
+// for use in writing the output.txt or the raw block, Err(reason) if a transaction
+// can't be serialized
 fn return_block(
     block_header_bytes: &[u8],
     coinbase_tx: CoinbaseTxData,
     transactions: &Vec<Transaction>,
-) -> Block {
+    mining_stats: MiningStats,
+) -> Result<Block, String> {
     let header_hex = hex::encode(block_header_bytes);
     let coinbase_tx_hex = hex::encode(coinbase_tx.assembled_tx);
     let mut txids_hex: Vec<String> = vec![coinbase_tx.txid_hex];
+    let mut transactions_bytes: Vec<Vec<u8>> = Vec::new();
+    let mut fees: u64 = 0;
     for tx in transactions {
         txids_hex.push(tx.meta.txid_hex.clone());
+        transactions_bytes.push(serialize_transaction(tx)?);
+        fees += tx.meta.fee;
     }
-    Block {
+    Ok(Block {
         header_hex,
         coinbase_tx_hex,
         txids_hex,
-    }
+        transactions_bytes,
+        fees,
+        mining_stats,
+    })
 }
 
-// main "mining" function. Takes a HashMap of valid transactions,
+// main "mining" function. Takes a HashMap of valid transactions, the block template config and
+// the confirmations of the spent outputs (for relative locks),
 // Returns a Block struct with a blockheader, coinbase transaction and
-// a Vec of txids sorted to maximise fee revenue and block space utilization
-pub fn mine_block(txid_tx_map: &mut HashMap<String, Transaction>) -> Block {
+// a Vec of txids sorted to maximise fee revenue and block space utilization,
+// or Err(reason) if the header can't be mined
+pub fn mine_block(
+    txid_tx_map: &mut HashMap<String, Transaction>,
+    config: &BlockTemplateConfig,
+    utxo_confirmations: &UtxoConfirmations,
+) -> Result<Block, String> {
     // link children with parent transactions
     assign_mempool_parents(txid_tx_map);
 
-    // calculate packet weights for transactions with ancestors in mempool
-    calculate_packet_weights(txid_tx_map);
+    // keep transactions with locktime or relative locks not reached out of the block
+    remove_non_final_transactions(txid_tx_map, config, utxo_confirmations);
+
+    // keep a single spender of each outpoint, resolved by BIP125 replacement rules
+    resolve_conflicts(txid_tx_map);
+
+    // weight of header, transaction count and coinbase besides the selected transactions
+    let budget = BlockWeightBudget::new(config);
 
-    // sorts transactions by packet feerate and ancestry and removes enough respect block size
-    let block_ordered: Vec<Transaction> = cut_size(sort_transactions(txid_tx_map));
+    let block_ordered: Vec<Transaction> = match config.selection {
+        // selects ancestor packages by feerate within the block weight and sigop limits
+        SelectionStrategy::AncestorScore => {
+            BlockAssembler::new(txid_tx_map, budget).add_package_txs()
+        }
+        SelectionStrategy::PacketSort => {
+            // calculate packet weights for transactions with ancestors in mempool
+            calculate_packet_weights(txid_tx_map);
 
-    // assembles the coinbase transaction including the witness commitment
-    let coinbase_tx: CoinbaseTxData = assemble_coinbase_transaction(&block_ordered);
+            // sorts transactions by packet feerate and ancestry and removes enough respect block size
+            cut_size(sort_transactions(txid_tx_map), &budget)
+        }
+        // linearizes the mempool clusters and merges their chunks by feerate
+        SelectionStrategy::ClusterLinearization => {
+            select_by_cluster_linearization(txid_tx_map, budget)
+        }
+    };
 
-    // assembles the block header
-    let block_header = construct_header(&block_ordered, &coinbase_tx);
+    // assembles the coinbase transaction including the witness commitment and mines
+    // the block header, rolling the extranonce of the coinbase if needed
+    let (block_header, coinbase_tx, mining_stats) = construct_header(&block_ordered, config)?;
 
     // encode in Block struct and returns final data needed for output.txt
-    return_block(&block_header, coinbase_tx, &block_ordered)
+    return_block(&block_header, coinbase_tx, &block_ordered, mining_stats)
 }
 
 // -----------------------
@@ -1,48 +1,169 @@
-use crate::parsing::transaction_structs::Transaction;
-use std::collections::HashMap;
+use crate::parsing::transaction_structs::{Packet, Transaction};
+use std::collections::{HashMap, HashSet};
 
-struct FeeAndWeight {
-    fee: u64,
-    weight: u64,
+// Transaction graph of the mempool, built from the parents set by assign_mempool_parents.
+// Transactions are indexed sorted by txid (for a deterministic order of equal scores) and
+// listed once as parent or child even if multiple outputs are spent.
+// The ancestor and descendant sets (without the transaction itself) are calculated once from
+// the already calculated sets of the parents (or children), so shared ancestors of diamond
+// shaped dependencies are contained once and aren't visited again for every path.
+pub struct MempoolRelatives {
+    txids: Vec<String>,
+    indices: HashMap<String, usize>,
+    parents: Vec<Vec<usize>>,
+    children: Vec<Vec<usize>>,
+    ancestors: Vec<Vec<usize>>,
+    descendants: Vec<Vec<usize>>,
 }
 
-// recursively goes to the bottom of a transaction dependency structure and sums up the fee and weight
-// up to the passed transaction which are returned in FeeAndWeight
-fn calc_parents(transactions: &HashMap<String, Transaction>, child_txid: &String) -> FeeAndWeight {
-    let mut fee_and_weight: FeeAndWeight;
+// collects the passed transactions and their already calculated relatives once
+// returns: sorted Vec of the unique indices
+fn merge_relatives(direct: &[usize], relatives: &[Vec<usize>]) -> Vec<usize> {
+    let mut merged: HashSet<usize> = HashSet::new();
+    for &index in direct {
+        merged.insert(index);
+        merged.extend(&relatives[index]);
+    }
+    let mut merged: Vec<usize> = merged.into_iter().collect();
+    merged.sort_unstable();
+    merged
+}
 
-    if let Some(child_transaction) = transactions.get(child_txid) {
-        fee_and_weight = FeeAndWeight {
-            fee: child_transaction.meta.fee,
-            weight: child_transaction.meta.weight,
-        };
+impl MempoolRelatives {
+    // indexes the transactions, builds the parent and child lists and calculates the ancestor
+    // sets from the parents to the children and the descendant sets from the children to
+    // the parents
+    // returns: MempoolRelatives
+    pub fn new(transactions: &HashMap<String, Transaction>) -> MempoolRelatives {
+        let mut txids: Vec<String> = transactions.keys().cloned().collect();
+        txids.sort_unstable();
+        let indices: HashMap<String, usize> = txids
+            .iter()
+            .enumerate()
+            .map(|(index, txid)| (txid.clone(), index))
+            .collect();
 
-        if let Some(parents_txids) = child_transaction.meta.parents.as_ref() {
-            for parent in parents_txids {
-                let temp_result = calc_parents(transactions, parent);
-                fee_and_weight.fee += temp_result.fee;
-                fee_and_weight.weight += temp_result.weight;
+        let mut parents: Vec<Vec<usize>> = vec![Vec::new(); txids.len()];
+        let mut children: Vec<Vec<usize>> = vec![Vec::new(); txids.len()];
+        for (index, txid) in txids.iter().enumerate() {
+            for parent_txid in transactions[txid].meta.parents.iter().flatten() {
+                let parent = indices[parent_txid];
+                if !parents[index].contains(&parent) {
+                    parents[index].push(parent);
+                    children[parent].push(index);
+                }
             }
-        } else {
-            return fee_and_weight;
+        }
+
+        let mut relatives = MempoolRelatives {
+            ancestors: vec![Vec::new(); txids.len()],
+            descendants: vec![Vec::new(); txids.len()],
+            txids,
+            indices,
+            parents,
+            children,
         };
-    } else {
-        panic!("calc_parent_fees: tx not found?");
-    };
+        let order = relatives.topological_order();
+        for &index in &order {
+            relatives.ancestors[index] =
+                merge_relatives(&relatives.parents[index], &relatives.ancestors);
+        }
+        for &index in order.iter().rev() {
+            relatives.descendants[index] =
+                merge_relatives(&relatives.children[index], &relatives.descendants);
+        }
+        relatives
+    }
+
+    // orders the transaction indices so every transaction comes after its parents
+    // (Kahn's algorithm)
+    // returns: Vec of indices in topological order
+    pub fn topological_order(&self) -> Vec<usize> {
+        let mut missing_parents: Vec<usize> =
+            self.parents.iter().map(|parents| parents.len()).collect();
+        let mut ready: Vec<usize> = (0..self.txids.len())
+            .filter(|&index| missing_parents[index] == 0)
+            .collect();
+        let mut order: Vec<usize> = Vec::with_capacity(self.txids.len());
+
+        while let Some(index) = ready.pop() {
+            order.push(index);
+            for &child in &self.children[index] {
+                missing_parents[child] -= 1;
+                if missing_parents[child] == 0 {
+                    ready.push(child);
+                }
+            }
+        }
+        order
+    }
+
+    // returns: the transactions of the map in index order
+    pub fn indexed_transactions<'a>(
+        &self,
+        transactions: &'a HashMap<String, Transaction>,
+    ) -> Vec<&'a Transaction> {
+        self.txids.iter().map(|txid| &transactions[txid]).collect()
+    }
+
+    // returns: parent indices of the transaction at index
+    pub fn parent_indices(&self, index: usize) -> &[usize] {
+        &self.parents[index]
+    }
+
+    // returns: child indices of the transaction at index
+    pub fn child_indices(&self, index: usize) -> &[usize] {
+        &self.children[index]
+    }
+
+    // returns: sorted indices of all mempool ancestors of the transaction at index
+    pub fn ancestor_indices(&self, index: usize) -> &[usize] {
+        &self.ancestors[index]
+    }
+
+    // returns: sorted indices of all mempool descendants of the transaction at index
+    pub fn descendant_indices(&self, index: usize) -> &[usize] {
+        &self.descendants[index]
+    }
+
+    // returns: txids of all mempool ancestors of the transaction
+    pub fn ancestors(&self, txid: &str) -> impl Iterator<Item = &String> + '_ {
+        self.ancestors[self.indices[txid]]
+            .iter()
+            .map(|&index| &self.txids[index])
+    }
 
-    fee_and_weight
+    // returns: txids of all mempool descendants of the transaction
+    pub fn descendants(&self, txid: &str) -> impl Iterator<Item = &String> + '_ {
+        self.descendants[self.indices[txid]]
+            .iter()
+            .map(|&index| &self.txids[index])
+    }
 }
 
-// assigning the packet fee, weight and feerate to each transaction.
+// assigning the packet fee, weight, sigop cost, sigop-adjusted vsize and feerate to each
+// transaction, summed up over the transaction and each of its ancestors once.
 // the packet data are equal to the tx data if the tx has no parents
 pub fn calculate_packet_weights(transactions: &mut HashMap<String, Transaction>) {
-    let transactions_original_clone = transactions.clone();
-
-    for (txid, tx) in transactions.iter_mut() {
-        let temp_result = calc_parents(&transactions_original_clone, txid);
-        tx.meta.packet_data.packet_fee_sat = temp_result.fee;
-        tx.meta.packet_data.packet_weight = temp_result.weight;
+    let relatives = MempoolRelatives::new(transactions);
+    let packets: Vec<(String, Packet)> = transactions
+        .keys()
+        .map(|txid| {
+            let mut packet = Packet::default();
+            for member in relatives.ancestors(txid).chain(std::iter::once(txid)) {
+                packet.packet_fee_sat += transactions[member].meta.fee;
+                packet.packet_weight += transactions[member].meta.weight;
+                packet.packet_sigops_cost += transactions[member].meta.sigops_cost;
+                packet.packet_vsize += transactions[member].sigop_adjusted_vsize();
+            }
+            packet.packet_feerate_weight = packet.packet_fee_sat / packet.packet_weight;
+            (txid.clone(), packet)
+        })
+        .collect();
 
-        tx.meta.packet_data.packet_feerate_weight = temp_result.fee / temp_result.weight;
+    for (txid, packet) in packets {
+        if let Some(tx) = transactions.get_mut(&txid) {
+            tx.meta.packet_data = packet;
+        }
     }
 }
@@ -0,0 +1,43 @@
+use super::block_template::BlockTemplateConfig;
+use super::packet_weight::MempoolRelatives;
+use crate::parsing::transaction_structs::Transaction;
+use crate::validation::finality::{check_finality, UtxoConfirmations};
+use crate::validation::ValidationResult;
+use std::collections::{HashMap, HashSet};
+
+// removes the transactions that are not final in the template block (locktime or BIP68
+// relative lock not reached) together with all their mempool descendants, as a child can't
+// be included without its parent. Needs the parents set by assign_mempool_parents.
+pub fn remove_non_final_transactions(
+    txid_tx_map: &mut HashMap<String, Transaction>,
+    config: &BlockTemplateConfig,
+    utxo_confirmations: &UtxoConfirmations,
+) {
+    let non_final: Vec<&String> = txid_tx_map
+        .iter()
+        .filter(|(_, tx)| {
+            let result = check_finality(
+                tx,
+                config.height,
+                config.median_time_past,
+                utxo_confirmations,
+                txid_tx_map,
+            );
+            matches!(result, ValidationResult::Invalid(_))
+        })
+        .map(|(txid, _)| txid)
+        .collect();
+    if non_final.is_empty() {
+        return;
+    }
+
+    let relatives = MempoolRelatives::new(txid_tx_map);
+    let mut removed: HashSet<String> = HashSet::new();
+    for txid in non_final {
+        removed.insert(txid.clone());
+        removed.extend(relatives.descendants(txid).cloned());
+    }
+    for txid in &removed {
+        txid_tx_map.remove(txid);
+    }
+}
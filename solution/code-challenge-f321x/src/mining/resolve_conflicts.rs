@@ -0,0 +1,525 @@
+use super::packet_weight::MempoolRelatives;
+use crate::parsing::transaction_structs::Transaction;
+use crate::validation::validate_values::decode_prevout;
+use std::collections::{HashMap, HashSet};
+
+// inputs with a sequence up to this value signal replaceability (BIP125)
+const MAX_BIP125_RBF_SEQUENCE: u32 = 0xfffffffd;
+// maximum amount of transactions evicted by a replacement (including descendants)
+const MAX_REPLACEMENT_CANDIDATES: usize = 100;
+// the replacement has to pay for its own vsize at this feerate (sat/vbyte) on top
+const INCREMENTAL_RELAY_FEERATE: u64 = 1;
+
+// Transactions of the mempool spending each outpoint (decoded txid, vout). Two transactions
+// spending the same outpoint conflict, only one of them can be included in a block.
+// The txids are compared as bytes, so the same outpoint written in different hex letter
+// case is a conflict too.
+pub struct ConflictIndex {
+    spenders: HashMap<([u8; 32], u32), Vec<String>>,
+}
+
+impl ConflictIndex {
+    // returns: ConflictIndex of all inputs of the passed transactions
+    pub fn new(txid_tx_map: &HashMap<String, Transaction>) -> ConflictIndex {
+        let mut spenders: HashMap<([u8; 32], u32), Vec<String>> = HashMap::new();
+
+        for (txid, tx) in txid_tx_map {
+            for outpoint in tx.vin.iter().filter_map(decode_prevout) {
+                let outpoint_spenders = spenders.entry(outpoint).or_default();
+                if !outpoint_spenders.contains(txid) {
+                    outpoint_spenders.push(txid.clone());
+                }
+            }
+        }
+        ConflictIndex { spenders }
+    }
+
+    // returns: sorted txids of the other transactions spending an input of the transaction
+    pub fn conflicts(&self, txid: &str, tx: &Transaction) -> Vec<String> {
+        let mut conflicts: Vec<String> = tx
+            .vin
+            .iter()
+            .filter_map(decode_prevout)
+            .filter_map(|outpoint| self.spenders.get(&outpoint))
+            .flatten()
+            .filter(|spender| spender.as_str() != txid)
+            .cloned()
+            .collect();
+        conflicts.sort_unstable();
+        conflicts.dedup();
+        conflicts
+    }
+
+    // groups the conflicting transactions into sets connected by shared outpoints, a conflict
+    // set can be resolved independent of the others
+    // returns: conflict sets as sorted txids, sorted by their first txid
+    pub fn conflict_sets(&self) -> Vec<Vec<String>> {
+        let mut neighbours: HashMap<&String, Vec<&String>> = HashMap::new();
+        for outpoint_spenders in self.spenders.values() {
+            if outpoint_spenders.len() > 1 {
+                for spender in outpoint_spenders {
+                    neighbours
+                        .entry(spender)
+                        .or_default()
+                        .extend(outpoint_spenders);
+                }
+            }
+        }
+
+        let mut visited: HashSet<&String> = HashSet::new();
+        let mut conflict_sets: Vec<Vec<String>> = Vec::new();
+        for &start in neighbours.keys() {
+            if !visited.insert(start) {
+                continue;
+            }
+            let mut conflict_set: Vec<String> = Vec::new();
+            let mut stack: Vec<&String> = vec![start];
+            while let Some(txid) = stack.pop() {
+                conflict_set.push(txid.clone());
+                for &neighbour in &neighbours[txid] {
+                    if visited.insert(neighbour) {
+                        stack.push(neighbour);
+                    }
+                }
+            }
+            conflict_set.sort_unstable();
+            conflict_sets.push(conflict_set);
+        }
+        conflict_sets.sort_unstable();
+        conflict_sets
+    }
+}
+
+// returns: true if the transaction or one of its mempool ancestors signals replaceability
+fn signals_replaceability(
+    txid: &str,
+    txid_tx_map: &HashMap<String, Transaction>,
+    relatives: &MempoolRelatives,
+) -> bool {
+    let signals = |tx: &Transaction| {
+        tx.vin
+            .iter()
+            .any(|txin| txin.sequence <= MAX_BIP125_RBF_SEQUENCE)
+    };
+    signals(&txid_tx_map[txid])
+        || relatives
+            .ancestors(txid)
+            .any(|ancestor| signals(&txid_tx_map[ancestor]))
+}
+
+// returns: the conflicts and all their descendants that are not removed yet
+fn get_evicted(
+    conflicts: &[String],
+    relatives: &MempoolRelatives,
+    removed: &HashSet<String>,
+) -> HashSet<String> {
+    let mut evicted: HashSet<String> = HashSet::new();
+    for conflict in conflicts {
+        evicted.insert(conflict.clone());
+        evicted.extend(
+            relatives
+                .descendants(conflict)
+                .filter(|descendant| !removed.contains(*descendant))
+                .cloned(),
+        );
+    }
+    evicted
+}
+
+// returns: true if the transaction spends (directly or through its ancestors) one of the
+// evicted transactions, it would be evicted itself then
+fn spends_evicted(txid: &str, evicted: &HashSet<String>, relatives: &MempoolRelatives) -> bool {
+    relatives
+        .ancestors(txid)
+        .any(|ancestor| evicted.contains(ancestor))
+}
+
+// checks if the transaction can replace its direct conflicts following the BIP125 rules:
+// all conflicts signal replaceability (also inherited from ancestors), at most 100 transactions
+// are evicted, no new unconfirmed inputs are added, the fee is at least the fee of all evicted
+// transactions and pays the incremental relay feerate for its own vsize on top
+// returns: Ok(()) or Err(reason)
+fn check_replacement(
+    txid: &str,
+    conflicts: &[String],
+    evicted: &HashSet<String>,
+    txid_tx_map: &HashMap<String, Transaction>,
+    relatives: &MempoolRelatives,
+) -> Result<(), String> {
+    let replacement = &txid_tx_map[txid];
+
+    if let Some(conflict) = conflicts
+        .iter()
+        .find(|conflict| !signals_replaceability(conflict, txid_tx_map, relatives))
+    {
+        return Err(format!("txn-mempool-conflict with {}", conflict));
+    }
+    if evicted.len() > MAX_REPLACEMENT_CANDIDATES {
+        return Err("too many potential replacements".to_string());
+    }
+    if spends_evicted(txid, evicted, relatives) {
+        return Err("replacement-spends-conflicting-tx".to_string());
+    }
+
+    // unconfirmed inputs are only allowed if a replaced transaction spent the same parent
+    let conflict_parents: HashSet<&String> = conflicts
+        .iter()
+        .flat_map(|conflict| txid_tx_map[conflict].vin.iter().map(|txin| &txin.txid))
+        .collect();
+    if replacement
+        .meta
+        .parents
+        .iter()
+        .flatten()
+        .any(|parent| !conflict_parents.contains(parent))
+    {
+        return Err("replacement-adds-unconfirmed".to_string());
+    }
+
+    let evicted_fee: u64 = evicted.iter().map(|txid| txid_tx_map[txid].meta.fee).sum();
+    if replacement.meta.fee < evicted_fee {
+        return Err("insufficient fee, less than the replaced transactions".to_string());
+    }
+    if replacement.meta.fee - evicted_fee
+        < INCREMENTAL_RELAY_FEERATE * replacement.sigop_adjusted_vsize()
+    {
+        return Err("insufficient fee, doesn't pay for its own relay".to_string());
+    }
+    Ok(())
+}
+
+// resolves a set of transactions connected by conflicts till none of them conflicts with
+// another one left in the mempool. Each transaction of the set is tried as replacement of its
+// remaining conflicts, the valid replacement with the highest fee wins and evicts its
+// conflicts with their descendants. If no transaction is a valid replacement the one with the
+// highest fee together with its descendants is kept, unless it spends one of its conflicts.
+// The evicted transactions are added to removed.
+fn resolve_conflict_set(
+    conflict_set: &[String],
+    txid_tx_map: &HashMap<String, Transaction>,
+    conflict_index: &ConflictIndex,
+    relatives: &MempoolRelatives,
+    removed: &mut HashSet<String>,
+) {
+    loop {
+        let mut best_replacement: Option<(u64, HashSet<String>)> = None;
+        let mut highest_fee: Option<(u64, HashSet<String>)> = None;
+        for txid in conflict_set.iter().filter(|txid| !removed.contains(*txid)) {
+            let tx = &txid_tx_map[txid];
+            let conflicts: Vec<String> = conflict_index
+                .conflicts(txid, tx)
+                .into_iter()
+                .filter(|conflict| !removed.contains(conflict))
+                .collect();
+            if conflicts.is_empty() {
+                continue;
+            }
+            let evicted = get_evicted(&conflicts, relatives, removed);
+            if spends_evicted(txid, &evicted, relatives) {
+                continue; // can't be kept without its conflicting ancestor
+            }
+
+            let fee = tx.meta.fee;
+            let descendants_fee: u64 = relatives
+                .descendants(txid)
+                .filter(|descendant| !removed.contains(*descendant))
+                .map(|descendant| txid_tx_map[descendant].meta.fee)
+                .sum();
+            let replaces =
+                check_replacement(txid, &conflicts, &evicted, txid_tx_map, relatives).is_ok();
+            if replaces
+                && best_replacement
+                    .as_ref()
+                    .is_none_or(|(best, _)| fee > *best)
+            {
+                best_replacement = Some((fee, evicted.clone()));
+            }
+            if highest_fee
+                .as_ref()
+                .is_none_or(|(highest, _)| fee + descendants_fee > *highest)
+            {
+                highest_fee = Some((fee + descendants_fee, evicted));
+            }
+        }
+
+        match best_replacement.or(highest_fee) {
+            Some((_, evicted)) => removed.extend(evicted),
+            None => return, // no conflicts left in the set
+        }
+    }
+}
+
+// resolves conflicting transactions (spending the same outpoint) of the mempool, so only one
+// spender of each outpoint stays. As the arrival order is unknown each conflicting
+// transaction is tried as replacement of its conflicts with the BIP125 rules. The conflict
+// index and the relatives are calculated once, each set of connected conflicts is resolved
+// on them and the evicted transactions are removed at the end.
+// Needs the parents set by assign_mempool_parents.
+pub fn resolve_conflicts(txid_tx_map: &mut HashMap<String, Transaction>) {
+    let conflict_index = ConflictIndex::new(txid_tx_map);
+    let relatives = MempoolRelatives::new(txid_tx_map);
+    let mut removed: HashSet<String> = HashSet::new();
+
+    for conflict_set in conflict_index.conflict_sets() {
+        resolve_conflict_set(
+            &conflict_set,
+            txid_tx_map,
+            &conflict_index,
+            &relatives,
+            &mut removed,
+        );
+    }
+    for txid in &removed {
+        txid_tx_map.remove(txid);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{mempool, mempool_tx, new_input, txid};
+
+    const SIGNALS: u32 = MAX_BIP125_RBF_SEQUENCE;
+    const FINAL: u32 = u32::MAX;
+    const WEIGHT: u64 = 400; // 100 vbyte
+
+    // returns: txid of a confirmed transaction (not in the mempool)
+    fn confirmed(number: u64) -> String {
+        txid(1000 + number)
+    }
+
+    // returns: result of checking the transaction as replacement of all its conflicts
+    fn check(txid_tx_map: &HashMap<String, Transaction>, replacement: &str) -> Result<(), String> {
+        let conflict_index = ConflictIndex::new(txid_tx_map);
+        let relatives = MempoolRelatives::new(txid_tx_map);
+        let conflicts = conflict_index.conflicts(replacement, &txid_tx_map[replacement]);
+        let evicted = get_evicted(&conflicts, &relatives, &HashSet::new());
+        check_replacement(replacement, &conflicts, &evicted, txid_tx_map, &relatives)
+    }
+
+    // returns: sorted txids of the transactions left after resolving the conflicts
+    fn resolve(transactions: Vec<Transaction>) -> Vec<String> {
+        let mut txid_tx_map = mempool(transactions);
+        resolve_conflicts(&mut txid_tx_map);
+        let mut kept: Vec<String> = txid_tx_map.into_keys().collect();
+        kept.sort_unstable();
+        kept
+    }
+
+    // returns: the original transaction 1 spending the first confirmed outpoint and the
+    // replacement 2 spending it too with the passed fee
+    fn original_and_replacement(sequence: u32, fee: u64) -> Vec<Transaction> {
+        vec![
+            mempool_tx(
+                &txid(1),
+                vec![new_input(&confirmed(1), 0, sequence)],
+                1000,
+                WEIGHT,
+                0,
+            ),
+            mempool_tx(
+                &txid(2),
+                vec![new_input(&confirmed(1), 0, FINAL)],
+                fee,
+                WEIGHT,
+                0,
+            ),
+        ]
+    }
+
+    #[test]
+    fn valid_replacement() {
+        let txid_tx_map = mempool(original_and_replacement(SIGNALS, 1100));
+        assert_eq!(check(&txid_tx_map, &txid(2)), Ok(()));
+        assert_eq!(
+            resolve(original_and_replacement(SIGNALS, 1100)),
+            vec![txid(2)]
+        );
+    }
+
+    #[test]
+    fn non_signalling_conflict() {
+        let txid_tx_map = mempool(original_and_replacement(FINAL, 1100));
+        let err = check(&txid_tx_map, &txid(2)).unwrap_err();
+        assert!(err.starts_with("txn-mempool-conflict"), "{}", err);
+
+        // replaceability is inherited from a signalling ancestor
+        let mut transactions = original_and_replacement(FINAL, 1100);
+        transactions.push(mempool_tx(
+            &txid(3),
+            vec![new_input(&confirmed(2), 0, SIGNALS)],
+            1000,
+            WEIGHT,
+            0,
+        ));
+        transactions[0].vin.push(new_input(&txid(3), 0, FINAL));
+        transactions[1].meta.fee = 2100;
+        assert_eq!(check(&mempool(transactions), &txid(2)), Ok(()));
+    }
+
+    #[test]
+    fn too_many_evictions() {
+        // the original and its children, each paying 1 sat
+        let with_children = |children: u64| {
+            let mut transactions = original_and_replacement(SIGNALS, 1_000_000);
+            for child in 0..children {
+                let vin = vec![new_input(&txid(1), child as u32, FINAL)];
+                transactions.push(mempool_tx(&txid(10 + child), vin, 1, WEIGHT, 0));
+            }
+            mempool(transactions)
+        };
+        assert_eq!(check(&with_children(99), &txid(2)), Ok(()));
+        assert_eq!(
+            check(&with_children(100), &txid(2)),
+            Err("too many potential replacements".to_string())
+        );
+    }
+
+    #[test]
+    fn replacement_adds_unconfirmed() {
+        let unconfirmed_parent = mempool_tx(
+            &txid(3),
+            vec![new_input(&confirmed(2), 0, FINAL)],
+            1000,
+            WEIGHT,
+            0,
+        );
+        let mut transactions = original_and_replacement(SIGNALS, 2000);
+        transactions.push(unconfirmed_parent.clone());
+        transactions[1].vin.push(new_input(&txid(3), 0, FINAL));
+        assert_eq!(
+            check(&mempool(transactions.clone()), &txid(2)),
+            Err("replacement-adds-unconfirmed".to_string())
+        );
+
+        // allowed if the replaced transaction spent the same parent
+        transactions[0].vin.push(new_input(&txid(3), 1, FINAL));
+        assert_eq!(check(&mempool(transactions), &txid(2)), Ok(()));
+    }
+
+    #[test]
+    fn insufficient_fee() {
+        let txid_tx_map = mempool(original_and_replacement(SIGNALS, 999));
+        assert_eq!(
+            check(&txid_tx_map, &txid(2)),
+            Err("insufficient fee, less than the replaced transactions".to_string())
+        );
+
+        // the fees of the evicted descendants count too
+        let mut transactions = original_and_replacement(SIGNALS, 1400);
+        let vin = vec![new_input(&txid(1), 0, FINAL)];
+        transactions.push(mempool_tx(&txid(3), vin, 500, WEIGHT, 0));
+        assert_eq!(
+            check(&mempool(transactions), &txid(2)),
+            Err("insufficient fee, less than the replaced transactions".to_string())
+        );
+    }
+
+    #[test]
+    fn insufficient_incremental_fee() {
+        let txid_tx_map = mempool(original_and_replacement(SIGNALS, 1099));
+        assert_eq!(
+            check(&txid_tx_map, &txid(2)),
+            Err("insufficient fee, doesn't pay for its own relay".to_string())
+        );
+
+        // the incremental fee is paid for the sigop-adjusted vsize (400 * 20 / 4 vbyte)
+        let mut transactions = original_and_replacement(SIGNALS, 1100);
+        transactions[1].meta.sigops_cost = 400;
+        assert_eq!(
+            check(&mempool(transactions), &txid(2)),
+            Err("insufficient fee, doesn't pay for its own relay".to_string())
+        );
+    }
+
+    #[test]
+    fn replacement_spends_conflicting_tx() {
+        // 2 spends the outpoint of 1 and an output of 1 itself
+        let mut transactions = original_and_replacement(SIGNALS, 5000);
+        transactions[1].vin.push(new_input(&txid(1), 0, FINAL));
+        assert_eq!(
+            check(&mempool(transactions.clone()), &txid(2)),
+            Err("replacement-spends-conflicting-tx".to_string())
+        );
+        // the child can't be kept without its parent, even with the higher fee
+        assert_eq!(resolve(transactions), vec![txid(1)]);
+    }
+
+    #[test]
+    fn highest_fee_without_valid_replacement() {
+        assert_eq!(
+            resolve(original_and_replacement(FINAL, 2000)),
+            vec![txid(2)]
+        );
+        assert_eq!(resolve(original_and_replacement(FINAL, 500)), vec![txid(1)]);
+    }
+
+    #[test]
+    fn outpoints_in_different_letter_case_conflict() {
+        let lower = "ab".repeat(32);
+        let upper = "AB".repeat(32);
+        let transactions = vec![
+            mempool_tx(
+                &txid(1),
+                vec![new_input(&lower, 0, SIGNALS)],
+                1000,
+                WEIGHT,
+                0,
+            ),
+            mempool_tx(&txid(2), vec![new_input(&upper, 0, FINAL)], 1100, WEIGHT, 0),
+        ];
+        let txid_tx_map = mempool(transactions.clone());
+        assert_eq!(
+            ConflictIndex::new(&txid_tx_map).conflict_sets(),
+            vec![vec![txid(1), txid(2)]]
+        );
+        assert_eq!(resolve(transactions), vec![txid(2)]);
+    }
+
+    #[test]
+    fn chained_conflict_sets() {
+        // 1 and 2 spend the first outpoint, 2 and 3 the second one
+        let chain = |fees: [u64; 3]| {
+            vec![
+                mempool_tx(
+                    &txid(1),
+                    vec![new_input(&confirmed(1), 0, SIGNALS)],
+                    fees[0],
+                    WEIGHT,
+                    0,
+                ),
+                mempool_tx(
+                    &txid(2),
+                    vec![
+                        new_input(&confirmed(1), 0, SIGNALS),
+                        new_input(&confirmed(2), 0, SIGNALS),
+                    ],
+                    fees[1],
+                    WEIGHT,
+                    0,
+                ),
+                mempool_tx(
+                    &txid(3),
+                    vec![new_input(&confirmed(2), 0, SIGNALS)],
+                    fees[2],
+                    WEIGHT,
+                    0,
+                ),
+            ]
+        };
+        assert_eq!(
+            ConflictIndex::new(&mempool(chain([1000, 5000, 1000]))).conflict_sets(),
+            vec![vec![txid(1), txid(2), txid(3)]]
+        );
+        // the middle transaction replaces both ends
+        assert_eq!(resolve(chain([1000, 5000, 1000])), vec![txid(2)]);
+        // an end replaces the middle, the other end has no conflict left
+        assert_eq!(resolve(chain([3000, 1000, 3000])), vec![txid(1), txid(3)]);
+
+        // descendants of a replaced transaction are evicted with it
+        let mut transactions = chain([1000, 5000, 1000]);
+        let vin = vec![new_input(&txid(1), 0, FINAL)];
+        transactions.push(mempool_tx(&txid(4), vin, 100, WEIGHT, 0));
+        assert_eq!(resolve(transactions), vec![txid(2)]);
+    }
+}
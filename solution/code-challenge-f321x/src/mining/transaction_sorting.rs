@@ -1,103 +1,93 @@
+use super::block_assembler::{AncestorScore, RESERVED_SIGOPS_COST};
+use super::block_weight::BlockWeightBudget;
+use super::packet_weight::MempoolRelatives;
 use crate::parsing::transaction_structs::Transaction;
-use std::collections::HashMap;
+use crate::validation::sigops::MAX_BLOCK_SIGOPS_COST;
+use std::collections::{BinaryHeap, HashMap, HashSet};
 
-// returns the index of txid in Vec<Transaction> transactions.
-fn get_parent_index(transactions: &Vec<Transaction>, txid: &String) -> usize {
-    let mut parent_index: usize = 0;
-
-    for tx in transactions {
-        if *tx.meta.txid_hex == *txid {
-            break;
-        };
-        parent_index += 1;
-    }
-    parent_index
-}
+// entry function for sorting. Orders the transactions by packet feerate (fee per sigop-adjusted
+// vsize) with all parents in front of their children: Kahn's algorithm taking the ready
+// transaction (all parents sorted) with the highest priority next. The priority of a transaction is the highest packet feerate
+// of itself and its descendants, so parents are pulled in front by their best child.
+pub fn sort_transactions(txid_tx_map: &HashMap<String, Transaction>) -> Vec<Transaction> {
+    let relatives = MempoolRelatives::new(txid_tx_map);
+    let transactions = relatives.indexed_transactions(txid_tx_map);
 
-// gets called by put_parents_in_front to take the Transaction at parent_index and put it in front of
-// child_index
-fn push_parent_in_front(
-    transactions: &mut Vec<Transaction>,
-    parent_index: usize,
-    child_index: usize,
-) {
-    if parent_index < transactions.len() && child_index < transactions.len() {
-        let parent = transactions.remove(parent_index);
-        transactions.insert(child_index, parent);
+    // best packet (fee, sigop-adjusted vsize) of each transaction and its descendants,
+    // children first
+    let mut priorities: Vec<(u64, u64)> = transactions
+        .iter()
+        .map(|tx| {
+            let packet = &tx.meta.packet_data;
+            (packet.packet_fee_sat, packet.packet_vsize)
+        })
+        .collect();
+    for &index in relatives.topological_order().iter().rev() {
+        for &child in relatives.child_indices(index) {
+            let (fee, size) = priorities[index];
+            let (child_fee, child_size) = priorities[child];
+            if child_fee as u128 * size as u128 > fee as u128 * child_size as u128 {
+                priorities[index] = priorities[child];
+            }
+        }
     }
-}
-
-// puts parents in front of their children in the presorted Vec<Transaction>
-fn put_parents_in_front(presorted: &mut Vec<Transaction>) {
-    let mut nothing_changed: bool = false;
+    let score = |index: usize| AncestorScore {
+        fee: priorities[index].0,
+        size: priorities[index].1,
+        index,
+    };
 
-    'outer: while !nothing_changed {
-        nothing_changed = true;
-        let mut tx_index: usize = 0;
+    let mut missing_parents: Vec<usize> = (0..transactions.len())
+        .map(|index| relatives.parent_indices(index).len())
+        .collect();
+    let mut ready: BinaryHeap<AncestorScore> = (0..transactions.len())
+        .filter(|&index| missing_parents[index] == 0)
+        .map(score)
+        .collect();
+    let mut sorted_transactions: Vec<Transaction> = Vec::with_capacity(transactions.len());
 
-        let transactions_cloned = presorted.clone();
-        for tx in transactions_cloned.iter() {
-            if let Some(parents) = tx.meta.parents.as_ref() {
-                for parent_txid in parents {
-                    let parent_index = get_parent_index(presorted, parent_txid);
-                    if parent_index > tx_index {
-                        push_parent_in_front(presorted, parent_index, tx_index);
-                        nothing_changed = false;
-                        continue 'outer;
-                    };
-                }
-            };
-            tx_index += 1;
+    while let Some(best) = ready.pop() {
+        sorted_transactions.push(transactions[best.index].clone());
+        for &child in relatives.child_indices(best.index) {
+            missing_parents[child] -= 1;
+            if missing_parents[child] == 0 {
+                ready.push(score(child));
+            }
         }
     }
-}
-
-// entry function for sorting. sorts by packet feerate, then puts the parents in front
-// of the children
-pub fn sort_transactions(txid_tx_map: &HashMap<String, Transaction>) -> Vec<Transaction> {
-    let mut transactions: Vec<&Transaction> = txid_tx_map.values().collect();
-    transactions.sort_by(|a, b: &&Transaction| {
-        b.meta
-            .packet_data
-            .packet_feerate_weight
-            .cmp(&a.meta.packet_data.packet_feerate_weight)
-    });
-
-    let mut sorted_transactions: Vec<Transaction> = transactions.into_iter().cloned().collect();
-    put_parents_in_front(&mut sorted_transactions);
-    // validate_sorting(&sorted_transactions);  // call to validation function for testing
     sorted_transactions
 }
 
 // removes enough Transactions from the sorted Vec<Transaction> to respect the
-// block size limit of 4 000 000 weight units
-pub fn cut_size(sorted_transactions: Vec<Transaction>) -> Vec<Transaction> {
+// block size limit of 4 000 000 weight units (including header and coinbase) and the
+// block sigop cost limit of 80 000 (including the coinbase reserve).
+// Transactions that don't fit are skipped and the following, smaller ones are still
+// added if they fit and all their parents are already in the block (tail filling)
+pub fn cut_size(
+    sorted_transactions: Vec<Transaction>,
+    budget: &BlockWeightBudget,
+) -> Vec<Transaction> {
     let mut block: Vec<Transaction> = Vec::new();
-    let mut free_block_space: i64 = 3970000;
+    let mut included_txids: HashSet<String> = HashSet::new();
+    let mut transactions_weight: u64 = 0;
+    let mut block_sigops_cost: u64 = RESERVED_SIGOPS_COST;
     for tx in sorted_transactions {
-        if free_block_space > tx.meta.weight as i64 {
-            free_block_space -= tx.meta.weight as i64;
+        let parents_included = tx
+            .meta
+            .parents
+            .iter()
+            .flatten()
+            .all(|parent_txid| included_txids.contains(parent_txid));
+
+        if parents_included
+            && budget.fits(transactions_weight + tx.meta.weight, block.len() + 1)
+            && block_sigops_cost + tx.meta.sigops_cost < MAX_BLOCK_SIGOPS_COST
+        {
+            transactions_weight += tx.meta.weight;
+            block_sigops_cost += tx.meta.sigops_cost;
+            included_txids.insert(tx.meta.txid_hex.clone());
             block.push(tx);
-        } else {
-            break;
         };
     }
     block
 }
-
-// Function to validate that no child occurs before its parents.
-// pub fn validate_sorting(sorted_transactions: &Vec<Transaction>) -> () {
-//     let mut index = 0;
-
-//     for tx in sorted_transactions {
-//         if let Some(parents_txids) = tx.meta.parents.as_ref() {
-//             for parent in parents_txids {
-//                 let parent_index = get_parent_index(sorted_transactions, parent);
-//                 if parent_index >= index {
-//                     panic!("Parent after child!");
-//                 }
-//             }
-//         };
-//         index += 1;
-//     }
-// }
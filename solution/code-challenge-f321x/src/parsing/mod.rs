@@ -1,3 +1,4 @@
+pub mod raw_transaction;
 pub mod transaction_structs;
 
 use self::transaction_structs::{InputType, Transaction};
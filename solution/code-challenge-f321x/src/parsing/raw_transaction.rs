@@ -0,0 +1,252 @@
+// Deserializer for transactions and blocks in the consensus (raw hex) format, legacy and
+// segwit serialization. Raw transactions don't contain the spent outputs like the mempool json,
+// so the prevouts are looked up in a UtxoSet supplied by the caller.
+
+use super::transaction_structs::{InputType, Script, Transaction, TxIn, TxOut};
+use crate::validation::opcodes::script_to_asm;
+use crate::validation::utils::decode_varint;
+use crate::validation::validate_parsing::validate_txid_hash_filename;
+use std::collections::HashMap;
+
+// spent outputs by outpoint (txid as hex, output index)
+pub type UtxoSet = HashMap<(String, u32), TxOut>;
+
+// returns: type of the scriptpubkey as named in the mempool json (e.g. "v0_p2wpkh")
+pub fn get_scriptpubkey_type(scriptpubkey: &[u8]) -> &'static str {
+    match scriptpubkey {
+        [0x76, 0xa9, 0x14, .., 0x88, 0xac] if scriptpubkey.len() == 25 => "p2pkh",
+        [0xa9, 0x14, .., 0x87] if scriptpubkey.len() == 23 => "p2sh",
+        [0x00, 0x14, ..] if scriptpubkey.len() == 22 => "v0_p2wpkh",
+        [0x00, 0x20, ..] if scriptpubkey.len() == 34 => "v0_p2wsh",
+        [0x51, 0x20, ..] if scriptpubkey.len() == 34 => "v1_p2tr",
+        [0x21, .., 0xac] if scriptpubkey.len() == 35 => "p2pk",
+        [0x41, .., 0xac] if scriptpubkey.len() == 67 => "p2pk",
+        [0x6a, ..] => "op_return",
+        _ => "unknown",
+    }
+}
+
+// creates a TxOut of the scriptpubkey and value with asm and type filled in like in the
+// mempool json. Also used to build the UtxoSet entries of the spent outputs.
+// returns: TxOut
+pub fn new_txout(scriptpubkey: &[u8], value: u64) -> TxOut {
+    TxOut {
+        scriptpubkey: Some(hex::encode(scriptpubkey)).filter(|hex| !hex.is_empty()),
+        scriptpubkey_asm: script_to_asm(scriptpubkey).unwrap_or_default(),
+        scriptpubkey_type: get_scriptpubkey_type(scriptpubkey).to_string(),
+        scriptpubkey_address: None,
+        value,
+    }
+}
+
+// returns: prevout Script of a TxIn holding the spent output
+fn prevout_from_utxo(utxo: &TxOut) -> Script {
+    Script {
+        scriptpubkey: utxo.scriptpubkey.clone().unwrap_or_default(),
+        scriptpubkey_asm: utxo.scriptpubkey_asm.clone(),
+        scriptpubkey_type: utxo.scriptpubkey_type.clone(),
+        scriptpubkey_address: utxo.scriptpubkey_address.clone(),
+        value: utxo.value,
+    }
+}
+
+// cursor reading the fields of a raw transaction
+struct RawTxReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl RawTxReader<'_> {
+    // returns: the next len bytes or Err if the transaction ends before
+    fn read(&mut self, len: usize) -> Result<&[u8], String> {
+        if len > self.bytes.len() - self.position {
+            return Err("Raw transaction ends unexpectedly".to_string());
+        }
+        self.position += len;
+        Ok(&self.bytes[self.position - len..self.position])
+    }
+
+    fn read_u32(&mut self) -> Result<u32, String> {
+        let bytes = self.read(4)?;
+        Ok(u32::from_le_bytes(bytes.try_into().expect("Read 4 bytes")))
+    }
+
+    fn read_u64(&mut self) -> Result<u64, String> {
+        let bytes = self.read(8)?;
+        Ok(u64::from_le_bytes(bytes.try_into().expect("Read 8 bytes")))
+    }
+
+    fn read_varint(&mut self) -> Result<u64, String> {
+        let (value, len) = decode_varint(&self.bytes[self.position..])?;
+        self.position += len;
+        Ok(value)
+    }
+
+    // returns: the varint length prefixed bytes
+    fn read_var_bytes(&mut self) -> Result<Vec<u8>, String> {
+        let len = self.read_varint()?;
+        match usize::try_from(len) {
+            Ok(len) => Ok(self.read(len)?.to_vec()),
+            Err(_) => Err("Raw transaction ends unexpectedly".to_string()),
+        }
+    }
+}
+
+// reads an input, the prevout is filled in by fill_prevouts afterwards
+// returns: TxIn or Err(reason)
+fn read_input(reader: &mut RawTxReader) -> Result<TxIn, String> {
+    let txid: Vec<u8> = reader.read(32)?.iter().rev().copied().collect();
+    let txid = hex::encode(txid);
+    let vout = reader.read_u32()?;
+    let scriptsig = reader.read_var_bytes()?;
+    let sequence = reader.read_u32()?;
+    let is_coinbase = vout == u32::MAX && txid.bytes().all(|digit| digit == b'0');
+
+    Ok(TxIn {
+        in_type: InputType::default(),
+        txid,
+        vout,
+        scriptsig: Some(hex::encode(&scriptsig)).filter(|hex| !hex.is_empty()),
+        scriptsig_asm: script_to_asm(&scriptsig).ok().filter(|asm| !asm.is_empty()),
+        prevout: prevout_from_utxo(&new_txout(&[], 0)),
+        witness: None,
+        inner_witnessscript_asm: None,
+        inner_redeemscript_asm: None,
+        is_coinbase,
+        sequence,
+    })
+}
+
+// fills the prevouts of the inputs from the utxo set (coinbase inputs spend nothing)
+// and sets the input types
+// returns: Ok(()) or Err(reason) if a spent output is not in the utxo set
+fn fill_prevouts(tx: &mut Transaction, utxos: &UtxoSet) -> Result<(), String> {
+    for txin in &mut tx.vin {
+        match utxos.get(&(txin.txid.clone(), txin.vout)) {
+            Some(utxo) => txin.prevout = prevout_from_utxo(utxo),
+            None if txin.is_coinbase => {}
+            None => {
+                return Err(format!(
+                    "Prevout {}:{} not in utxo set",
+                    txin.txid, txin.vout
+                ))
+            }
+        }
+        InputType::fetch_type(txin);
+    }
+    Ok(())
+}
+
+// reads the varint count and the inputs or outputs following it
+// returns: Vec of the read elements or Err(reason)
+fn read_vector<T>(
+    reader: &mut RawTxReader,
+    mut read_element: impl FnMut(&mut RawTxReader) -> Result<T, String>,
+) -> Result<Vec<T>, String> {
+    let count = reader.read_varint()?;
+    let mut elements: Vec<T> = Vec::new();
+
+    for _ in 0..count {
+        elements.push(read_element(reader)?);
+    }
+    Ok(elements)
+}
+
+// reads a transaction in consensus format. The segwit serialization is detected by the
+// marker (0x00, would be an empty input list) and flag (0x01) after the version.
+// returns: Transaction or Err(reason)
+fn read_transaction(reader: &mut RawTxReader) -> Result<Transaction, String> {
+    let version = reader.read_u32()? as i32;
+    let mut vin = read_vector(reader, read_input)?;
+    let mut flag = 0;
+    if vin.is_empty() {
+        flag = reader.read(1)?[0];
+        if flag != 0 {
+            vin = read_vector(reader, read_input)?;
+        }
+    }
+    let vout = read_vector(reader, |reader| {
+        let value = reader.read_u64()?;
+        Ok(new_txout(&reader.read_var_bytes()?, value))
+    })?;
+    if flag & 0x01 != 0 {
+        flag ^= 0x01;
+        for txin in &mut vin {
+            let witness = read_vector(reader, |reader| Ok(hex::encode(reader.read_var_bytes()?)))?;
+            txin.witness = Some(witness).filter(|witness| !witness.is_empty());
+        }
+        if vin.iter().all(|txin| txin.witness.is_none()) {
+            return Err("Superfluous witness record".to_string());
+        }
+    }
+    if flag != 0 {
+        return Err("Unknown transaction optional data".to_string());
+    }
+    let locktime = reader.read_u32()?;
+    Ok(Transaction {
+        meta: Default::default(),
+        version,
+        locktime,
+        vin,
+        vout,
+    })
+}
+
+// Deserializes a transaction in consensus format (legacy or segwit serialization).
+// The prevouts of the inputs are looked up in the utxo set.
+// returns: Transaction or Err(reason) if the bytes are no valid transaction
+pub fn deserialize_transaction(raw: &[u8], utxos: &UtxoSet) -> Result<Transaction, String> {
+    let mut reader = RawTxReader {
+        bytes: raw,
+        position: 0,
+    };
+    let mut tx = read_transaction(&mut reader)?;
+    if reader.position != raw.len() {
+        return Err("Data after the end of the transaction".to_string());
+    }
+    fill_prevouts(&mut tx, utxos)?;
+    Ok(tx)
+}
+
+// deserializes a transaction given as hex String (e.g. from getrawtransaction)
+// returns: Transaction or Err(reason)
+pub fn deserialize_transaction_hex(raw_hex: &str, utxos: &UtxoSet) -> Result<Transaction, String> {
+    match hex::decode(raw_hex.trim()) {
+        Ok(raw) => deserialize_transaction(&raw, utxos),
+        Err(err) => Err(format!("Raw transaction hex invalid: {}", err)),
+    }
+}
+
+// Deserializes a block: 80 byte header, varint transaction count and the transactions.
+// The prevouts of the inputs are looked up in the utxo set and the outputs of all
+// transactions of the block, the order of parents and children is checked by validate_block.
+// returns: (header bytes, transactions in block order with txids set) or Err(reason)
+pub fn deserialize_block(
+    raw: &[u8],
+    utxos: &UtxoSet,
+) -> Result<(Vec<u8>, Vec<Transaction>), String> {
+    let mut reader = RawTxReader {
+        bytes: raw,
+        position: 0,
+    };
+    let header = reader.read(80)?.to_vec();
+    let mut transactions = read_vector(&mut reader, |reader| {
+        let mut tx = read_transaction(reader)?;
+        validate_txid_hash_filename(&mut tx)?; // sets txid and wtxid
+        Ok(tx)
+    })?;
+    if reader.position != raw.len() {
+        return Err("Data after the end of the block".to_string());
+    }
+
+    let mut utxos = utxos.clone();
+    for tx in &transactions {
+        for (vout, txout) in tx.vout.iter().enumerate() {
+            utxos.insert((tx.meta.txid_hex.clone(), vout as u32), txout.clone());
+        }
+    }
+    for tx in &mut transactions {
+        fill_prevouts(tx, &utxos)?;
+    }
+    Ok((header, transactions))
+}
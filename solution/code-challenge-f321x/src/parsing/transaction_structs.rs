@@ -1,6 +1,8 @@
 // Definition of data structures to hold a bitcoin transaction and relevant metadata
 
+use crate::validation::sigops::get_sigop_adjusted_vsize;
 use crate::validation::utils::{get_outpoint, varint};
+use crate::validation::validate_parsing::serialize_output;
 use serde::Deserialize;
 use serde_with::{serde_as, NoneAsEmptyString};
 
@@ -48,6 +50,8 @@ pub struct Packet {
     pub packet_weight: u64,
     pub packet_fee_sat: u64,
     pub packet_feerate_weight: u64, // sat/weight_unit
+    pub packet_sigops_cost: u64,
+    pub packet_vsize: u64, // summed up sigop-adjusted vsize, used for feerate ranking
 }
 
 #[derive(Default, Debug, Clone)]
@@ -57,6 +61,7 @@ pub struct TxMetadata {
     pub wtxid_hex: String,
     pub packet_data: Packet,
     pub weight: u64,
+    pub sigops_cost: u64,
     pub fee: u64,
     pub parents: Option<Vec<String>>,
 }
@@ -73,6 +78,12 @@ pub struct Transaction {
 }
 
 impl Transaction {
+    // returns: vsize of the transaction, increased if it has many sigops compared to its weight
+    // (weight and sigop cost have to be set by the validation)
+    pub fn sigop_adjusted_vsize(&self) -> u64 {
+        get_sigop_adjusted_vsize(self.meta.weight, self.meta.sigops_cost)
+    }
+
     // return Vec<u8> of all sequences in little endian byte format
     pub fn serialize_all_sequences(&self) -> Vec<u8> {
         let mut all_sequences = Vec::new();
@@ -82,28 +93,47 @@ impl Transaction {
         all_sequences
     }
 
-    // return Vec<u8> of all outpoints of referenced Transaction
-    pub fn serialize_all_outpoints(&self) -> Vec<u8> {
+    // return Vec<u8> of all outpoints of referenced Transaction,
+    // or Err(reason) if an outpoint txid hex is invalid
+    pub fn serialize_all_outpoints(&self) -> Result<Vec<u8>, String> {
         let mut all_outpoints = Vec::new();
         for input in &self.vin {
-            all_outpoints.extend(get_outpoint(input));
+            all_outpoints.extend(get_outpoint(input)?);
+        }
+        Ok(all_outpoints)
+    }
+
+    // return Vec<u8> of all spent prevout amounts in little endian byte format
+    pub fn serialize_all_amounts(&self) -> Vec<u8> {
+        let mut all_amounts = Vec::new();
+        for input in &self.vin {
+            all_amounts.extend(input.prevout.value.to_le_bytes());
+        }
+        all_amounts
+    }
+
+    // return Vec<u8> of all spent prevout scriptpubkeys, each prefixed with its length as varint,
+    // or Err(reason) if a prevout scriptpubkey hex is invalid
+    pub fn serialize_all_scriptpubkeys(&self) -> Result<Vec<u8>, String> {
+        let mut all_scriptpubkeys = Vec::new();
+        for input in &self.vin {
+            let scriptpubkey = hex::decode(&input.prevout.scriptpubkey)
+                .map_err(|err| format!("Prevout scriptpubkey hex invalid: {}", err))?;
+            all_scriptpubkeys.extend(varint(scriptpubkey.len() as u128));
+            all_scriptpubkeys.extend(scriptpubkey);
         }
-        all_outpoints
+        Ok(all_scriptpubkeys)
     }
 
     // return all outputs of Transaction serialized as Vec<u8>
-    pub fn serialize_all_outputs(&self) -> Vec<u8> {
+    // (outputs without scriptpubkey are serialized with an empty script),
+    // or Err(reason) if an output scriptpubkey hex is invalid
+    pub fn serialize_all_outputs(&self) -> Result<Vec<u8>, String> {
         let mut all_outputs = Vec::new();
         for output in &self.vout {
-            all_outputs.extend(&output.value.to_le_bytes());
-            if let Some(scriptpubkey) = &output.scriptpubkey {
-                all_outputs.extend(varint(hex::decode(scriptpubkey).unwrap().len() as u128));
-                all_outputs.extend(hex::decode(scriptpubkey).unwrap());
-            } else {
-                panic!("No scriptpubkey in output!");
-            }
+            all_outputs.extend(serialize_output(output)?);
         }
-        all_outputs
+        Ok(all_outputs)
     }
 }
 
@@ -0,0 +1,121 @@
+// Hand-built transactions for the unit tests. The inputs only carry the spent outpoint and
+// the sequence, fee, weight and sigop cost are set directly instead of being calculated by
+// the validation.
+
+use crate::parsing::transaction_structs::{InputType, Script, Transaction, TxIn};
+use std::collections::HashMap;
+
+// returns: 64 character txid hex of the number
+pub fn txid(number: u64) -> String {
+    format!("{:064x}", number)
+}
+
+// returns: input spending the outpoint with the sequence
+pub fn new_input(txid: &str, vout: u32, sequence: u32) -> TxIn {
+    TxIn {
+        in_type: InputType::default(),
+        txid: txid.to_string(),
+        vout,
+        scriptsig: None,
+        scriptsig_asm: None,
+        prevout: Script {
+            scriptpubkey: "51".to_string(),
+            scriptpubkey_asm: "OP_PUSHNUM_1".to_string(),
+            scriptpubkey_type: "unknown".to_string(),
+            scriptpubkey_address: None,
+            value: 0,
+        },
+        witness: None,
+        inner_witnessscript_asm: None,
+        inner_redeemscript_asm: None,
+        is_coinbase: false,
+        sequence,
+    }
+}
+
+// returns: transaction with the inputs and no outputs
+pub fn new_tx(version: i32, locktime: u32, vin: Vec<TxIn>) -> Transaction {
+    Transaction {
+        meta: Default::default(),
+        version,
+        locktime,
+        vin,
+        vout: Vec::new(),
+    }
+}
+
+// returns: version 2 mempool transaction with txid, fee, weight and sigop cost set
+pub fn mempool_tx(
+    txid: &str,
+    vin: Vec<TxIn>,
+    fee: u64,
+    weight: u64,
+    sigops_cost: u64,
+) -> Transaction {
+    let mut tx = new_tx(2, 0, vin);
+    tx.meta.txid_hex = txid.to_string();
+    tx.meta.fee = fee;
+    tx.meta.weight = weight;
+    tx.meta.sigops_cost = sigops_cost;
+    tx
+}
+
+// returns: mempool transaction number spending output 0 of each parent number, a transaction
+// without parents spends a confirmed output
+pub fn package_tx(
+    number: u64,
+    parents: &[u64],
+    fee: u64,
+    weight: u64,
+    sigops_cost: u64,
+) -> Transaction {
+    let vin = match parents {
+        [] => vec![new_input(&txid(1000 + number), 0, u32::MAX)],
+        _ => parents
+            .iter()
+            .map(|parent| new_input(&txid(*parent), 0, u32::MAX))
+            .collect(),
+    };
+    mempool_tx(&txid(number), vin, fee, weight, sigops_cost)
+}
+
+// collects the transactions by txid and assigns the parents in the mempool like
+// assign_mempool_parents
+// returns: HashMap txid hex -> Transaction
+pub fn mempool(transactions: Vec<Transaction>) -> HashMap<String, Transaction> {
+    let mut txid_tx_map: HashMap<String, Transaction> = transactions
+        .into_iter()
+        .map(|tx| (tx.meta.txid_hex.clone(), tx))
+        .collect();
+    let txids: Vec<String> = txid_tx_map.keys().cloned().collect();
+
+    for txid in txids {
+        let parents: Vec<String> = txid_tx_map[&txid]
+            .vin
+            .iter()
+            .filter(|txin| txid_tx_map.contains_key(&txin.txid))
+            .map(|txin| txin.txid.clone())
+            .collect();
+        if !parents.is_empty() {
+            txid_tx_map.get_mut(&txid).unwrap().meta.parents = Some(parents);
+        }
+    }
+    txid_tx_map
+}
+
+// returns: txids of the transactions in block order
+pub fn block_txids(block: &[Transaction]) -> Vec<String> {
+    block.iter().map(|tx| tx.meta.txid_hex.clone()).collect()
+}
+
+// returns: true if all mempool parents of each block transaction are in the block before it
+pub fn parents_first(block: &[Transaction]) -> bool {
+    let txids = block_txids(block);
+    block.iter().enumerate().all(|(position, tx)| {
+        tx.meta
+            .parents
+            .iter()
+            .flatten()
+            .all(|parent| txids[..position].contains(parent))
+    })
+}
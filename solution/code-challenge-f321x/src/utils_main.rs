@@ -1,4 +1,4 @@
-use crate::parsing::transaction_structs::Transaction;
+use code_challenge_f321x::parsing::transaction_structs::Transaction;
 use std::collections::{HashMap, HashSet};
 
 // Converts a Vec<Transaction> to HashMap<hex txid Sting, Transaction>
@@ -0,0 +1,281 @@
+use super::ValidationResult;
+use crate::parsing::transaction_structs::Transaction;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+
+// Transaction finality like IsFinalTx and the BIP68 sequence locks of bitcoin core,
+// evaluated against the height and median time past of the block template. Without a known
+// median time past time based locks count as not final (the current time would be later than
+// the median time past and accept locks that aren't reached yet, BIP113).
+
+const LOCKTIME_THRESHOLD: u32 = 500000000; // below block height, above unix time
+const SEQUENCE_FINAL: u32 = 0xffffffff;
+const SEQUENCE_LOCKTIME_DISABLE_FLAG: u32 = 1 << 31;
+const SEQUENCE_LOCKTIME_TYPE_FLAG: u32 = 1 << 22;
+const SEQUENCE_LOCKTIME_MASK: u32 = 0x0000ffff;
+const SEQUENCE_LOCKTIME_GRANULARITY: u32 = 9; // time based relative locks count 512 seconds
+
+// block that confirmed a spent output, the median time past is the one of the block
+// before the confirming block (as used by BIP68)
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct CoinConfirmation {
+    pub height: u64,
+    pub median_time_past: u32,
+}
+
+// confirmations of the spent outputs by outpoint (txid hex, vout)
+pub type UtxoConfirmations = HashMap<(String, u32), CoinConfirmation>;
+
+// entry of the utxo confirmations json file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct UtxoConfirmationEntry {
+    txid: String,
+    vout: u32,
+    height: u64,
+    median_time_past: u32,
+}
+
+// loads the confirmations from a json array of
+// {"txid": hex, "vout": n, "height": n, "median_time_past": unix time} objects
+// returns: UtxoConfirmations or Err(reason)
+pub fn load_utxo_confirmations(path: &str) -> Result<UtxoConfirmations, String> {
+    let content =
+        fs::read_to_string(path).map_err(|err| format!("Reading {} failed: {}", path, err))?;
+    let entries: Vec<UtxoConfirmationEntry> =
+        serde_json::from_str(&content).map_err(|err| format!("{}: {}", path, err))?;
+
+    Ok(entries
+        .into_iter()
+        .map(|entry| {
+            let confirmation = CoinConfirmation {
+                height: entry.height,
+                median_time_past: entry.median_time_past,
+            };
+            ((entry.txid, entry.vout), confirmation)
+        })
+        .collect())
+}
+
+// checks the absolute locktime (IsFinalTx). The locktime is a block height below 500000000
+// and a unix time above, it is ignored if all inputs have a final sequence.
+// returns: true if the transaction can be included in a block of the passed height and time
+// (a time based locktime is not reached if the block time is unknown)
+pub fn is_final_tx(tx: &Transaction, block_height: u64, block_time: Option<u32>) -> bool {
+    if tx.locktime == 0 {
+        return true;
+    }
+    let lock_reached = if tx.locktime < LOCKTIME_THRESHOLD {
+        (tx.locktime as u64) < block_height
+    } else {
+        block_time.is_some_and(|block_time| tx.locktime < block_time)
+    };
+    lock_reached || tx.vin.iter().all(|txin| txin.sequence == SEQUENCE_FINAL)
+}
+
+// checks the relative locks of the inputs (BIP68, CalculateSequenceLocks + EvaluateSequenceLocks)
+// of version 2+ transactions. Outputs of mempool transactions count as confirmed in the
+// template block. Inputs with a relative lock of 0 are always final, for the other inputs
+// the confirmation has to be known from the utxo confirmations, for time based locks also
+// the median time past.
+// returns: ValidationResult
+pub fn check_sequence_locks(
+    tx: &Transaction,
+    block_height: u64,
+    median_time_past: Option<u32>,
+    utxo_confirmations: &UtxoConfirmations,
+    mempool: &HashMap<String, Transaction>,
+) -> ValidationResult {
+    // the version is compared unsigned like in bitcoin core, negative versions are >= 2
+    if (tx.version as u32) < 2 {
+        return ValidationResult::Valid;
+    }
+    // last height and time at which the transaction is still locked
+    let mut min_height: i64 = -1;
+    let mut min_time: i64 = -1;
+
+    for txin in &tx.vin {
+        if txin.sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG != 0 {
+            continue;
+        }
+        let lock = (txin.sequence & SEQUENCE_LOCKTIME_MASK) as i64;
+        if lock == 0 {
+            continue;
+        }
+        let is_time_lock = txin.sequence & SEQUENCE_LOCKTIME_TYPE_FLAG != 0;
+        if is_time_lock && median_time_past.is_none() {
+            return ValidationResult::Invalid("non-BIP68-final".to_string());
+        }
+        let coin = if mempool.contains_key(&txin.txid) {
+            CoinConfirmation {
+                height: block_height,
+                median_time_past: median_time_past.unwrap_or_default(),
+            }
+        } else {
+            match utxo_confirmations.get(&(txin.txid.clone(), txin.vout)) {
+                Some(coin) => *coin,
+                None => {
+                    return ValidationResult::Invalid(format!(
+                        "Confirmation of {}:{} unknown for relative lock",
+                        txin.txid, txin.vout
+                    ))
+                }
+            }
+        };
+
+        if is_time_lock {
+            let lock_time = coin.median_time_past as i64 + (lock << SEQUENCE_LOCKTIME_GRANULARITY);
+            min_time = min_time.max(lock_time - 1);
+        } else {
+            min_height = min_height.max(coin.height as i64 + lock - 1);
+        }
+    }
+
+    if min_height >= block_height as i64
+        || median_time_past.is_some_and(|median_time_past| min_time >= median_time_past as i64)
+    {
+        return ValidationResult::Invalid("non-BIP68-final".to_string());
+    }
+    ValidationResult::Valid
+}
+
+// checks the absolute locktime against the median time past (BIP113) and the
+// relative locks of the inputs
+// returns: ValidationResult
+pub fn check_finality(
+    tx: &Transaction,
+    block_height: u64,
+    median_time_past: Option<u32>,
+    utxo_confirmations: &UtxoConfirmations,
+    mempool: &HashMap<String, Transaction>,
+) -> ValidationResult {
+    if !is_final_tx(tx, block_height, median_time_past) {
+        return ValidationResult::Invalid("bad-txns-nonfinal".to_string());
+    }
+    check_sequence_locks(
+        tx,
+        block_height,
+        median_time_past,
+        utxo_confirmations,
+        mempool,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::new_input;
+
+    const HEIGHT: u64 = 800_000;
+    const MTP: u32 = 1_700_000_000;
+    const PARENT_TXID: &str = "1111111111111111111111111111111111111111111111111111111111111111";
+
+    // returns: transaction spending the outputs 0, 1, ... of the parent with the passed sequences
+    fn new_tx(version: i32, locktime: u32, sequences: &[u32]) -> Transaction {
+        let vin = sequences
+            .iter()
+            .enumerate()
+            .map(|(vout, sequence)| new_input(PARENT_TXID, vout as u32, *sequence))
+            .collect();
+        crate::test_utils::new_tx(version, locktime, vin)
+    }
+
+    // returns: confirmations of the spent outputs, all confirmed at the passed height and time
+    fn confirmations(inputs: u32, height: u64, median_time_past: u32) -> UtxoConfirmations {
+        (0..inputs)
+            .map(|vout| {
+                let coin = CoinConfirmation {
+                    height,
+                    median_time_past,
+                };
+                ((PARENT_TXID.to_string(), vout), coin)
+            })
+            .collect()
+    }
+
+    // returns: true if the relative locks are reached in the block at HEIGHT
+    fn sequence_locks(
+        tx: &Transaction,
+        median_time_past: Option<u32>,
+        coins: &UtxoConfirmations,
+    ) -> bool {
+        matches!(
+            check_sequence_locks(tx, HEIGHT, median_time_past, coins, &HashMap::new()),
+            ValidationResult::Valid
+        )
+    }
+
+    #[test]
+    fn height_locktime() {
+        let locked = |locktime| new_tx(1, locktime, &[0]);
+        assert!(is_final_tx(&locked(0), HEIGHT, None));
+        assert!(is_final_tx(&locked(HEIGHT as u32 - 1), HEIGHT, None));
+        assert!(!is_final_tx(&locked(HEIGHT as u32), HEIGHT, None));
+        // final sequences disable the locktime
+        assert!(is_final_tx(
+            &new_tx(1, HEIGHT as u32, &[SEQUENCE_FINAL]),
+            HEIGHT,
+            None
+        ));
+        assert!(!is_final_tx(
+            &new_tx(1, HEIGHT as u32, &[SEQUENCE_FINAL, 0]),
+            HEIGHT,
+            None
+        ));
+    }
+
+    #[test]
+    fn time_locktime() {
+        let locked = new_tx(1, MTP - 1, &[0]);
+        assert!(is_final_tx(&locked, HEIGHT, Some(MTP)));
+        assert!(!is_final_tx(&new_tx(1, MTP, &[0]), HEIGHT, Some(MTP)));
+        // unknown median time past, a time lock can't be evaluated
+        assert!(!is_final_tx(&locked, HEIGHT, None));
+        assert!(is_final_tx(
+            &new_tx(1, MTP, &[SEQUENCE_FINAL]),
+            HEIGHT,
+            None
+        ));
+    }
+
+    #[test]
+    fn height_sequence_lock() {
+        let tx = new_tx(2, 0, &[10]);
+        assert!(sequence_locks(&tx, None, &confirmations(1, HEIGHT - 10, 0)));
+        assert!(!sequence_locks(&tx, None, &confirmations(1, HEIGHT - 9, 0)));
+        // confirmation of the spent output unknown
+        assert!(!sequence_locks(&tx, None, &UtxoConfirmations::new()));
+    }
+
+    #[test]
+    fn time_sequence_lock() {
+        let tx = new_tx(2, 0, &[SEQUENCE_LOCKTIME_TYPE_FLAG | 2]);
+        let lock_seconds = 2 << SEQUENCE_LOCKTIME_GRANULARITY;
+        let coins = confirmations(1, HEIGHT - 1, MTP - lock_seconds);
+        assert!(sequence_locks(&tx, Some(MTP), &coins));
+        assert!(!sequence_locks(&tx, Some(MTP - 1), &coins));
+        // unknown median time past, the time lock can't be evaluated
+        assert!(!sequence_locks(&tx, None, &coins));
+    }
+
+    #[test]
+    fn disabled_sequence_lock() {
+        let tx = new_tx(2, 0, &[SEQUENCE_LOCKTIME_DISABLE_FLAG | 10]);
+        assert!(sequence_locks(&tx, None, &UtxoConfirmations::new()));
+        // version 1 transactions don't have relative locks
+        assert!(sequence_locks(
+            &new_tx(1, 0, &[10]),
+            None,
+            &UtxoConfirmations::new()
+        ));
+    }
+
+    #[test]
+    fn negative_version_sequence_lock() {
+        // 0xffffffff is version 4294967295 for bip68
+        let tx = new_tx(-1, 0, &[10]);
+        assert!(!sequence_locks(&tx, None, &confirmations(1, HEIGHT - 9, 0)));
+        assert!(sequence_locks(&tx, None, &confirmations(1, HEIGHT - 10, 0)));
+    }
+}
@@ -1,13 +1,23 @@
-mod script;
-mod signature_verification;
+pub mod finality;
+pub mod opcodes;
+pub mod script;
+pub mod script_error;
+mod sighash;
+pub mod signature_verification;
+pub mod sigops;
+mod taproot;
 pub mod utils;
+pub mod validate_block;
 pub mod validate_parsing;
 pub mod validate_values;
+pub mod verify_flags;
 pub mod weight_calculation;
 
-use self::signature_verification::{verify_p2pkh, verify_p2wpkh};
+use self::signature_verification::verify_input;
+use self::sigops::validate_and_set_sigops_cost;
 use self::validate_parsing::validate_txid_hash_filename;
-use self::validate_values::{validate_feerate, validate_values_and_set_fee};
+use self::validate_values::{check_transaction, validate_feerate, validate_values_and_set_fee};
+use self::verify_flags::VerifyFlags;
 use self::weight_calculation::validate_and_set_weight;
 use crate::parsing::transaction_structs::{InputType, Transaction};
 
@@ -18,17 +28,27 @@ pub enum ValidationResult {
 
 // Sanity checks to sort out impossible transactions before doing
 // more compute intensive signature verification. Gets called on each Transaction.
-// Also sets weight and fee in the Transaction while calculating it for the checks.
+// Also sets weight, sigop cost and fee in the Transaction while calculating it for the checks.
 // returns: ValidationResult
 fn sanity_checks(tx: &mut Transaction) -> ValidationResult {
-    if !validate_values_and_set_fee(tx) {
-        return ValidationResult::Invalid("Values don't add up.".to_string());
+    if let ValidationResult::Invalid(reason) = check_transaction(tx) {
+        return ValidationResult::Invalid(reason);
     }
-    if !validate_txid_hash_filename(tx) {
-        return ValidationResult::Invalid("Txid does not represent filename!".to_string());
+    if let ValidationResult::Invalid(reason) = validate_values_and_set_fee(tx) {
+        return ValidationResult::Invalid(reason);
     }
-    if !validate_and_set_weight(tx) {
-        return ValidationResult::Invalid("Transaction weight too high!".to_string());
+    match validate_txid_hash_filename(tx) {
+        Ok(true) => {}
+        Ok(false) => {
+            return ValidationResult::Invalid("Txid does not represent filename!".to_string())
+        }
+        Err(reason) => return ValidationResult::Invalid(reason),
+    }
+    if let ValidationResult::Invalid(reason) = validate_and_set_weight(tx) {
+        return ValidationResult::Invalid(reason);
+    }
+    if let ValidationResult::Invalid(reason) = validate_and_set_sigops_cost(tx) {
+        return ValidationResult::Invalid(reason);
     }
     if !validate_feerate(tx) {
         return ValidationResult::Invalid("too low feerate".to_string());
@@ -36,15 +56,18 @@ fn sanity_checks(tx: &mut Transaction) -> ValidationResult {
     ValidationResult::Valid
 }
 
-// takes a transaction and calls the according signature/script verification
-// function on each input. Implemented checks for p2pkh and p2wpkh.
+// takes a transaction and calls the script verification with the flags on each input.
+// Implemented for p2pkh, p2sh (incl. nested segwit), p2wpkh, p2wsh and p2tr.
 // returns: ValidationResult
-fn signature_verification(tx: &Transaction) -> ValidationResult {
-    for txin in &tx.vin {
+fn signature_verification(tx: &Transaction, flags: VerifyFlags) -> ValidationResult {
+    for (input_index, txin) in tx.vin.iter().enumerate() {
         let tx_type = &txin.in_type;
         let result = match tx_type {
-            InputType::P2WPKH => verify_p2wpkh(tx, txin),
-            InputType::P2PKH => verify_p2pkh(tx, txin),
+            InputType::P2WPKH
+            | InputType::P2PKH
+            | InputType::P2SH
+            | InputType::P2WSH
+            | InputType::P2TR => verify_input(tx, input_index, flags),
             _ => {
                 // println!("Unknown type: {:#?}", tx_type);
                 ValidationResult::Invalid("Input type not implemented!".to_string())
@@ -63,14 +86,21 @@ fn signature_verification(tx: &Transaction) -> ValidationResult {
 // implements validate function that does sanity checks and cryptographic verification
 // returns: ValidationResult enum either ::Valid or ::Invalid(reason String)
 impl Transaction {
+    // validates with the standard script verification flags of bitcoin core
     pub fn validate(&mut self) -> ValidationResult {
+        self.validate_with_flags(VerifyFlags::default())
+    }
+
+    // validates with the passed script verification flags,
+    // e.g. VerifyFlags::CONSENSUS to only check consensus rules
+    pub fn validate_with_flags(&mut self, flags: VerifyFlags) -> ValidationResult {
         match sanity_checks(self) {
             ValidationResult::Valid => (),
             ValidationResult::Invalid(msg) => {
                 return ValidationResult::Invalid(msg);
             }
         }
-        match signature_verification(self) {
+        match signature_verification(self, flags) {
             ValidationResult::Valid => (),
             ValidationResult::Invalid(msg) => {
                 return ValidationResult::Invalid(msg);
@@ -1,29 +1,115 @@
 use byteorder::{ByteOrder, LittleEndian};
-use core::panic;
-use hex_literal::hex as hexlit;
 use secp256k1::{ecdsa::Signature, Message, PublicKey};
 use std::collections::VecDeque;
-use std::error::Error;
 
-use super::utils::{decode_num, double_hash, get_outpoint, hash160, hash_sha256, varint};
-use super::validate_parsing::serialize_output;
-use crate::parsing::transaction_structs::{InputType, Transaction, TxIn};
+use super::script_error::ScriptError;
+use super::sighash::{legacy_sighash, segwit_v0_sighash, taproot_sighash};
+use super::taproot::{split_schnorr_signature, verify_schnorr};
+use super::utils::{
+    cast_to_bool, decode_script_num, double_hash, encode_script_num, hash160, hash_ripemd160,
+    hash_sha1, hash_sha256, serialize_push,
+};
+use super::verify_flags::VerifyFlags;
+use crate::parsing::transaction_structs::{Transaction, TxIn};
 
 // Implementation of Script opcodes for use in tx verification
 // The Stack is represented as VecDeque<Vec<u8>>
-// If an opcode returns Err(reason) script execution fails.
+// If an opcode returns Err(ScriptError) script execution fails.
 // Entry is fn evaluate_script()
 
-fn op_swap(stack: &mut VecDeque<Vec<u8>>) -> Result<(), &'static str> {
+pub const MAX_SCRIPT_ELEMENT_SIZE: usize = 520;
+const MAX_STACK_SIZE: usize = 1000;
+const MAX_SCRIPT_SIZE: usize = 10000;
+const MAX_OPS_PER_SCRIPT: usize = 201;
+const MAX_PUBKEYS_PER_MULTISIG: i64 = 20;
+const LOCKTIME_THRESHOLD: i64 = 500000000;
+const SEQUENCE_FINAL: u32 = 0xffffffff;
+const SEQUENCE_LOCKTIME_DISABLE_FLAG: i64 = 1 << 31;
+const SEQUENCE_LOCKTIME_TYPE_FLAG: i64 = 1 << 22;
+const SEQUENCE_LOCKTIME_MASK: i64 = 0x0000ffff;
+const VALIDATION_WEIGHT_PER_SIGOP: i64 = 50;
+
+// rules the script is executed with, depending on where the script comes from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SigVersion {
+    Base,
+    WitnessV0,
+    Tapscript,
+}
+
+// additional data needed to execute a tapscript leaf (BIP342)
+#[derive(Debug, Clone)]
+pub struct TapscriptData {
+    pub leaf_hash: Vec<u8>,
+    pub annex: Option<Vec<u8>>,
+    pub codesep_pos: u32,
+    pub validation_weight_left: i64,
+}
+
+// transaction data and rules the script gets evaluated against.
+// script_code is the executed script from the last OP_CODESEPARATOR on (set by execute_script)
+pub struct ScriptContext<'a> {
+    pub tx: &'a Transaction,
+    pub input_index: usize,
+    pub sig_version: SigVersion,
+    pub flags: VerifyFlags,
+    pub script_code: Vec<u8>,
+    pub tapscript: Option<TapscriptData>,
+}
+
+impl<'a> ScriptContext<'a> {
+    pub fn new(
+        tx: &'a Transaction,
+        input_index: usize,
+        sig_version: SigVersion,
+        flags: VerifyFlags,
+    ) -> Self {
+        ScriptContext {
+            tx,
+            input_index,
+            sig_version,
+            flags,
+            script_code: Vec::new(),
+            tapscript: None,
+        }
+    }
+
+    pub fn txin(&self) -> &'a TxIn {
+        &self.tx.vin[self.input_index]
+    }
+}
+
+// decodes a stack element as script number with at most max_len bytes. With require_minimal
+// (MINIMALDATA) the number must not have unnecessary zero padding (e.g. 0x0100 for 1).
+fn decode_stack_num(
+    element: &[u8],
+    max_len: usize,
+    require_minimal: bool,
+) -> Result<i64, ScriptError> {
+    if require_minimal {
+        if let Some((last, rest)) = element.split_last() {
+            if last & 0x7f == 0
+                && rest
+                    .last()
+                    .is_none_or(|second_last| second_last & 0x80 == 0)
+            {
+                return Err(ScriptError::ScriptNumMinimal);
+            }
+        }
+    }
+    decode_script_num(element, max_len).map_err(|_| ScriptError::ScriptNumOverflow)
+}
+
+fn op_swap(stack: &mut VecDeque<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.len() >= 2 {
         let len = stack.len();
         stack.swap(len - 1, len - 2);
         return Ok(());
     }
-    Err("OP_SWAP stack < 2")
+    Err(ScriptError::InvalidStackOperation)
 }
 
-fn op_equal(stack: &mut VecDeque<Vec<u8>>) -> Result<(), &'static str> {
+fn op_equal(stack: &mut VecDeque<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.len() >= 2 {
         let last = &stack.pop_back().expect("Unwrap op_equal");
         let second_last = &stack.pop_back().expect("OP_Equal");
@@ -32,102 +118,59 @@ fn op_equal(stack: &mut VecDeque<Vec<u8>>) -> Result<(), &'static str> {
             return Ok(());
         }
     } else {
-        return Err("OP_EQUAL stack len < 2");
+        return Err(ScriptError::InvalidStackOperation);
     }
     stack.push_back(Vec::new());
     Ok(())
 }
 
-fn op_rot(stack: &mut VecDeque<Vec<u8>>) -> Result<(), &'static str> {
+fn op_rot(stack: &mut VecDeque<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.len() >= 3 {
         let third_item = stack.pop_back().expect("OP_ROT pop_back");
         let second_item = stack.pop_back().expect("OP_ROT pop_back");
         let first_item = stack.pop_back().expect("OP_ROT pop_back");
         stack.push_back(second_item);
-        stack.push_back(first_item);
         stack.push_back(third_item);
+        stack.push_back(first_item);
         return Ok(());
     }
-    Err("OP_ROT stack len < 3")
+    Err(ScriptError::InvalidStackOperation)
 }
 
-fn op_size(stack: &mut VecDeque<Vec<u8>>) -> Result<(), &'static str> {
-    if !stack.is_empty() {
-        if let Some(last) = stack.back() {
-            let length = last.len();
-            let length_bytes = length.to_le_bytes().to_vec();
-            stack.push_back(length_bytes);
-            return Ok(());
-        } else {
-            return Err("OP_SIZE getting last element failed");
-        }
+fn op_size(stack: &mut VecDeque<Vec<u8>>) -> Result<(), ScriptError> {
+    if let Some(last) = stack.back() {
+        let length_bytes = encode_script_num(last.len() as i64);
+        stack.push_back(length_bytes);
+        return Ok(());
     }
-    Err("OP_SIZE stack empty")
+    Err(ScriptError::InvalidStackOperation)
 }
 
-fn op_over(stack: &mut VecDeque<Vec<u8>>) -> Result<(), &'static str> {
+fn op_over(stack: &mut VecDeque<Vec<u8>>) -> Result<(), ScriptError> {
     let stack_len = stack.len();
     if stack_len >= 2 {
-        if let Some(second_element) = stack.get(stack_len - 2) {
-            stack.push_back(second_element.clone());
-            return Ok(());
-        } else {
-            return Err("OP_OVER getting second element failed");
-        }
-    }
-    Err("OP_OVER stack < 2")
-}
-
-fn op_greaterthan(stack: &mut VecDeque<Vec<u8>>) -> Result<(), &'static str> {
-    let stack_size = stack.len();
-    if stack_size >= 2 {
-        if let Some(b) = stack.pop_back() {
-            if let Some(a) = stack.pop_back() {
-                let a = decode_num(&a);
-                let b = decode_num(&b);
-                if a > b {
-                    stack.push_back(vec![1u8]);
-                } else {
-                    stack.push_back(Vec::new());
-                }
-                return Ok(());
-            } else {
-                return Err("OP_GREATERTHAN second element pop failed");
-            }
-        } else {
-            return Err("OP_GREATERTHAN first element pop failed");
-        }
+        stack.push_back(stack[stack_len - 2].clone());
+        return Ok(());
     }
-    Err("OP_GREATERTHAN stack < 2")
+    Err(ScriptError::InvalidStackOperation)
 }
 
-fn op_equalverify(stack: &mut VecDeque<Vec<u8>>) -> Result<(), &'static str> {
+fn op_equalverify(stack: &mut VecDeque<Vec<u8>>) -> Result<(), ScriptError> {
     op_equal(stack)?;
-    if let Some(bool) = stack.pop_back() {
-        if bool.is_empty() {
-            Err("Equalverify false")
-        } else {
-            Ok(())
-        }
-    } else {
-        Err("OP_EQUALVERIFY stack pop failed")
+    match stack.pop_back() {
+        Some(result) if !result.is_empty() => Ok(()),
+        _ => Err(ScriptError::EqualVerify),
     }
 }
 
-fn op_ifdup(stack: &mut VecDeque<Vec<u8>>) -> Result<(), &'static str> {
-    let length = stack.len();
-    if length < 1 {
-        return Err("OP_IFDUP length < 1");
-    };
-    if let Some(last_item) = stack.get(length - 1) {
-        if last_item.is_empty() {
-            return Ok(());
-        } else {
+fn op_ifdup(stack: &mut VecDeque<Vec<u8>>) -> Result<(), ScriptError> {
+    if let Some(last_item) = stack.back() {
+        if cast_to_bool(last_item) {
             stack.push_back(last_item.clone());
         }
         Ok(())
     } else {
-        Err("OP_IFDUP getting last element failed")
+        Err(ScriptError::InvalidStackOperation)
     }
 }
 
@@ -137,410 +180,987 @@ fn op_checksequenceverify(
     stack: &mut VecDeque<Vec<u8>>,
     txin: &TxIn,
     tx: &Transaction,
-) -> Result<(), &'static str> {
-    let sequence = txin.sequence;
-    let disable_flag = 1 << 31;
-    let locktime_mask = 0x0000ffff;
-    let time_flag = 1 << 22;
-    if stack.is_empty() {
-        return Err("OP_CSV stack empty");
+    require_minimal: bool,
+) -> Result<(), ScriptError> {
+    let stack_sequence = match stack.back() {
+        Some(top_item) => decode_stack_num(top_item, 5, require_minimal)?,
+        None => return Err(ScriptError::InvalidStackOperation),
     };
-
-    if let Some(locktime_element) = stack.pop_back() {
-        let number = decode_num(&locktime_element);
-        if number < 0 || locktime_element.is_empty() {
-            return Err("OP_CSV number < 0 or empty");
-        };
-        let number = number as u32;
-
-        if (number & disable_flag) == 0 {
-            if tx.version < 2 {
-                return Err("OP_CSV Transaction version is less than 2.");
-            };
-            if (sequence & disable_flag) != 0 {
-                return Err("OP_CSV Transaction input sequence number disable flag is set.");
-            };
-            if (number & time_flag) != (sequence & time_flag) {
-                return Err("OP_CSV Relative lock-time types are not the same.");
-            };
-
-            let locktime_sequence = sequence & locktime_mask;
-            let locktime_stack = number & locktime_mask;
-            if locktime_stack > locktime_sequence {
-                return Err("OP_CSV Stack > Sequence LT");
-            };
-        }
-    } else {
-        return Err("OP_CSV time pop from stack failed.");
+    if stack_sequence < 0 {
+        return Err(ScriptError::NegativeLocktime);
+    };
+    if stack_sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG != 0 {
+        return Ok(()); // behaves as OP_NOP3
     }
+    if (tx.version as u32) < 2 {
+        return Err(ScriptError::UnsatisfiedLocktime);
+    };
+    let txin_sequence = txin.sequence as i64;
+    if txin_sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG != 0 {
+        return Err(ScriptError::UnsatisfiedLocktime);
+    };
+    let locktime_mask = SEQUENCE_LOCKTIME_TYPE_FLAG | SEQUENCE_LOCKTIME_MASK;
+    let txin_sequence = txin_sequence & locktime_mask;
+    let stack_sequence = stack_sequence & locktime_mask;
+    if (txin_sequence < SEQUENCE_LOCKTIME_TYPE_FLAG)
+        != (stack_sequence < SEQUENCE_LOCKTIME_TYPE_FLAG)
+    {
+        return Err(ScriptError::UnsatisfiedLocktime);
+    };
+    if stack_sequence > txin_sequence {
+        return Err(ScriptError::UnsatisfiedLocktime);
+    };
     Ok(())
 }
 
+// Marks transaction as invalid if the top stack item is greater than the transaction's
+// nLockTime field or of a different lock time type (BIP 0065).
 fn op_checklocktimeverify(
     stack: &mut VecDeque<Vec<u8>>,
     tx: &Transaction,
     txin: &TxIn,
-) -> Result<(), String> {
-    if stack.is_empty() {
-        return Err("OP_CLTV stack empty".to_string());
+    require_minimal: bool,
+) -> Result<(), ScriptError> {
+    let stack_locktime = match stack.back() {
+        Some(top_item) => decode_stack_num(top_item, 5, require_minimal)?,
+        None => return Err(ScriptError::InvalidStackOperation),
     };
-    if let Some(top_item) = stack.pop_back() {
-        let decoded_number = decode_num(&top_item);
-
-        if decoded_number < 0 {
-            return Err("OP_CLTV number < 0".to_string());
-        };
-        let decoded_number: u32 = decoded_number as u32;
-        if (decoded_number < 500000000 && tx.locktime > 500000000)
-            || (decoded_number > 500000000 && tx.locktime < 500000000)
-        {
-            return Err("OP_CLTV different locktime types".to_string());
-        }
-        if tx.locktime < decoded_number {
-            return Err(format!(
-                "OP_CLTV locktime {} < {} stack num.",
-                tx.locktime, decoded_number
-            ));
-        }
-        if txin.sequence == 0xffffffff {
-            return Err("OP_CLTV in sequence is 0xffffffff".to_string());
-        }
-    } else {
-        return Err("OP_CLTV pop item failed".to_string());
+    if stack_locktime < 0 {
+        return Err(ScriptError::NegativeLocktime);
     };
+    let tx_locktime = tx.locktime as i64;
+    if (stack_locktime < LOCKTIME_THRESHOLD) != (tx_locktime < LOCKTIME_THRESHOLD) {
+        return Err(ScriptError::UnsatisfiedLocktime); // different locktime types
+    }
+    if stack_locktime > tx_locktime || txin.sequence == SEQUENCE_FINAL {
+        return Err(ScriptError::UnsatisfiedLocktime);
+    }
     Ok(())
 }
 
-// serializes input of legacy transaction into Vec<u8>
-// all inputs except the one that is being verified (parameter) will be returned as 0x00
-// returns: byte serialized input as Vec<u8>
-fn serialize_input_legacy(input: &TxIn, signing_txin: &TxIn) -> Vec<u8> {
-    let mut serialized_input = get_outpoint(input);
-
-    if input == signing_txin {
-        let scriptpubkey_len = varint(
-            hex::decode(&signing_txin.prevout.scriptpubkey)
-                .expect("serialize_input_legacy hex encoding")
-                .len() as u128,
-        );
-        serialized_input.extend(scriptpubkey_len);
-        serialized_input.extend(
-            hex::decode(&signing_txin.prevout.scriptpubkey)
-                .expect("OP_CHECKSIG scriptpubkey hex decode failed"),
-        );
-    } else {
-        serialized_input.extend(hexlit!("00"));
+// Verify DER encoded signature against message and pubkey. Like bitcoin core the signature
+// is parsed laxly and normalized to low S, the encoding rules are enforced by the flags in
+// check_signature_encoding. Signatures and pubkeys that can't be decoded make the check
+// fail like an invalid signature.
+// returns: Ok(true) if the signature is valid, Ok(false) if not
+fn verify_sig_op_checksig(msg: &[u8], pubkey: &[u8], sig: &[u8]) -> Result<bool, ScriptError> {
+    let mut sig = match Signature::from_der_lax(sig) {
+        Ok(value) => value,
+        Err(_) => return Ok(false),
+    };
+    Signature::normalize_s(&mut sig);
+    let msg: [u8; 32] = match msg.try_into() {
+        Ok(msg) => msg,
+        Err(_) => return Err(ScriptError::Sighash("message is not 32 byte".to_string())),
+    };
+    let msg = Message::from_digest(msg);
+    let pubkey = match PublicKey::from_slice(pubkey) {
+        Ok(pubkey) => pubkey,
+        Err(_) => return Ok(false),
+    };
+    Ok(sig.verify(&msg, &pubkey).is_ok())
+}
+
+// BIP66 strict DER encoding of a signature including the hash type byte:
+// 0x30 [total-length] 0x02 [R-length] [R] 0x02 [S-length] [S] [sighash]
+// R and S have to be positive and without unnecessary leading zero bytes.
+fn is_valid_signature_encoding(sig: &[u8]) -> bool {
+    if sig.len() < 9 || sig.len() > 73 || sig[0] != 0x30 || sig[1] as usize != sig.len() - 3 {
+        return false;
+    }
+    let len_r = sig[3] as usize;
+    if 5 + len_r >= sig.len() {
+        return false;
     }
-    serialized_input.extend(input.sequence.to_le_bytes());
-    serialized_input
+    let len_s = sig[5 + len_r] as usize;
+    if len_r + len_s + 7 != sig.len() || sig[2] != 0x02 || len_r == 0 || sig[4] & 0x80 != 0 {
+        return false;
+    }
+    if len_r > 1 && sig[4] == 0x00 && sig[5] & 0x80 == 0 {
+        return false;
+    }
+    if sig[len_r + 4] != 0x02 || len_s == 0 || sig[len_r + 6] & 0x80 != 0 {
+        return false;
+    }
+    !(len_s > 1 && sig[len_r + 6] == 0x00 && sig[len_r + 7] & 0x80 == 0)
 }
 
-// Serialize legacy transaction (non segwit) for signature verification of specified input
-// returns: double SHA256 digest of serialized transaction
-fn serialize_legacy_tx(tx: &Transaction, signing_txin: &TxIn, sighash: u32) -> Vec<u8> {
-    let mut preimage: Vec<u8> = Vec::new();
+// returns: true if the S value of the DER signature (with hash type byte) is at most order / 2
+fn is_low_s_signature(sig: &[u8]) -> bool {
+    match Signature::from_der_lax(&sig[..sig.len() - 1]) {
+        Ok(signature) => {
+            let mut normalized = signature;
+            normalized.normalize_s();
+            normalized == signature
+        }
+        Err(_) => false,
+    }
+}
+
+// returns: true if the hash type byte (without ANYONECANPAY) is ALL, NONE or SINGLE
+fn is_defined_hashtype_signature(sig: &[u8]) -> bool {
+    match sig.last() {
+        Some(hash_type) => (0x01..=0x03).contains(&(hash_type & !0x80)),
+        None => false,
+    }
+}
 
-    preimage.extend(&tx.version.to_le_bytes()); // VERSION
-    preimage.extend(varint(tx.vin.len() as u128)); // INPUT amount
-    for tx_in in &tx.vin {
-        preimage.append(&mut serialize_input_legacy(tx_in, signing_txin));
+// Checks the encoding of an ECDSA signature according to the DERSIG, LOW_S and STRICTENC
+// flags. Empty signatures are always allowed as they provide a compact way to fail.
+// returns: Ok(()) or the ScriptError of the violated rule
+fn check_signature_encoding(sig: &[u8], flags: VerifyFlags) -> Result<(), ScriptError> {
+    if sig.is_empty() {
+        return Ok(());
+    }
+    let strict_der = VerifyFlags::DERSIG | VerifyFlags::LOW_S | VerifyFlags::STRICTENC;
+    if flags.intersects(strict_der) && !is_valid_signature_encoding(sig) {
+        return Err(ScriptError::SigDer);
+    }
+    if flags.contains(VerifyFlags::LOW_S) && !is_low_s_signature(sig) {
+        return Err(ScriptError::SigHighS);
     }
-    preimage.extend(varint(tx.vout.len() as u128)); // Output amount
-    for tx_out in &tx.vout {
-        preimage.append(&mut serialize_output(tx_out));
+    if flags.contains(VerifyFlags::STRICTENC) && !is_defined_hashtype_signature(sig) {
+        return Err(ScriptError::SigHashtype);
     }
-    preimage.extend(tx.locktime.to_le_bytes());
-    preimage.extend(sighash.to_le_bytes());
-    double_hash(&preimage)
+    Ok(())
 }
 
-// Verify DER encoded signature against message and pubkey
-fn verify_sig_op_checksig(msg: &[u8], pubkey: &[u8], sig: &[u8]) -> Result<(), String> {
-    let sig = Signature::from_der(sig);
-    let mut sig = match sig {
-        Ok(value) => value,
-        Err(err) => {
-            return Err(format!("Loading DER encoded signature failed: {}", err));
-        }
-    };
-    Signature::normalize_s(&mut sig);
-    let msg: [u8; 32] = msg.try_into().expect("Commitment hash is not 32 byte!");
-    let msg = Message::from_digest(msg);
-    let pubkey = PublicKey::from_slice(pubkey).expect("Pubkey invalid!");
-    let result = sig.verify(&msg, &pubkey);
-    match result {
-        Ok(_) => Ok(()),
-        Err(err) => Err(format!("Signature verification failed: {}", err)),
+// Checks the pubkey encoding according to STRICTENC (compressed or uncompressed) and
+// WITNESS_PUBKEYTYPE (compressed only in witness v0 scripts).
+// returns: Ok(()) or the ScriptError of the violated rule
+fn check_pubkey_encoding(pubkey: &[u8], ctx: &ScriptContext) -> Result<(), ScriptError> {
+    let compressed = pubkey.len() == 33 && (pubkey[0] == 0x02 || pubkey[0] == 0x03);
+    let uncompressed = pubkey.len() == 65 && pubkey[0] == 0x04;
+    if ctx.flags.contains(VerifyFlags::STRICTENC) && !compressed && !uncompressed {
+        return Err(ScriptError::PubkeyType);
+    }
+    if ctx.flags.contains(VerifyFlags::WITNESS_PUBKEYTYPE)
+        && ctx.sig_version == SigVersion::WitnessV0
+        && !compressed
+    {
+        return Err(ScriptError::WitnessPubkeyType);
     }
+    Ok(())
 }
 
-// implemented for non-witness transactions and SIGHASH_ALL only
-fn op_checksig(stack: &mut VecDeque<Vec<u8>>, tx: &Transaction, txin: &TxIn) -> Result<(), String> {
-    if stack.len() < 2 {
-        return Err("OP_CHECKSIG stack < 2".to_string());
+// BIP342 signature validation used by the tapscript signature opcodes. Every non empty
+// signature consumes validation weight and has to be valid, otherwise the script fails.
+// Pubkeys of unknown size (not 32 byte) are treated as valid for future upgrades
+// unless DISCOURAGE_UPGRADABLE_PUBKEYTYPE is set.
+// returns: Ok(true) if the signature is valid, Ok(false) if it is empty
+fn verify_sig_tapscript(
+    sig: &[u8],
+    pubkey: &[u8],
+    ctx: &mut ScriptContext,
+) -> Result<bool, ScriptError> {
+    let flags = ctx.flags;
+    let tapscript = match ctx.tapscript.as_mut() {
+        Some(tapscript) => tapscript,
+        None => return Err(ScriptError::Sighash("tapscript data missing".to_string())),
     };
-    let pubkey = if let Some(pubkey) = stack.pop_back() {
-        pubkey
-    } else {
-        return Err("OP_CHECKSIG popping pubkey from stack failed!".to_string());
-    };
-    let mut der_signature = if let Some(signature) = stack.pop_back() {
-        signature
-    } else {
-        return Err("OP_CHECKSIG popping signature from stack failed!".to_string());
-    };
-    let sighash: u32 = if let Some(sighash_byte) = der_signature.pop() {
-        sighash_byte as u32
+    let success = !sig.is_empty();
+    if success {
+        tapscript.validation_weight_left -= VALIDATION_WEIGHT_PER_SIGOP;
+        if tapscript.validation_weight_left < 0 {
+            return Err(ScriptError::TapscriptValidationWeight);
+        }
+    }
+    if pubkey.is_empty() {
+        return Err(ScriptError::PubkeyType);
+    }
+    if pubkey.len() == 32 {
+        if success {
+            let (signature, hash_type) = split_schnorr_signature(sig)?;
+            let sighash = taproot_sighash(
+                ctx.tx,
+                ctx.input_index,
+                hash_type,
+                tapscript.annex.as_deref(),
+                Some((&tapscript.leaf_hash, tapscript.codesep_pos)),
+            )
+            .map_err(|_| ScriptError::SchnorrSigHashtype)?;
+            verify_schnorr(&sighash, pubkey, signature)?;
+        }
+    } else if flags.contains(VerifyFlags::DISCOURAGE_UPGRADABLE_PUBKEYTYPE) {
+        return Err(ScriptError::DiscourageUpgradablePubkeyType);
+    }
+    Ok(success)
+}
+
+// OP_CHECKSIGADD (tapscript only): <sig> <n> <pubkey> -> <n + 1 if sig valid else n>
+fn op_checksigadd(
+    stack: &mut VecDeque<Vec<u8>>,
+    ctx: &mut ScriptContext,
+) -> Result<(), ScriptError> {
+    if stack.len() < 3 {
+        return Err(ScriptError::InvalidStackOperation);
+    }
+    let require_minimal = ctx.flags.contains(VerifyFlags::MINIMALDATA);
+    let pubkey = stack.pop_back().expect("OP_CHECKSIGADD pop pubkey");
+    let number = decode_stack_num(
+        &stack.pop_back().expect("OP_CHECKSIGADD pop n"),
+        4,
+        require_minimal,
+    )?;
+    let signature = stack.pop_back().expect("OP_CHECKSIGADD pop signature");
+
+    if verify_sig_tapscript(&signature, &pubkey, ctx)? {
+        stack.push_back(encode_script_num(number + 1));
     } else {
-        return Err("OP_CHECKSIG popping sighash from signature failed".to_string());
-    };
-    if sighash != 0x00000001 {
-        // SIGHASH_ALL
-        return Err("sighash not implemented".to_string());
-    }
-    let message = match txin.in_type {
-        InputType::P2PKH => serialize_legacy_tx(tx, txin, sighash),
-        InputType::P2SH => serialize_legacy_tx(tx, txin, sighash),
-        _ => panic!("op_checksig unsupported txtype"),
-    };
-    match verify_sig_op_checksig(&message, &pubkey, &der_signature) {
-        Ok(_) => stack.push_back(vec![1u8]),
-        Err(_) => stack.push_back(vec![]),
+        stack.push_back(encode_script_num(number));
     }
     Ok(())
 }
 
-fn op_verify(stack: &mut VecDeque<Vec<u8>>) -> Result<(), &'static str> {
-    if let Some(top_stack_element) = stack.pop_back() {
-        if top_stack_element.is_empty() {
-            Err("OP_VERIFY not valid")
+// returns: script code the ECDSA signatures commit to. In legacy scripts the passed
+// signatures (including hash type byte) are removed from it (FindAndDelete), which
+// fails the script if CONST_SCRIPTCODE is set and a signature was found.
+fn get_signing_script_code(
+    ctx: &ScriptContext,
+    signatures: &[Vec<u8>],
+) -> Result<Vec<u8>, ScriptError> {
+    let mut script_code = ctx.script_code.clone();
+    if ctx.sig_version != SigVersion::Base {
+        return Ok(script_code);
+    }
+    for signature in signatures {
+        let deleted = find_and_delete(&script_code, &serialize_push(signature));
+        if deleted.len() != script_code.len() && ctx.flags.contains(VerifyFlags::CONST_SCRIPTCODE) {
+            return Err(ScriptError::SigFindAndDelete);
+        }
+        script_code = deleted;
+    }
+    Ok(script_code)
+}
+
+// Calculates the sighash of the hash type over the script code for the current sig version
+// returns: message (sighash) to verify ECDSA signatures against or Err(reason)
+fn get_ecdsa_message(
+    ctx: &ScriptContext,
+    hash_type: u32,
+    script_code: &[u8],
+) -> Result<Vec<u8>, ScriptError> {
+    match ctx.sig_version {
+        SigVersion::WitnessV0 => segwit_v0_sighash(ctx.tx, ctx.input_index, script_code, hash_type)
+            .map_err(ScriptError::Sighash),
+        _ => legacy_sighash(ctx.tx, ctx.input_index, script_code, hash_type)
+            .map_err(ScriptError::Sighash),
+    }
+}
+
+// ECDSA signature check for legacy and segwit v0 scripts, and schnorr for tapscript
+fn op_checksig(stack: &mut VecDeque<Vec<u8>>, ctx: &mut ScriptContext) -> Result<(), ScriptError> {
+    if stack.len() < 2 {
+        return Err(ScriptError::InvalidStackOperation);
+    };
+    if ctx.sig_version == SigVersion::Tapscript {
+        let pubkey = stack.pop_back().expect("OP_CHECKSIG pop pubkey");
+        let signature = stack.pop_back().expect("OP_CHECKSIG pop signature");
+        if verify_sig_tapscript(&signature, &pubkey, ctx)? {
+            stack.push_back(vec![1u8]);
         } else {
-            Ok(())
+            stack.push_back(Vec::new());
         }
+        return Ok(());
+    }
+    let pubkey = stack.pop_back().expect("OP_CHECKSIG pop pubkey");
+    let signature = stack.pop_back().expect("OP_CHECKSIG pop signature");
+    let script_code = get_signing_script_code(ctx, std::slice::from_ref(&signature))?;
+    check_signature_encoding(&signature, ctx.flags)?;
+    check_pubkey_encoding(&pubkey, ctx)?;
+    if check_ecdsa_signature(&signature, &pubkey, ctx, &script_code)? {
+        stack.push_back(vec![1u8]);
+    } else if ctx.flags.contains(VerifyFlags::NULLFAIL) && !signature.is_empty() {
+        return Err(ScriptError::SigNullFail);
     } else {
-        Err("OP_VERIFY popping top stack element failed")
+        stack.push_back(Vec::new());
+    }
+    Ok(())
+}
+
+// pops the top stack element and fails with the passed error if it is false
+fn op_verify(stack: &mut VecDeque<Vec<u8>>, error: ScriptError) -> Result<(), ScriptError> {
+    match stack.pop_back() {
+        Some(top_stack_element) if cast_to_bool(&top_stack_element) => Ok(()),
+        Some(_) => Err(error),
+        None => Err(ScriptError::InvalidStackOperation),
     }
 }
 
-fn op_pushnum(stack: &mut VecDeque<Vec<u8>>, amount: u8) -> Result<(), &'static str> {
+fn op_pushnum(stack: &mut VecDeque<Vec<u8>>, amount: u8) -> Result<(), ScriptError> {
     let number: u8 = amount - 80;
     let number_bytes: Vec<u8> = vec![number];
     stack.push_back(number_bytes);
     Ok(())
 }
 
-fn op_pushbytes(
-    stack: &mut VecDeque<Vec<u8>>,
-    index: &mut usize,
-    script: &[u8],
-) -> Result<(), &'static str> {
+// reads the bytes pushed by OP_PUSHBYTES_X at the index and moves the index to the last pushed byte
+fn op_pushbytes(index: &mut usize, script: &[u8]) -> Result<Vec<u8>, ScriptError> {
     let opcode: u8 = script[*index];
-    let mut bytes: Vec<u8> = Vec::new();
+    let start = *index + 1;
+    let end = start + opcode as usize;
 
-    if *index + opcode as usize <= script.len() {
-        bytes.resize(opcode as usize, 0);
-        bytes.clone_from_slice(&script[*index + 1..*index + 1 + opcode as usize]);
-        stack.push_back(bytes);
-        *index += opcode as usize;
-    } else {
-        return Err("OP_PUSHBYTES opcode out of range");
+    if end > script.len() {
+        return Err(ScriptError::BadPush);
     }
-    Ok(())
+    *index += opcode as usize;
+    Ok(script[start..end].to_vec())
 }
 
+// returns: amount of bytes pushed by OP_PUSHDATA1/2/4 (amount_bytes 1/2/4) at current_index
 pub fn get_pushdata_amount(
     script: &[u8],
     amount_bytes: u8,
     current_index: usize,
-) -> Result<u32, &'static str> {
-    let mut amount_of_bytes_to_push: Vec<u8> = vec![0; amount_bytes as usize];
-
-    amount_of_bytes_to_push
-        .clone_from_slice(&script[current_index + 1..current_index + 1 + amount_bytes as usize]);
+) -> Result<u32, ScriptError> {
+    let start = current_index + 1;
+    let end = start + amount_bytes as usize;
+    if end > script.len() {
+        return Err(ScriptError::BadPush);
+    }
+    let amount_of_bytes_to_push = &script[start..end];
     match amount_bytes {
         1 => Ok(amount_of_bytes_to_push[0] as u32),
-        2 => Ok(LittleEndian::read_u16(&amount_of_bytes_to_push) as u32),
-        4 => Ok(LittleEndian::read_u32(&amount_of_bytes_to_push)),
-        _ => Err("get_pushdata_amount weird amount in match"),
+        2 => Ok(LittleEndian::read_u16(amount_of_bytes_to_push) as u32),
+        4 => Ok(LittleEndian::read_u32(amount_of_bytes_to_push)),
+        _ => Err(ScriptError::BadPush),
     }
 }
 
-fn op_pushdata(
+// reads the bytes pushed by OP_PUSHDATA1/2/4 at the index and moves the index to the last pushed byte
+fn op_pushdata(amount_bytes: u8, index: &mut usize, script: &[u8]) -> Result<Vec<u8>, ScriptError> {
+    let amount_of_bytes_to_push = get_pushdata_amount(script, amount_bytes, *index)? as usize;
+    let start = *index + 1 + amount_bytes as usize;
+    let end = start + amount_of_bytes_to_push;
+
+    if end > script.len() {
+        return Err(ScriptError::BadPush);
+    }
+    *index = end - 1;
+    Ok(script[start..end].to_vec())
+}
+
+// reads the data pushed by the push opcode (OP_0 - OP_PUSHDATA4) at the index
+// and moves the index to the last byte belonging to the push
+fn read_push(index: &mut usize, script: &[u8]) -> Result<Vec<u8>, ScriptError> {
+    match script[*index] {
+        0x00 => Ok(Vec::new()),
+        0x01..=0x4b => op_pushbytes(index, script),
+        0x4c => op_pushdata(1, index, script),
+        0x4d => op_pushdata(2, index, script),
+        0x4e => op_pushdata(4, index, script),
+        opcode => Err(ScriptError::BadOpcode(opcode)),
+    }
+}
+
+// returns: Ok(true) if the script only contains push opcodes (OP_0 - OP_16)
+pub fn is_push_only(script: &[u8]) -> Result<bool, ScriptError> {
+    let mut index = 0;
+
+    while index < script.len() {
+        let opcode = script[index];
+        if opcode <= 0x4e {
+            read_push(&mut index, script)?;
+        } else if opcode > 0x60 {
+            return Ok(false);
+        }
+        index += 1;
+    }
+    Ok(true)
+}
+
+// returns: data pushed by the last operation of the script, None if it is no push
+// or the script can't be decoded
+pub fn get_last_push(script: &[u8]) -> Option<Vec<u8>> {
+    let mut index = 0;
+    let mut last_push = None;
+
+    while index < script.len() {
+        last_push = match script[index] {
+            opcode if opcode <= 0x4e => Some(read_push(&mut index, script).ok()?),
+            _ => None,
+        };
+        index += 1;
+    }
+    last_push
+}
+
+// splits the script at the opcode boundaries, pushes are kept together with their data.
+// A push exceeding the script is returned as last element with the remaining bytes.
+// returns: Vec of the single operations as byte slices
+pub fn split_script(script: &[u8]) -> Vec<&[u8]> {
+    let mut operations: Vec<&[u8]> = Vec::new();
+    let mut index = 0;
+
+    while index < script.len() {
+        let opcode = script[index];
+        let length_bytes: usize = match opcode {
+            0x4c => 1,
+            0x4d => 2,
+            0x4e => 4,
+            _ => 0,
+        };
+        let mut end = index + 1 + length_bytes;
+        if end > script.len() {
+            operations.push(&script[index..]);
+            break;
+        }
+        end += match opcode {
+            0x01..=0x4b => opcode as usize,
+            0x4c..=0x4e => {
+                let mut len_bytes = [0u8; 4];
+                len_bytes[..length_bytes].copy_from_slice(&script[index + 1..end]);
+                u32::from_le_bytes(len_bytes) as usize
+            }
+            _ => 0,
+        };
+        if end > script.len() {
+            operations.push(&script[index..]);
+            break;
+        }
+        operations.push(&script[index..end]);
+        index = end;
+    }
+    operations
+}
+
+// removes all operations equal to the pattern from the script (FindAndDelete), used to
+// remove the signatures from the script code of legacy scripts before hashing
+// returns: script without the pattern
+pub fn find_and_delete(script: &[u8], pattern: &[u8]) -> Vec<u8> {
+    if pattern.is_empty() {
+        return script.to_vec();
+    }
+    split_script(script)
+        .into_iter()
+        .filter(|operation| *operation != pattern)
+        .flatten()
+        .copied()
+        .collect()
+}
+
+// returns: true for the opcodes that make a tapscript succeed unconditionally (BIP342)
+fn is_op_success(opcode: u8) -> bool {
+    matches!(
+        opcode,
+        0x50 | 0x62 | 0x7e..=0x81 | 0x83..=0x86 | 0x89..=0x8a | 0x8d..=0x8e | 0x95..=0x99 | 0xbb..=0xfe
+    )
+}
+
+// decodes the tapscript and looks for OP_SUCCESSx opcodes
+// returns: Ok(true) if the script contains one, Err if the script can't be decoded before
+pub fn contains_op_success(script: &[u8]) -> Result<bool, ScriptError> {
+    let mut index = 0;
+
+    while index < script.len() {
+        let opcode = script[index];
+        if opcode <= 0x4e {
+            read_push(&mut index, script)?;
+        } else if is_op_success(opcode) {
+            return Ok(true);
+        }
+        index += 1;
+    }
+    Ok(false)
+}
+
+// OP_IF / OP_NOTIF: pops the condition if the current branch is executed and
+// pushes the result on the exec stack. Tapscript (and witness v0 with MINIMALIF)
+// requires minimal encoding (empty or 0x01).
+fn op_if(
     stack: &mut VecDeque<Vec<u8>>,
-    amount_bytes: u8,
-    index: &mut usize,
-    script: &[u8],
-) -> Result<(), &'static str> {
-    let mut data_push: Vec<u8> = Vec::new();
-
-    let amount_of_bytes_to_push = get_pushdata_amount(script, amount_bytes, *index)?;
-    *index += amount_bytes as usize + 1;
-    data_push.resize(amount_of_bytes_to_push as usize, 0);
-    data_push.clone_from_slice(&script[*index..*index + amount_of_bytes_to_push as usize]);
-    stack.push_back(data_push);
-    *index += amount_of_bytes_to_push as usize - 1;
+    exec_stack: &mut Vec<bool>,
+    executing: bool,
+    notif: bool,
+    ctx: &ScriptContext,
+) -> Result<(), ScriptError> {
+    let mut condition = false;
+
+    if executing {
+        let top = match stack.pop_back() {
+            Some(top) => top,
+            None => return Err(ScriptError::UnbalancedConditional),
+        };
+        let minimal = top.is_empty() || top == [1u8];
+        if ctx.sig_version == SigVersion::Tapscript && !minimal {
+            return Err(ScriptError::TapscriptMinimalIf);
+        }
+        if ctx.sig_version == SigVersion::WitnessV0
+            && ctx.flags.contains(VerifyFlags::MINIMALIF)
+            && !minimal
+        {
+            return Err(ScriptError::MinimalIf);
+        }
+        condition = cast_to_bool(&top) != notif;
+    }
+    exec_stack.push(condition);
     Ok(())
 }
 
-fn op_depth(stack: &mut VecDeque<Vec<u8>>) -> Result<(), &'static str> {
-    stack.push_back(varint(stack.len() as u128));
+fn op_depth(stack: &mut VecDeque<Vec<u8>>) -> Result<(), ScriptError> {
+    stack.push_back(encode_script_num(stack.len() as i64));
     Ok(())
 }
 
-// NULL sig1 sig2 ... <number of signatures> pub1 pub2 <number of public keys>
+// verifies an ECDSA signature (including hash type byte) of a legacy or segwit v0 script
+// against the sighash of the passed script code (see get_signing_script_code)
+// returns: Ok(true) if the signature is valid, Ok(false) if not
+fn check_ecdsa_signature(
+    signature: &[u8],
+    pubkey: &[u8],
+    ctx: &ScriptContext,
+    script_code: &[u8],
+) -> Result<bool, ScriptError> {
+    let (der_signature, hash_type) = match signature.split_last() {
+        Some((hash_type, der_signature)) => (der_signature, *hash_type as u32),
+        None => return Ok(false),
+    };
+    let message = get_ecdsa_message(ctx, hash_type, script_code)?;
+    verify_sig_op_checksig(&message, pubkey, der_signature)
+}
+
+// <dummy> <sig1> ... <sigm> <m> <pubkey1> ... <pubkeyn> <n> -> <true if the m signatures
+// match m of the n pubkeys in the same order>. The dummy element is consumed because of
+// the off-by-one bug of the original implementation and has to be empty with NULLDUMMY.
+// The pubkeys count towards the op count.
 fn op_checkmultisig(
     stack: &mut VecDeque<Vec<u8>>,
-    tx: &Transaction,
-    txin: &TxIn,
-) -> Result<(), &'static str> {
-    let mut signatures: VecDeque<Vec<u8>> = VecDeque::new();
-    let mut pubkeys: VecDeque<Vec<u8>> = VecDeque::new();
-    let number_of_pubkeys;
-    let mut number_of_signatures;
-
-    if let Some(pubkey_amount) = stack.pop_back() {
-        number_of_pubkeys = pubkey_amount[0]; // should be enough for these scripts
-        for _ in 0..number_of_pubkeys {
-            if let Some(pubkey) = stack.pop_back() {
-                pubkeys.push_back(pubkey);
-            } else {
-                return Err("OP_CHECKMULTISIG error popping pubkey from stack");
-            };
+    ctx: &ScriptContext,
+    op_count: &mut usize,
+) -> Result<(), ScriptError> {
+    let require_minimal = ctx.flags.contains(VerifyFlags::MINIMALDATA);
+    let pubkey_count = match stack.pop_back() {
+        Some(pubkey_count) => decode_stack_num(&pubkey_count, 4, require_minimal)?,
+        None => return Err(ScriptError::InvalidStackOperation),
+    };
+    if !(0..=MAX_PUBKEYS_PER_MULTISIG).contains(&pubkey_count) {
+        return Err(ScriptError::PubkeyCount);
+    }
+    *op_count += pubkey_count as usize;
+    if *op_count > MAX_OPS_PER_SCRIPT {
+        return Err(ScriptError::OpCount);
+    }
+    if stack.len() < pubkey_count as usize {
+        return Err(ScriptError::InvalidStackOperation);
+    }
+    let pubkeys: Vec<Vec<u8>> = stack.split_off(stack.len() - pubkey_count as usize).into();
+
+    let signature_count = match stack.pop_back() {
+        Some(signature_count) => decode_stack_num(&signature_count, 4, require_minimal)?,
+        None => return Err(ScriptError::InvalidStackOperation),
+    };
+    if signature_count < 0 || signature_count > pubkey_count {
+        return Err(ScriptError::SigCount);
+    }
+    if stack.len() < signature_count as usize {
+        return Err(ScriptError::InvalidStackOperation);
+    }
+    let signatures: Vec<Vec<u8>> = stack
+        .split_off(stack.len() - signature_count as usize)
+        .into();
+    let dummy = match stack.pop_back() {
+        Some(dummy) => dummy,
+        None => return Err(ScriptError::InvalidStackOperation), // dummy element missing
+    };
+    let script_code = get_signing_script_code(ctx, &signatures)?;
+
+    // signatures and pubkeys are matched starting with the last ones like in bitcoin core
+    let (mut signatures_left, mut pubkeys_left) = (signatures.len(), pubkeys.len());
+    let mut success = true;
+    while success && signatures_left > 0 {
+        let signature = &signatures[signatures_left - 1];
+        let pubkey = &pubkeys[pubkeys_left - 1];
+        check_signature_encoding(signature, ctx.flags)?;
+        check_pubkey_encoding(pubkey, ctx)?;
+        if check_ecdsa_signature(signature, pubkey, ctx, &script_code)? {
+            signatures_left -= 1;
+        }
+        pubkeys_left -= 1;
+        if signatures_left > pubkeys_left {
+            success = false;
         }
+    }
+    if !success
+        && ctx.flags.contains(VerifyFlags::NULLFAIL)
+        && signatures.iter().any(|signature| !signature.is_empty())
+    {
+        return Err(ScriptError::SigNullFail);
+    }
+    if ctx.flags.contains(VerifyFlags::NULLDUMMY) && !dummy.is_empty() {
+        return Err(ScriptError::SigNullDummy);
+    }
+    if success {
+        stack.push_back(vec![1u8]);
     } else {
-        return Err("OP_CHECKMULTISIG error popping number of pubkeys");
+        stack.push_back(Vec::new());
+    }
+    Ok(())
+}
+
+// OP_2DROP, OP_2DUP, OP_3DUP, OP_2OVER, OP_2ROT, OP_2SWAP, OP_NIP and OP_TUCK
+fn op_stack_manipulation(stack: &mut VecDeque<Vec<u8>>, opcode: u8) -> Result<(), ScriptError> {
+    let needed = match opcode {
+        0x6d | 0x6e | 0x77 | 0x7d => 2,
+        0x6f => 3,
+        0x70 | 0x72 => 4,
+        0x71 => 6,
+        _ => return Err(ScriptError::BadOpcode(opcode)),
     };
-    if let Some(signature_amount) = stack.pop_back() {
-        number_of_signatures = signature_amount[0];
-        for _ in 0..number_of_signatures {
-            if let Some(signature) = stack.pop_back() {
-                signatures.push_front(signature);
-            } else {
-                return Err("OP_CHECKMULTISIG error popping signature from stack");
-            };
+    let len = stack.len();
+    if len < needed {
+        return Err(ScriptError::InvalidStackOperation);
+    }
+    match opcode {
+        0x6d => stack.truncate(len - 2), // OP_2DROP
+        0x6e | 0x6f => {
+            // OP_2DUP, OP_3DUP
+            for i in len - needed..len {
+                stack.push_back(stack[i].clone());
+            }
+        }
+        0x70 => {
+            // OP_2OVER
+            for i in len - 4..len - 2 {
+                stack.push_back(stack[i].clone());
+            }
+        }
+        0x71 | 0x72 => {
+            // OP_2ROT, OP_2SWAP
+            let moved: Vec<Vec<u8>> = stack.drain(len - needed..len - needed + 2).collect();
+            stack.extend(moved);
+        }
+        0x77 => {
+            // OP_NIP
+            stack.remove(len - 2);
         }
-        stack.pop_back(); // OP_CHECKMULTISIG BUG
+        _ => {
+            // OP_TUCK
+            let top = stack[len - 1].clone();
+            stack.insert(len - 2, top);
+        }
+    }
+    Ok(())
+}
+
+// OP_PICK / OP_ROLL: copies (or moves) the item n back in the stack to the top
+fn op_pick_roll(
+    stack: &mut VecDeque<Vec<u8>>,
+    roll: bool,
+    require_minimal: bool,
+) -> Result<(), ScriptError> {
+    let n = match stack.pop_back() {
+        Some(n) => decode_stack_num(&n, 4, require_minimal)?,
+        None => return Err(ScriptError::InvalidStackOperation),
+    };
+    if n < 0 || n as usize >= stack.len() {
+        return Err(ScriptError::InvalidStackOperation);
+    }
+    let index = stack.len() - 1 - n as usize;
+    let item = if roll {
+        stack.remove(index).expect("OP_ROLL index checked")
     } else {
-        return Err("OP_CHECKMULTISIG error popping number of signatures");
+        stack[index].clone()
     };
+    stack.push_back(item);
+    Ok(())
+}
 
-    'outer: for mut signature in signatures {
-        let mut retry = true;
+// OP_1ADD, OP_1SUB, OP_NEGATE, OP_ABS, OP_NOT and OP_0NOTEQUAL on the top stack number
+fn op_numeric_unary(
+    stack: &mut VecDeque<Vec<u8>>,
+    opcode: u8,
+    require_minimal: bool,
+) -> Result<(), ScriptError> {
+    let number = match stack.pop_back() {
+        Some(number) => decode_stack_num(&number, 4, require_minimal)?,
+        None => return Err(ScriptError::InvalidStackOperation),
+    };
+    let result = match opcode {
+        0x8b => number + 1,
+        0x8c => number - 1,
+        0x8f => -number,
+        0x90 => number.abs(),
+        0x91 => i64::from(number == 0),
+        0x92 => i64::from(number != 0),
+        _ => return Err(ScriptError::BadOpcode(opcode)),
+    };
+    stack.push_back(encode_script_num(result));
+    Ok(())
+}
 
-        let sighash: u32 = if let Some(sighash_byte) = signature.pop() {
-            sighash_byte as u32
-        } else {
-            return Err("OP_CHECKSIG popping sighash from signature failed");
-        };
-        if sighash != 0x00000001 {
-            // IMPLEMENT OTHER SIGHASH TYPES
-            return Err("OP_CHECKMULTISIG sighash not implemented");
-        };
-        let message = match txin.in_type {
-            InputType::P2SH => serialize_legacy_tx(tx, txin, sighash),
-            _ => panic!("op_checkmultisig unsupported txtype"),
-        };
+// OP_ADD, OP_SUB, OP_BOOLAND, OP_BOOLOR, OP_NUMEQUAL(VERIFY), OP_NUMNOTEQUAL, OP_LESSTHAN,
+// OP_GREATERTHAN, OP_LESSTHANOREQUAL, OP_GREATERTHANOREQUAL, OP_MIN and OP_MAX: <a> <b> -> <result>
+fn op_numeric_binary(
+    stack: &mut VecDeque<Vec<u8>>,
+    opcode: u8,
+    require_minimal: bool,
+) -> Result<(), ScriptError> {
+    if stack.len() < 2 {
+        return Err(ScriptError::InvalidStackOperation);
+    }
+    let b = stack.pop_back().expect("numeric opcode pop b");
+    let a = stack.pop_back().expect("numeric opcode pop a");
+    let a = decode_stack_num(&a, 4, require_minimal)?;
+    let b = decode_stack_num(&b, 4, require_minimal)?;
+    let result = match opcode {
+        0x93 => a + b,
+        0x94 => a - b,
+        0x9a => i64::from(a != 0 && b != 0),
+        0x9b => i64::from(a != 0 || b != 0),
+        0x9c | 0x9d => i64::from(a == b),
+        0x9e => i64::from(a != b),
+        0x9f => i64::from(a < b),
+        0xa0 => i64::from(a > b),
+        0xa1 => i64::from(a <= b),
+        0xa2 => i64::from(a >= b),
+        0xa3 => a.min(b),
+        0xa4 => a.max(b),
+        _ => return Err(ScriptError::BadOpcode(opcode)),
+    };
+    stack.push_back(encode_script_num(result));
+    if opcode == 0x9d {
+        op_verify(stack, ScriptError::NumEqualVerify)?;
+    }
+    Ok(())
+}
 
-        while retry {
-            if let Some(pubkey) = pubkeys.pop_back() {
-                retry = false;
-                match verify_sig_op_checksig(&message, &pubkey, &signature) {
-                    Ok(_) => {
-                        number_of_signatures -= 1;
-                    }
-                    Err(err) => {
-                        println!("{}", err);
-                        retry = true;
-                    }
-                };
-            } else {
-                break 'outer;
-            };
-        }
+// OP_WITHIN: <x> <min> <max> -> <1 if min <= x < max else 0>
+fn op_within(stack: &mut VecDeque<Vec<u8>>, require_minimal: bool) -> Result<(), ScriptError> {
+    if stack.len() < 3 {
+        return Err(ScriptError::InvalidStackOperation);
     }
-    if number_of_signatures == 0 {
-        stack.push_back(vec![1u8]);
-    } else {
-        stack.push_back(vec![]);
+    let max = stack.pop_back().expect("OP_WITHIN pop max");
+    let min = stack.pop_back().expect("OP_WITHIN pop min");
+    let x = stack.pop_back().expect("OP_WITHIN pop x");
+    let x = decode_stack_num(&x, 4, require_minimal)?;
+    let min = decode_stack_num(&min, 4, require_minimal)?;
+    let max = decode_stack_num(&max, 4, require_minimal)?;
+    stack.push_back(encode_script_num(i64::from(min <= x && x < max)));
+    Ok(())
+}
+
+// OP_RIPEMD160, OP_SHA1, OP_SHA256, OP_HASH160 and OP_HASH256 on the top stack item
+fn op_hash(stack: &mut VecDeque<Vec<u8>>, opcode: u8) -> Result<(), ScriptError> {
+    let item = match stack.pop_back() {
+        Some(item) => item,
+        None => return Err(ScriptError::InvalidStackOperation),
+    };
+    let digest = match opcode {
+        0xa6 => hash_ripemd160(&item),
+        0xa7 => hash_sha1(&item),
+        0xa8 => hash_sha256(&item),
+        0xa9 => hash160(&item),
+        0xaa => double_hash(&item),
+        _ => return Err(ScriptError::BadOpcode(opcode)),
     };
+    stack.push_back(digest);
     Ok(())
 }
 
+// returns: true if the data is pushed with the smallest possible push opcode (MINIMALDATA)
+fn is_minimal_push(data: &[u8], opcode: u8) -> bool {
+    match data.len() {
+        0 => opcode == 0x00,                                          // OP_0
+        1 if (1..=16).contains(&data[0]) || data[0] == 0x81 => false, // OP_1 - OP_16, OP_1NEGATE
+        len if len <= 75 => opcode as usize == len,
+        len if len <= 0xff => opcode == 0x4c,
+        len if len <= 0xffff => opcode == 0x4d,
+        _ => true,
+    }
+}
+
+// returns: true for the opcodes that fail the script even in not executed branches
+fn is_disabled_opcode(opcode: u8) -> bool {
+    matches!(opcode, 0x7e..=0x81 | 0x83..=0x86 | 0x8d..=0x8e | 0x95..=0x99)
+}
+
 // main script interpretion function
-// executes the script argument and returns Ok() if the script is valid and True
-pub fn evaluate_script(
-    script: Vec<u8>,
-    txin: &TxIn,
-    tx: &Transaction,
-) -> Result<(), Box<dyn Error>> {
-    let mut stack: VecDeque<Vec<u8>> = VecDeque::new();
+// executes the script on the passed stack. Opcodes in not executed branches
+// (OP_IF/OP_NOTIF/OP_ELSE) are skipped, disabled opcodes fail the script anyway.
+// Doesn't check the resulting stack.
+// returns: Ok(()) if no opcode failed
+pub fn execute_script(
+    script: &[u8],
+    stack: &mut VecDeque<Vec<u8>>,
+    ctx: &mut ScriptContext,
+) -> Result<(), ScriptError> {
+    let mut alt_stack: VecDeque<Vec<u8>> = VecDeque::new();
+    let mut exec_stack: Vec<bool> = Vec::new();
     let mut index = 0;
+    let mut opcode_pos: u32 = 0;
+    let mut op_count: usize = 0;
+    let is_tapscript = ctx.sig_version == SigVersion::Tapscript;
+    let require_minimal = ctx.flags.contains(VerifyFlags::MINIMALDATA);
+    let discourage_nops = ctx.flags.contains(VerifyFlags::DISCOURAGE_UPGRADABLE_NOPS);
 
+    if !is_tapscript && script.len() > MAX_SCRIPT_SIZE {
+        return Err(ScriptError::ScriptSize);
+    }
+    ctx.script_code = script.to_vec();
     while index < script.len() {
         let opcode = script[index];
-        match opcode {
-            0xa8 => {
-                // SHA256
-                if let Some(last) = stack.pop_back() {
-                    stack.push_back(hash_sha256(&last));
-                } else {
-                    return Err("OP_SHA256 stack empty".into());
-                }
+        let executing = !exec_stack.contains(&false);
+
+        if !is_tapscript && opcode > 0x60 {
+            op_count += 1;
+            if op_count > MAX_OPS_PER_SCRIPT {
+                return Err(ScriptError::OpCount);
+            }
+        }
+        if is_disabled_opcode(opcode) {
+            return Err(ScriptError::DisabledOpcode(opcode));
+        }
+        if opcode == 0xab
+            && ctx.sig_version == SigVersion::Base
+            && ctx.flags.contains(VerifyFlags::CONST_SCRIPTCODE)
+        {
+            return Err(ScriptError::OpCodeseparator);
+        }
+        if opcode <= 0x4e {
+            // OP_0, OP_PUSHBYTES, OP_PUSHDATA
+            let data = read_push(&mut index, script)?;
+            if data.len() > MAX_SCRIPT_ELEMENT_SIZE {
+                return Err(ScriptError::PushSize);
             }
-            0xa9 => {
-                // OP_HASH160
-                if let Some(last) = stack.pop_back() {
-                    stack.push_back(hash160(&last));
-                } else {
-                    return Err("OP_HASH160 stack empty".into());
+            if executing {
+                if require_minimal && !is_minimal_push(&data, opcode) {
+                    return Err(ScriptError::MinimalData);
                 }
+                stack.push_back(data);
             }
-            0x75 => {
-                if stack.pop_back().is_none() {
-                    return Err("OP_DROP stack empty".into());
+        } else if executing || (0x63..=0x68).contains(&opcode) {
+            match opcode {
+                0x4f => stack.push_back(encode_script_num(-1)), // OP_1NEGATE
+                0x51..=0x60 => op_pushnum(stack, opcode)?,      // OP_PUSHNUM (1-16)
+                0x61 => {}                                      // OP_NOP
+                0x63 => op_if(stack, &mut exec_stack, executing, false, ctx)?, // OP_IF
+                0x64 => op_if(stack, &mut exec_stack, executing, true, ctx)?, // OP_NOTIF
+                0x67 => {
+                    // OP_ELSE
+                    if let Some(last) = exec_stack.last_mut() {
+                        *last = !*last;
+                    } else {
+                        return Err(ScriptError::UnbalancedConditional);
+                    }
                 }
-            } // OP_DROP
-            0x7c => op_swap(&mut stack)?,        // OP_SWAP
-            0x00 => stack.push_back(Vec::new()), // OP_0
-            0x76 => {
-                // OP_DUP
-                if let Some(last) = stack.back() {
-                    stack.push_back(last.clone());
-                } else {
-                    return Err("OP_DUP stack empty.".into());
+                0x68 => {
+                    // OP_ENDIF
+                    if exec_stack.pop().is_none() {
+                        return Err(ScriptError::UnbalancedConditional);
+                    }
                 }
-            }
-            0x87 => op_equal(&mut stack)?,       // OP_EQUAL
-            0x7b => op_rot(&mut stack)?,         // OP_ROT
-            0x82 => op_size(&mut stack)?,        // OP_SIZE
-            0x78 => op_over(&mut stack)?,        // OP_OVER
-            0xa0 => op_greaterthan(&mut stack)?, // OP_GREATERTHAN
-            0x88 => op_equalverify(&mut stack)?, // OP_EQUALVERIFY
-            0x73 => op_ifdup(&mut stack)?,       // OP_IFDUP
-            0xb2 => op_checksequenceverify(&mut stack, txin, tx)?, // OP_CSV
-            0xb1 => op_checklocktimeverify(&mut stack, tx, txin)?, // OP_CLTV
-            0xac => op_checksig(&mut stack, tx, txin)?, // OP_CHECKSIG
-            0x74 => op_depth(&mut stack)?,       // OP_DEPTH
-            0xad => {
-                // OP_CHECKSIGVERIFY
-                op_checksig(&mut stack, tx, txin)?;
-                op_verify(&mut stack)?;
-            }
-            0x51..=0x60 => op_pushnum(&mut stack, opcode)?, // OP_PUSHNUM (1-16)
-            0x4f => stack.push_back(vec![255]),             // OP_1NEGATE
-            0x01..=0x4b => op_pushbytes(&mut stack, &mut index, &script)?, // OP_PUSHBYTES
-            0x4c => op_pushdata(&mut stack, 1, &mut index, &script)?, // OP_PUSHDATA1
-            0x4d => op_pushdata(&mut stack, 2, &mut index, &script)?, // OP_PUSHDATA2
-            0x4e => op_pushdata(&mut stack, 4, &mut index, &script)?, // OP_PUSHDATA4
-            0xae => op_checkmultisig(&mut stack, tx, txin)?, // OP_CHECKMULTISIG
-            _ => panic!("no script operator found!"),
-        };
+                0x69 => op_verify(stack, ScriptError::Verify)?, // OP_VERIFY
+                0x6a => return Err(ScriptError::OpReturn),      // OP_RETURN
+                0x6b => {
+                    // OP_TOALTSTACK
+                    match stack.pop_back() {
+                        Some(item) => alt_stack.push_back(item),
+                        None => return Err(ScriptError::InvalidStackOperation),
+                    }
+                }
+                0x6c => {
+                    // OP_FROMALTSTACK
+                    match alt_stack.pop_back() {
+                        Some(item) => stack.push_back(item),
+                        None => return Err(ScriptError::InvalidAltstackOperation),
+                    }
+                }
+                0x6d..=0x72 | 0x77 | 0x7d => op_stack_manipulation(stack, opcode)?,
+                0x73 => op_ifdup(stack)?, // OP_IFDUP
+                0x74 => op_depth(stack)?, // OP_DEPTH
+                0x75 => {
+                    // OP_DROP
+                    if stack.pop_back().is_none() {
+                        return Err(ScriptError::InvalidStackOperation);
+                    }
+                }
+                0x76 => {
+                    // OP_DUP
+                    if let Some(last) = stack.back() {
+                        stack.push_back(last.clone());
+                    } else {
+                        return Err(ScriptError::InvalidStackOperation);
+                    }
+                }
+                0x78 => op_over(stack)?, // OP_OVER
+                0x79 => op_pick_roll(stack, false, require_minimal)?, // OP_PICK
+                0x7a => op_pick_roll(stack, true, require_minimal)?, // OP_ROLL
+                0x7b => op_rot(stack)?,  // OP_ROT
+                0x7c => op_swap(stack)?, // OP_SWAP
+                0x82 => op_size(stack)?, // OP_SIZE
+                0x87 => op_equal(stack)?, // OP_EQUAL
+                0x88 => op_equalverify(stack)?, // OP_EQUALVERIFY
+                0x8b | 0x8c | 0x8f..=0x92 => op_numeric_unary(stack, opcode, require_minimal)?,
+                0x93 | 0x94 | 0x9a..=0xa4 => op_numeric_binary(stack, opcode, require_minimal)?,
+                0xa5 => op_within(stack, require_minimal)?, // OP_WITHIN
+                0xa6..=0xaa => op_hash(stack, opcode)?,     // OP_RIPEMD160 - OP_HASH256
+                0xab => {
+                    // OP_CODESEPARATOR
+                    if let Some(tapscript) = ctx.tapscript.as_mut() {
+                        tapscript.codesep_pos = opcode_pos;
+                    } else {
+                        ctx.script_code = script[index + 1..].to_vec();
+                    }
+                }
+                0xac => op_checksig(stack, ctx)?, // OP_CHECKSIG
+                0xad => {
+                    // OP_CHECKSIGVERIFY
+                    op_checksig(stack, ctx)?;
+                    op_verify(stack, ScriptError::CheckSigVerify)?;
+                }
+                0xae | 0xaf if is_tapscript => {
+                    return Err(ScriptError::TapscriptCheckMultisig);
+                }
+                0xae => op_checkmultisig(stack, ctx, &mut op_count)?, // OP_CHECKMULTISIG
+                0xaf => {
+                    // OP_CHECKMULTISIGVERIFY
+                    op_checkmultisig(stack, ctx, &mut op_count)?;
+                    op_verify(stack, ScriptError::CheckMultisigVerify)?;
+                }
+                0xb1 if ctx.flags.contains(VerifyFlags::CHECKLOCKTIMEVERIFY) => {
+                    op_checklocktimeverify(stack, ctx.tx, ctx.txin(), require_minimal)?
+                }
+                0xb2 if ctx.flags.contains(VerifyFlags::CHECKSEQUENCEVERIFY) => {
+                    op_checksequenceverify(stack, ctx.txin(), ctx.tx, require_minimal)?
+                }
+                0xb0..=0xb9 => {
+                    // OP_NOP1, OP_NOP4-10 and OP_CLTV/OP_CSV if not enabled (OP_NOP2/3)
+                    if discourage_nops {
+                        return Err(ScriptError::DiscourageUpgradableNops);
+                    }
+                }
+                0xba if is_tapscript => op_checksigadd(stack, ctx)?, // OP_CHECKSIGADD
+                _ => return Err(ScriptError::BadOpcode(opcode)),
+            };
+        }
+        if stack.len() + alt_stack.len() > MAX_STACK_SIZE {
+            return Err(ScriptError::StackSize);
+        }
         index += 1;
+        opcode_pos += 1;
     }
-    if let Some(last) = stack.pop_back() {
-        if last.is_empty() {
-            return Err("SCRIPT INVALID".into());
-        };
+    if !exec_stack.is_empty() {
+        return Err(ScriptError::UnbalancedConditional);
     }
     Ok(())
 }
+
+// executes the script and checks the resulting stack. The top element has to be true,
+// witness scripts additionally require it to be the only element left (clean stack).
+// The CLEANSTACK flag for legacy scripts is checked by verify_script after P2SH evaluation.
+// returns: Ok() if the script is valid and True
+pub fn evaluate_script(
+    script: &[u8],
+    mut stack: VecDeque<Vec<u8>>,
+    ctx: &mut ScriptContext,
+) -> Result<(), ScriptError> {
+    execute_script(script, &mut stack, ctx)?;
+
+    if ctx.sig_version != SigVersion::Base && stack.len() != 1 {
+        return Err(ScriptError::CleanStack);
+    }
+    match stack.back() {
+        Some(last) if cast_to_bool(last) => Ok(()),
+        _ => Err(ScriptError::EvalFalse),
+    }
+}
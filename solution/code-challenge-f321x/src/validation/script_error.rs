@@ -0,0 +1,254 @@
+use super::opcodes::opcode_name;
+use std::error::Error;
+use std::fmt;
+
+// Reasons for a failed script execution, named after the script errors of bitcoin core.
+// Returned by the script interpreter instead of panicking so that one malformed
+// transaction only becomes invalid.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptError {
+    EvalFalse,
+    OpReturn,
+    ScriptSize,
+    PushSize,
+    OpCount,
+    StackSize,
+    SigCount,
+    PubkeyCount,
+    Verify,
+    EqualVerify,
+    NumEqualVerify,
+    CheckSigVerify,
+    CheckMultisigVerify,
+    BadOpcode(u8),
+    DisabledOpcode(u8),
+    BadPush,
+    InvalidStackOperation,
+    InvalidAltstackOperation,
+    UnbalancedConditional,
+    ScriptNumOverflow,
+    NegativeLocktime,
+    UnsatisfiedLocktime,
+    CleanStack,
+    MinimalIf,
+    PubkeyType,
+    SchnorrSigSize,
+    SchnorrSigHashtype,
+    SchnorrSig,
+    TapscriptValidationWeight,
+    TapscriptCheckMultisig,
+    TapscriptMinimalIf,
+    TaprootWrongControlSize,
+    SigHashtype,
+    SigDer,
+    SigHighS,
+    SigNullDummy,
+    SigPushOnly,
+    SigNullFail,
+    SigFindAndDelete,
+    MinimalData,
+    ScriptNumMinimal,
+    OpCodeseparator,
+    WitnessPubkeyType,
+    DiscourageUpgradableNops,
+    DiscourageUpgradableWitnessProgram,
+    DiscourageUpgradableTaprootVersion,
+    DiscourageOpSuccess,
+    DiscourageUpgradablePubkeyType,
+    WitnessProgramWrongLength,
+    WitnessProgramWitnessEmpty,
+    WitnessProgramMismatch,
+    WitnessMalleated,
+    WitnessMalleatedP2SH,
+    WitnessUnexpected,
+    Sighash(String),
+}
+
+impl ScriptError {
+    // returns: name of the error as used in the bitcoin core test vectors (script_tests.json).
+    // Script number errors are reported as UNKNOWN_ERROR like in bitcoin core.
+    pub fn core_name(&self) -> &'static str {
+        match self {
+            ScriptError::EvalFalse => "EVAL_FALSE",
+            ScriptError::OpReturn => "OP_RETURN",
+            ScriptError::ScriptSize => "SCRIPT_SIZE",
+            ScriptError::PushSize => "PUSH_SIZE",
+            ScriptError::OpCount => "OP_COUNT",
+            ScriptError::StackSize => "STACK_SIZE",
+            ScriptError::SigCount => "SIG_COUNT",
+            ScriptError::PubkeyCount => "PUBKEY_COUNT",
+            ScriptError::Verify => "VERIFY",
+            ScriptError::EqualVerify => "EQUALVERIFY",
+            ScriptError::NumEqualVerify => "NUMEQUALVERIFY",
+            ScriptError::CheckSigVerify => "CHECKSIGVERIFY",
+            ScriptError::CheckMultisigVerify => "CHECKMULTISIGVERIFY",
+            ScriptError::BadOpcode(_) | ScriptError::BadPush => "BAD_OPCODE",
+            ScriptError::DisabledOpcode(_) => "DISABLED_OPCODE",
+            ScriptError::InvalidStackOperation => "INVALID_STACK_OPERATION",
+            ScriptError::InvalidAltstackOperation => "INVALID_ALTSTACK_OPERATION",
+            ScriptError::UnbalancedConditional => "UNBALANCED_CONDITIONAL",
+            ScriptError::ScriptNumOverflow | ScriptError::ScriptNumMinimal => "UNKNOWN_ERROR",
+            ScriptError::NegativeLocktime => "NEGATIVE_LOCKTIME",
+            ScriptError::UnsatisfiedLocktime => "UNSATISFIED_LOCKTIME",
+            ScriptError::CleanStack => "CLEANSTACK",
+            ScriptError::MinimalIf => "MINIMALIF",
+            ScriptError::PubkeyType => "PUBKEYTYPE",
+            ScriptError::SchnorrSigSize => "SCHNORR_SIG_SIZE",
+            ScriptError::SchnorrSigHashtype => "SCHNORR_SIG_HASHTYPE",
+            ScriptError::SchnorrSig => "SCHNORR_SIG",
+            ScriptError::TapscriptValidationWeight => "TAPSCRIPT_VALIDATION_WEIGHT",
+            ScriptError::TapscriptCheckMultisig => "TAPSCRIPT_CHECKMULTISIG",
+            ScriptError::TapscriptMinimalIf => "TAPSCRIPT_MINIMALIF",
+            ScriptError::TaprootWrongControlSize => "TAPROOT_WRONG_CONTROL_SIZE",
+            ScriptError::SigHashtype => "SIG_HASHTYPE",
+            ScriptError::SigDer => "SIG_DER",
+            ScriptError::SigHighS => "SIG_HIGH_S",
+            ScriptError::SigNullDummy => "SIG_NULLDUMMY",
+            ScriptError::SigPushOnly => "SIG_PUSHONLY",
+            ScriptError::SigNullFail => "NULLFAIL",
+            ScriptError::SigFindAndDelete => "SIG_FINDANDDELETE",
+            ScriptError::MinimalData => "MINIMALDATA",
+            ScriptError::OpCodeseparator => "OP_CODESEPARATOR",
+            ScriptError::WitnessPubkeyType => "WITNESS_PUBKEYTYPE",
+            ScriptError::DiscourageUpgradableNops => "DISCOURAGE_UPGRADABLE_NOPS",
+            ScriptError::DiscourageUpgradableWitnessProgram => {
+                "DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM"
+            }
+            ScriptError::DiscourageUpgradableTaprootVersion => {
+                "DISCOURAGE_UPGRADABLE_TAPROOT_VERSION"
+            }
+            ScriptError::DiscourageOpSuccess => "DISCOURAGE_OP_SUCCESS",
+            ScriptError::DiscourageUpgradablePubkeyType => "DISCOURAGE_UPGRADABLE_PUBKEYTYPE",
+            ScriptError::WitnessProgramWrongLength => "WITNESS_PROGRAM_WRONG_LENGTH",
+            ScriptError::WitnessProgramWitnessEmpty => "WITNESS_PROGRAM_WITNESS_EMPTY",
+            ScriptError::WitnessProgramMismatch => "WITNESS_PROGRAM_MISMATCH",
+            ScriptError::WitnessMalleated => "WITNESS_MALLEATED",
+            ScriptError::WitnessMalleatedP2SH => "WITNESS_MALLEATED_P2SH",
+            ScriptError::WitnessUnexpected => "WITNESS_UNEXPECTED",
+            ScriptError::Sighash(_) => "UNKNOWN_ERROR",
+        }
+    }
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScriptError::EvalFalse => write!(
+                f,
+                "Script evaluated without error but finished with a false/empty top stack element"
+            ),
+            ScriptError::OpReturn => write!(f, "OP_RETURN was encountered"),
+            ScriptError::ScriptSize => write!(f, "Script is too big"),
+            ScriptError::PushSize => write!(f, "Push value size limit exceeded"),
+            ScriptError::OpCount => write!(f, "Operation limit exceeded"),
+            ScriptError::StackSize => write!(f, "Stack size limit exceeded"),
+            ScriptError::SigCount => {
+                write!(f, "Signature count negative or greater than pubkey count")
+            }
+            ScriptError::PubkeyCount => write!(f, "Pubkey count negative or limit exceeded"),
+            ScriptError::Verify => write!(f, "Script failed an OP_VERIFY operation"),
+            ScriptError::EqualVerify => write!(f, "Script failed an OP_EQUALVERIFY operation"),
+            ScriptError::NumEqualVerify => {
+                write!(f, "Script failed an OP_NUMEQUALVERIFY operation")
+            }
+            ScriptError::CheckSigVerify => {
+                write!(f, "Script failed an OP_CHECKSIGVERIFY operation")
+            }
+            ScriptError::CheckMultisigVerify => {
+                write!(f, "Script failed an OP_CHECKMULTISIGVERIFY operation")
+            }
+            ScriptError::BadOpcode(opcode) => write!(f, "Bad opcode {}", opcode_name(*opcode)),
+            ScriptError::DisabledOpcode(opcode) => {
+                write!(f, "Disabled opcode {}", opcode_name(*opcode))
+            }
+            ScriptError::BadPush => write!(f, "Push exceeds the end of the script"),
+            ScriptError::InvalidStackOperation => {
+                write!(f, "Operation not valid with the current stack size")
+            }
+            ScriptError::InvalidAltstackOperation => {
+                write!(f, "Operation not valid with the current altstack size")
+            }
+            ScriptError::UnbalancedConditional => write!(f, "Invalid OP_IF construction"),
+            ScriptError::ScriptNumOverflow => write!(f, "Script number overflow"),
+            ScriptError::NegativeLocktime => write!(f, "Negative locktime"),
+            ScriptError::UnsatisfiedLocktime => write!(f, "Locktime requirement not satisfied"),
+            ScriptError::CleanStack => write!(f, "Stack size must be exactly one after execution"),
+            ScriptError::MinimalIf => write!(f, "OP_IF/NOTIF argument must be minimal"),
+            ScriptError::PubkeyType => {
+                write!(f, "Public key is neither compressed or uncompressed")
+            }
+            ScriptError::SchnorrSigSize => write!(f, "Invalid Schnorr signature size"),
+            ScriptError::SchnorrSigHashtype => write!(f, "Invalid Schnorr signature hash type"),
+            ScriptError::SchnorrSig => write!(f, "Invalid Schnorr signature"),
+            ScriptError::TapscriptValidationWeight => {
+                write!(
+                    f,
+                    "Too much signature validation relative to witness weight"
+                )
+            }
+            ScriptError::TapscriptCheckMultisig => {
+                write!(f, "OP_CHECKMULTISIG(VERIFY) is not available in tapscript")
+            }
+            ScriptError::TapscriptMinimalIf => {
+                write!(f, "OP_IF/NOTIF argument must be minimal in tapscript")
+            }
+            ScriptError::TaprootWrongControlSize => {
+                write!(f, "Invalid Taproot control block size")
+            }
+            ScriptError::SigHashtype => write!(f, "Signature hash type missing or not understood"),
+            ScriptError::SigDer => write!(f, "Non-canonical DER signature"),
+            ScriptError::SigHighS => {
+                write!(f, "Non-canonical signature: S value is unnecessarily high")
+            }
+            ScriptError::SigNullDummy => write!(f, "Dummy CHECKMULTISIG argument must be zero"),
+            ScriptError::SigPushOnly => write!(f, "Only push operators allowed in signatures"),
+            ScriptError::SigNullFail => write!(
+                f,
+                "Signature must be zero for failed CHECK(MULTI)SIG operation"
+            ),
+            ScriptError::SigFindAndDelete => {
+                write!(f, "Signature is found in scriptCode")
+            }
+            ScriptError::MinimalData => {
+                write!(f, "Data push larger than necessary")
+            }
+            ScriptError::ScriptNumMinimal => {
+                write!(f, "Script number is not minimally encoded")
+            }
+            ScriptError::OpCodeseparator => {
+                write!(f, "Using OP_CODESEPARATOR in non-witness script")
+            }
+            ScriptError::WitnessPubkeyType => write!(f, "Using non-compressed keys in segwit"),
+            ScriptError::DiscourageUpgradableNops => {
+                write!(f, "NOPx reserved for soft-fork upgrades")
+            }
+            ScriptError::DiscourageUpgradableWitnessProgram => {
+                write!(f, "Witness version reserved for soft-fork upgrades")
+            }
+            ScriptError::DiscourageUpgradableTaprootVersion => {
+                write!(f, "Taproot version reserved for soft-fork upgrades")
+            }
+            ScriptError::DiscourageOpSuccess => {
+                write!(f, "OP_SUCCESSx reserved for soft-fork upgrades")
+            }
+            ScriptError::DiscourageUpgradablePubkeyType => {
+                write!(f, "Public key version reserved for soft-fork upgrades")
+            }
+            ScriptError::WitnessProgramWrongLength => {
+                write!(f, "Witness program has incorrect length")
+            }
+            ScriptError::WitnessProgramWitnessEmpty => {
+                write!(f, "Witness program was passed an empty witness")
+            }
+            ScriptError::WitnessProgramMismatch => write!(f, "Witness program hash mismatch"),
+            ScriptError::WitnessMalleated => write!(f, "Witness requires empty scriptSig"),
+            ScriptError::WitnessMalleatedP2SH => {
+                write!(f, "Witness requires only-redeemscript scriptSig")
+            }
+            ScriptError::WitnessUnexpected => write!(f, "Witness provided for non-witness script"),
+            ScriptError::Sighash(reason) => write!(f, "Sighash calculation failed: {}", reason),
+        }
+    }
+}
+
+impl Error for ScriptError {}
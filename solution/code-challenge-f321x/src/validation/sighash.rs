@@ -0,0 +1,227 @@
+use super::{
+    script::split_script,
+    utils::{double_hash, get_outpoint, hash_sha256, tagged_hash, varint},
+    validate_parsing::serialize_output,
+};
+use crate::parsing::transaction_structs::Transaction;
+
+// Signature hashes (sighash) of the transaction that is signed by an input.
+// Legacy (pre segwit), BIP143 (segwit v0) and BIP341 (taproot) digests for all hash types.
+
+pub const SIGHASH_DEFAULT: u8 = 0x00;
+pub const SIGHASH_ALL: u8 = 0x01;
+pub const SIGHASH_NONE: u8 = 0x02;
+pub const SIGHASH_SINGLE: u8 = 0x03;
+pub const SIGHASH_ANYONECANPAY: u8 = 0x80;
+const OP_CODESEPARATOR: u8 = 0xab;
+
+// legacy digest returned if the input or the SIGHASH_SINGLE output doesn't exist (uint256 1)
+fn sighash_one() -> Vec<u8> {
+    let mut one = vec![0u8; 32];
+    one[0] = 0x01;
+    one
+}
+
+// removes all OP_CODESEPARATOR opcodes from the script code as done by the legacy serializer
+fn remove_codeseparators(script_code: &[u8]) -> Vec<u8> {
+    split_script(script_code)
+        .into_iter()
+        .filter(|op| op != &[OP_CODESEPARATOR])
+        .flatten()
+        .copied()
+        .collect()
+}
+
+// Serializes the transaction as signed by a legacy input (scriptsig) with the hash type
+// and returns the double sha256 digest. The script code has to be passed without the
+// signatures already (FindAndDelete). Inputs and outputs are committed according to
+// the hash type, SIGHASH_SINGLE without matching output returns 1 (consensus bug).
+// returns: 32 byte sighash as Vec<u8> or Err(reason) if a hex field is invalid
+pub fn legacy_sighash(
+    tx: &Transaction,
+    input_index: usize,
+    script_code: &[u8],
+    hash_type: u32,
+) -> Result<Vec<u8>, String> {
+    let output_type = (hash_type & 0x1f) as u8;
+    let anyonecanpay = hash_type & SIGHASH_ANYONECANPAY as u32 != 0;
+
+    if input_index >= tx.vin.len()
+        || (output_type == SIGHASH_SINGLE && input_index >= tx.vout.len())
+    {
+        return Ok(sighash_one());
+    }
+    let script_code = remove_codeseparators(script_code);
+    let mut preimage: Vec<u8> = Vec::new();
+
+    preimage.extend(tx.version.to_le_bytes());
+    if anyonecanpay {
+        preimage.extend(varint(1));
+    } else {
+        preimage.extend(varint(tx.vin.len() as u128));
+    }
+    for (index, txin) in tx.vin.iter().enumerate() {
+        if anyonecanpay && index != input_index {
+            continue;
+        }
+        preimage.extend(get_outpoint(txin)?);
+        if index == input_index {
+            preimage.extend(varint(script_code.len() as u128));
+            preimage.extend(&script_code);
+            preimage.extend(txin.sequence.to_le_bytes());
+        } else {
+            preimage.extend(varint(0)); // empty script
+            if output_type == SIGHASH_NONE || output_type == SIGHASH_SINGLE {
+                preimage.extend(0u32.to_le_bytes()); // others can update their inputs
+            } else {
+                preimage.extend(txin.sequence.to_le_bytes());
+            }
+        }
+    }
+    match output_type {
+        SIGHASH_NONE => preimage.extend(varint(0)),
+        SIGHASH_SINGLE => {
+            preimage.extend(varint(input_index as u128 + 1));
+            for _ in 0..input_index {
+                preimage.extend(u64::MAX.to_le_bytes()); // value -1
+                preimage.extend(varint(0)); // empty scriptpubkey
+            }
+            preimage.extend(serialize_output(&tx.vout[input_index])?);
+        }
+        _ => {
+            preimage.extend(varint(tx.vout.len() as u128));
+            for output in &tx.vout {
+                preimage.extend(serialize_output(output)?);
+            }
+        }
+    }
+    preimage.extend(tx.locktime.to_le_bytes());
+    preimage.extend(hash_type.to_le_bytes());
+    Ok(double_hash(&preimage))
+}
+
+// Assembles transaction commitment according to BIP143 with the passed scriptcode
+// (p2wpkh scriptcode or witness script) and the hash type.
+// returns: double sha256 digest as 32 byte Vec<u8> or Err(reason)
+pub fn segwit_v0_sighash(
+    tx: &Transaction,
+    input_index: usize,
+    script_code: &[u8],
+    hash_type: u32,
+) -> Result<Vec<u8>, String> {
+    let txin = match tx.vin.get(input_index) {
+        Some(txin) => txin,
+        None => return Err("BIP143 sighash input index out of range".to_string()),
+    };
+    let output_type = (hash_type & 0x1f) as u8;
+    let anyonecanpay = hash_type & SIGHASH_ANYONECANPAY as u32 != 0;
+    let single_or_none = output_type == SIGHASH_SINGLE || output_type == SIGHASH_NONE;
+
+    let hash_prevouts = if anyonecanpay {
+        vec![0u8; 32]
+    } else {
+        double_hash(&tx.serialize_all_outpoints()?)
+    };
+    let hash_sequence = if anyonecanpay || single_or_none {
+        vec![0u8; 32]
+    } else {
+        double_hash(&tx.serialize_all_sequences())
+    };
+    let hash_outputs = if !single_or_none {
+        double_hash(&tx.serialize_all_outputs()?)
+    } else if output_type == SIGHASH_SINGLE && input_index < tx.vout.len() {
+        double_hash(&serialize_output(&tx.vout[input_index])?)
+    } else {
+        vec![0u8; 32]
+    };
+    let mut commitment = Vec::new();
+    commitment.extend(tx.version.to_le_bytes());
+    commitment.extend(hash_prevouts);
+    commitment.extend(hash_sequence);
+    commitment.extend(get_outpoint(txin)?);
+    commitment.extend(varint(script_code.len() as u128));
+    commitment.extend(script_code);
+    commitment.extend(txin.prevout.value.to_le_bytes());
+    commitment.extend(txin.sequence.to_le_bytes());
+    commitment.extend(hash_outputs);
+    commitment.extend(tx.locktime.to_le_bytes());
+    commitment.extend(hash_type.to_le_bytes());
+    Ok(double_hash(&commitment))
+}
+
+// returns: true if hash_type is one of the types defined in BIP341
+fn is_valid_taproot_hash_type(hash_type: u8) -> bool {
+    matches!(hash_type, 0x00..=0x03 | 0x81..=0x83)
+}
+
+// Assembles the taproot signature message (SigMsg) according to BIP341 for the input at
+// input_index and hashes it with the TapSighash tag (prefixed by the sighash epoch 0x00).
+// script_path contains (tapleaf hash, codeseparator position) for the BIP342 extension.
+// returns: 32 byte sighash as Vec<u8> or Err(reason) if the hash type can't be used
+pub fn taproot_sighash(
+    tx: &Transaction,
+    input_index: usize,
+    hash_type: u8,
+    annex: Option<&[u8]>,
+    script_path: Option<(&[u8], u32)>,
+) -> Result<Vec<u8>, String> {
+    if !is_valid_taproot_hash_type(hash_type) {
+        return Err(format!("Invalid taproot sighash type: {:#04x}", hash_type));
+    }
+    let txin = match tx.vin.get(input_index) {
+        Some(txin) => txin,
+        None => return Err("Taproot sighash input index out of range".to_string()),
+    };
+    let output_type = if hash_type == SIGHASH_DEFAULT {
+        SIGHASH_ALL
+    } else {
+        hash_type & 0x03
+    };
+    let anyonecanpay = hash_type & SIGHASH_ANYONECANPAY != 0;
+    let mut sig_msg: Vec<u8> = vec![0x00]; // sighash epoch
+
+    sig_msg.push(hash_type);
+    sig_msg.extend(tx.version.to_le_bytes());
+    sig_msg.extend(tx.locktime.to_le_bytes());
+    if !anyonecanpay {
+        sig_msg.extend(hash_sha256(&tx.serialize_all_outpoints()?)); // sha_prevouts
+        sig_msg.extend(hash_sha256(&tx.serialize_all_amounts())); // sha_amounts
+        sig_msg.extend(hash_sha256(&tx.serialize_all_scriptpubkeys()?)); // sha_scriptpubkeys
+        sig_msg.extend(hash_sha256(&tx.serialize_all_sequences())); // sha_sequences
+    }
+    if output_type != SIGHASH_NONE && output_type != SIGHASH_SINGLE {
+        sig_msg.extend(hash_sha256(&tx.serialize_all_outputs()?)); // sha_outputs
+    }
+    let ext_flag = u8::from(script_path.is_some());
+    sig_msg.push(ext_flag * 2 + u8::from(annex.is_some())); // spend_type
+    if anyonecanpay {
+        let scriptpubkey = match hex::decode(&txin.prevout.scriptpubkey) {
+            Ok(bytes) => bytes,
+            Err(_) => return Err("Taproot sighash scriptpubkey hex invalid".to_string()),
+        };
+        sig_msg.extend(get_outpoint(txin)?);
+        sig_msg.extend(txin.prevout.value.to_le_bytes());
+        sig_msg.extend(varint(scriptpubkey.len() as u128));
+        sig_msg.extend(scriptpubkey);
+        sig_msg.extend(txin.sequence.to_le_bytes());
+    } else {
+        sig_msg.extend((input_index as u32).to_le_bytes());
+    }
+    if let Some(annex) = annex {
+        let mut serialized_annex = varint(annex.len() as u128);
+        serialized_annex.extend(annex);
+        sig_msg.extend(hash_sha256(&serialized_annex)); // sha_annex
+    }
+    if output_type == SIGHASH_SINGLE {
+        match tx.vout.get(input_index) {
+            Some(output) => sig_msg.extend(hash_sha256(&serialize_output(output)?)),
+            None => return Err("Taproot SIGHASH_SINGLE without matching output".to_string()),
+        }
+    }
+    if let Some((tapleaf_hash, codesep_pos)) = script_path {
+        sig_msg.extend(tapleaf_hash);
+        sig_msg.push(0x00); // key_version
+        sig_msg.extend(codesep_pos.to_le_bytes());
+    }
+    Ok(tagged_hash("TapSighash", &sig_msg))
+}
@@ -1,104 +1,254 @@
 use super::{
-    script::evaluate_script,
-    utils::{double_hash, get_outpoint, hash160},
+    opcodes::script_to_asm,
+    script::{
+        evaluate_script, execute_script, get_last_push, is_push_only, ScriptContext, SigVersion,
+        MAX_SCRIPT_ELEMENT_SIZE,
+    },
+    script_error::ScriptError,
+    taproot::verify_taproot,
+    utils::{cast_to_bool, decode_witness, hash_sha256, serialize_push},
+    verify_flags::VerifyFlags,
     ValidationResult,
 };
-use crate::parsing::transaction_structs::{Transaction, TxIn};
+use crate::parsing::transaction_structs::{InputType, Transaction, TxIn};
 use hex_literal::hex as hexlit;
-use secp256k1::{ecdsa::Signature, Message, PublicKey};
+use std::collections::VecDeque;
 
-// deserializes pubkey from p2wpkh scriptpubkey and reserializes it with the
+// Script verification of the inputs as done by bitcoin core (VerifyScript).
+// Entry is fn verify_input(), the rules applied are selected by the VerifyFlags.
+
+// serializes the 20 byte pubkey hash of a p2wpkh witness program with the
 // according opcodes to the scriptcode used in the tx commitment.
-// returns: scriptcode of the input as Vec<u8>
-fn serialize_p2wpkh_scriptcode(txin: &TxIn) -> Vec<u8> {
+// returns: scriptcode of the input as Vec<u8> (without length prefix)
+fn serialize_p2wpkh_scriptcode(pubkey_hash: &[u8]) -> Vec<u8> {
     let mut scriptcode = Vec::new();
-    let mut scriptpubkey_bytes =
-        hex::decode(&txin.prevout.scriptpubkey).expect("Error decoding scriptpubkey hex!");
-    scriptcode.extend(hexlit!("1976a914"));
-    scriptcode.extend(scriptpubkey_bytes.split_off(2));
+    scriptcode.extend(hexlit!("76a914"));
+    scriptcode.extend(pubkey_hash);
     scriptcode.extend(hexlit!("88ac"));
     scriptcode
 }
 
-// Assembles transaction commitment according to BIP143 and returns
-// the double sha256 digest as 32 byte Vec<u8>
-fn get_segwit_commitment_hash(tx: &Transaction, txin: &TxIn) -> Vec<u8> {
-    let mut commitment = Vec::new();
-    commitment.extend(tx.version.to_le_bytes());
-    commitment.extend(double_hash(&tx.serialize_all_outpoints()));
-    commitment.extend(double_hash(&tx.serialize_all_sequences()));
-    commitment.extend(get_outpoint(txin));
-    commitment.extend(serialize_p2wpkh_scriptcode(txin)); // add len prefix if p2wsh
-    commitment.extend(txin.prevout.value.to_le_bytes());
-    commitment.extend(txin.sequence.to_le_bytes());
-    commitment.extend(double_hash(&tx.serialize_all_outputs()));
-    commitment.extend(tx.locktime.to_le_bytes());
-    commitment.extend(hexlit!("01000000")); // sighash_all <- implement others later on
-    double_hash(&commitment)
+// Executes a witness v0 script (witness script or p2wpkh scriptcode) with the witness
+// elements as initial stack. Requires a clean stack with a true element left.
+// returns: Ok(()) or the ScriptError of the failed check
+fn execute_witness_script(
+    tx: &Transaction,
+    input_index: usize,
+    script: &[u8],
+    stack: &[Vec<u8>],
+    flags: VerifyFlags,
+) -> Result<(), ScriptError> {
+    if stack
+        .iter()
+        .any(|element| element.len() > MAX_SCRIPT_ELEMENT_SIZE)
+    {
+        return Err(ScriptError::PushSize);
+    }
+    let mut ctx = ScriptContext::new(tx, input_index, SigVersion::WitnessV0, flags);
+    evaluate_script(script, stack.iter().cloned().collect(), &mut ctx)
+}
+
+// returns: Some((version, program)) if the script is a witness program
+// (OP_0 - OP_16 followed by a single 2 - 40 byte push)
+pub fn get_witness_program(script: &[u8]) -> Option<(u8, &[u8])> {
+    if script.len() < 4 || script.len() > 42 || script[1] as usize != script.len() - 2 {
+        return None;
+    }
+    match script[0] {
+        0x00 => Some((0, &script[2..])),
+        0x51..=0x60 => Some((script[0] - 0x50, &script[2..])),
+        _ => None,
+    }
 }
 
-// Used to verify the signature in the p2wpkh input witness against the bip143 tx commitment hash
-// returns: ValidationResult ::Valid or ::Invalid(reason String)
-fn verify_signature_p2wpkh(msg: &[u8], pubkey: &[u8], sig: &[u8]) -> ValidationResult {
-    let sig = &sig[..sig.len() - 1]; // remove sighash byte
-    let sig = Signature::from_der(sig);
-    let mut sig = match sig {
-        Ok(value) => value,
-        Err(err) => {
-            return ValidationResult::Invalid(format!(
-                "Loading DER encoded signature failed: {}",
-                err
-            ));
+// returns: true if the scriptpubkey is a P2SH script (OP_HASH160 <20 bytes> OP_EQUAL)
+pub fn is_p2sh(scriptpubkey: &[u8]) -> bool {
+    scriptpubkey.len() == 23
+        && scriptpubkey[0] == 0xa9
+        && scriptpubkey[1] == 0x14
+        && scriptpubkey[22] == 0x87
+}
+
+// verifies the witness against the witness program: v0 with 20 byte (p2wpkh) or 32 byte
+// (p2wsh) programs and v1 with 32 byte programs (taproot, not nested in p2sh, unencumbered
+// without the TAPROOT flag). Other versions are unencumbered unless
+// DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM is set.
+// returns: Ok(()) or the ScriptError of the failed check
+fn verify_witness_program(
+    tx: &Transaction,
+    input_index: usize,
+    witness: &[Vec<u8>],
+    version: u8,
+    program: &[u8],
+    flags: VerifyFlags,
+    is_p2sh: bool,
+) -> Result<(), ScriptError> {
+    match (version, program.len()) {
+        (0, 32) => {
+            let (witness_script, stack) = match witness.split_last() {
+                Some(split) => split,
+                None => return Err(ScriptError::WitnessProgramWitnessEmpty),
+            };
+            if hash_sha256(witness_script) != program {
+                return Err(ScriptError::WitnessProgramMismatch);
+            }
+            execute_witness_script(tx, input_index, witness_script, stack, flags)
         }
-    };
-    Signature::normalize_s(&mut sig);
-    let msg: [u8; 32] = msg.try_into().expect("Commitment hash is not 32 byte!");
-    let msg = Message::from_digest(msg);
-    let pubkey = PublicKey::from_slice(pubkey).expect("Pubkey invalid!");
-    let result = sig.verify(&msg, &pubkey);
-    match result {
-        Ok(_) => ValidationResult::Valid,
-        Err(err) => ValidationResult::Invalid(format!("Signature verification failed: {}", err)),
+        (0, 20) => {
+            if witness.len() != 2 {
+                return Err(ScriptError::WitnessProgramMismatch);
+            }
+            let scriptcode = serialize_p2wpkh_scriptcode(program);
+            execute_witness_script(tx, input_index, &scriptcode, witness, flags)
+        }
+        (0, _) => Err(ScriptError::WitnessProgramWrongLength),
+        (1, 32) if !is_p2sh => {
+            if !flags.contains(VerifyFlags::TAPROOT) {
+                return Ok(());
+            }
+            verify_taproot(tx, input_index, witness, program, flags)
+        }
+        _ if flags.contains(VerifyFlags::DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM) => {
+            Err(ScriptError::DiscourageUpgradableWitnessProgram)
+        }
+        _ => Ok(()), // unknown witness programs are unencumbered (future upgrades)
     }
 }
 
-// Assembles tx commitment (BIP143), deserializes pubkey and signature from witness
-// then verifies witness pubkey and scriptpubkey equality and the signature of the given TxIn.
-// returns ValidationResult::Valid or ::Invalid(reason String)
-pub fn verify_p2wpkh(tx: &Transaction, txin: &TxIn) -> ValidationResult {
-    let msg: Vec<u8> = get_segwit_commitment_hash(tx, txin);
-    if let Some(witness) = &txin.witness {
-        let witness_sig = hex::decode(&witness[0]).expect("Witness sig decoding failed!");
-        let witness_pk = hex::decode(&witness[1]).expect("Witness pk hex decoding failed!");
-        let witness_pubkey_20bit = hash160(&witness_pk);
-        let scriptpubkey_pubkey = hex::decode(txin.prevout.scriptpubkey.clone().split_off(4))
-            .expect("Scriptpubkey pubkey decoding failed");
-        if witness_pubkey_20bit == scriptpubkey_pubkey {
-            verify_signature_p2wpkh(&msg, &witness_pk, &witness_sig)
-        } else {
-            ValidationResult::Invalid(format!(
-                "Pubkeys unequal, witness: {} | scriptpubkey: {}",
-                hex::encode(witness_pubkey_20bit),
-                hex::encode(scriptpubkey_pubkey)
-            ))
+// returns: true if the top stack element exists and is true
+fn top_is_true(stack: &VecDeque<Vec<u8>>) -> bool {
+    stack.back().is_some_and(|top| cast_to_bool(top))
+}
+
+// Verifies the scriptsig and witness of the input at input_index against the spent
+// scriptpubkey. The scriptsig is executed first, the scriptpubkey on the resulting stack.
+// Witness programs (P2WPKH, P2WSH, P2TR) and P2SH redeem scripts (BIP16, including
+// nested segwit) are evaluated afterwards if enabled by the flags.
+// returns: Ok(()) or the ScriptError of the failed check
+pub fn verify_script(
+    tx: &Transaction,
+    input_index: usize,
+    scriptsig: &[u8],
+    scriptpubkey: &[u8],
+    witness: &[Vec<u8>],
+    flags: VerifyFlags,
+) -> Result<(), ScriptError> {
+    if flags.contains(VerifyFlags::SIGPUSHONLY) && is_push_only(scriptsig) != Ok(true) {
+        return Err(ScriptError::SigPushOnly);
+    }
+    let mut ctx = ScriptContext::new(tx, input_index, SigVersion::Base, flags);
+    let mut stack: VecDeque<Vec<u8>> = VecDeque::new();
+    execute_script(scriptsig, &mut stack, &mut ctx)?;
+    let p2sh_stack = stack.clone();
+    execute_script(scriptpubkey, &mut stack, &mut ctx)?;
+    if !top_is_true(&stack) {
+        return Err(ScriptError::EvalFalse);
+    }
+
+    let mut had_witness = false;
+    if flags.contains(VerifyFlags::WITNESS) {
+        if let Some((version, program)) = get_witness_program(scriptpubkey) {
+            had_witness = true;
+            if !scriptsig.is_empty() {
+                return Err(ScriptError::WitnessMalleated);
+            }
+            verify_witness_program(tx, input_index, witness, version, program, flags, false)?;
+            stack.truncate(1); // witness programs leave a single true element
         }
-    } else {
-        ValidationResult::Invalid("No witness in transaction!".to_string())
+    }
+
+    if flags.contains(VerifyFlags::P2SH) && is_p2sh(scriptpubkey) {
+        if is_push_only(scriptsig) != Ok(true) {
+            return Err(ScriptError::SigPushOnly);
+        }
+        stack = p2sh_stack;
+        let redeem_script = match stack.pop_back() {
+            Some(redeem_script) => redeem_script,
+            None => return Err(ScriptError::EvalFalse),
+        };
+        execute_script(&redeem_script, &mut stack, &mut ctx)?;
+        if !top_is_true(&stack) {
+            return Err(ScriptError::EvalFalse);
+        }
+        if flags.contains(VerifyFlags::WITNESS) {
+            if let Some((version, program)) = get_witness_program(&redeem_script) {
+                had_witness = true;
+                if scriptsig != serialize_push(&redeem_script) {
+                    return Err(ScriptError::WitnessMalleatedP2SH);
+                }
+                verify_witness_program(tx, input_index, witness, version, program, flags, true)?;
+                stack.truncate(1);
+            }
+        }
+    }
+
+    if flags.contains(VerifyFlags::CLEANSTACK) && stack.len() != 1 {
+        return Err(ScriptError::CleanStack);
+    }
+    if flags.contains(VerifyFlags::WITNESS) && !had_witness && !witness.is_empty() {
+        return Err(ScriptError::WitnessUnexpected);
+    }
+    Ok(())
+}
+
+// returns: scriptsig of the input as bytes (empty if there is none) or Err(reason)
+// if the scriptsig hex is invalid
+pub fn decode_scriptsig(txin: &TxIn) -> Result<Vec<u8>, String> {
+    match &txin.scriptsig {
+        Some(scriptsig) => {
+            hex::decode(scriptsig).map_err(|err| format!("Scriptsig hex invalid: {}", err))
+        }
+        None => Ok(Vec::new()),
     }
 }
 
-// Assembles the evaluation script from scriptsig and scriptpubkey and calls validating function
+// compares the redeem script (last scriptsig push) and the witness script (last witness
+// element of p2wsh spends) with the inner_redeemscript_asm / inner_witnessscript_asm
+// fields of the json to make sure they describe the verified scripts
+// returns: Ok(()) or Err(reason) on a mismatch
+fn verify_inner_script_asm(
+    txin: &TxIn,
+    scriptsig: &[u8],
+    witness: &[Vec<u8>],
+) -> Result<(), String> {
+    if let Some(redeemscript_asm) = &txin.inner_redeemscript_asm {
+        let redeem_script = get_last_push(scriptsig).unwrap_or_default();
+        if script_to_asm(&redeem_script).as_ref() != Ok(redeemscript_asm) {
+            return Err("Redeem script does not match inner_redeemscript_asm".to_string());
+        }
+    }
+    if let Some(witnessscript_asm) = &txin.inner_witnessscript_asm {
+        if matches!(txin.in_type, InputType::P2WSH | InputType::P2SH) {
+            let witness_script = witness.last().cloned().unwrap_or_default();
+            if script_to_asm(&witness_script).as_ref() != Ok(witnessscript_asm) {
+                return Err("Witness script does not match inner_witnessscript_asm".to_string());
+            }
+        }
+    }
+    Ok(())
+}
+
+// Decodes scriptsig, witness and spent scriptpubkey of the input at input_index
+// and verifies them with verify_script under the passed flags.
 // returns ValidationResult::Valid or ::Invalid(reason String)
-pub fn verify_p2pkh(tx: &Transaction, txin: &TxIn) -> ValidationResult {
-    let mut script: Vec<u8> = Vec::new();
-    script.extend(
-        hex::decode(txin.scriptsig.as_ref().expect("p2pkh scriptsig empty"))
-            .expect("verify p2pkh scriptsig hex decode failed"),
-    );
-    script.extend(
-        hex::decode(&txin.prevout.scriptpubkey).expect("p2pkh scriptpubkey hex decode failed"),
-    );
-    match evaluate_script(script, txin, tx) {
+pub fn verify_input(tx: &Transaction, input_index: usize, flags: VerifyFlags) -> ValidationResult {
+    let txin = &tx.vin[input_index];
+    let scriptsig = match decode_scriptsig(txin) {
+        Ok(scriptsig) => scriptsig,
+        Err(err) => return ValidationResult::Invalid(err),
+    };
+    let scriptpubkey = match hex::decode(&txin.prevout.scriptpubkey) {
+        Ok(scriptpubkey) => scriptpubkey,
+        Err(err) => return ValidationResult::Invalid(format!("Scriptpubkey hex invalid: {}", err)),
+    };
+    let witness = match decode_witness(txin) {
+        Ok(witness) => witness,
+        Err(err) => return ValidationResult::Invalid(err),
+    };
+    if let Err(err) = verify_inner_script_asm(txin, &scriptsig, &witness) {
+        return ValidationResult::Invalid(err);
+    }
+    match verify_script(tx, input_index, &scriptsig, &scriptpubkey, &witness, flags) {
         Ok(_) => ValidationResult::Valid,
         Err(err) => ValidationResult::Invalid(err.to_string()),
     }
@@ -0,0 +1,138 @@
+use super::{
+    script::{get_last_push, is_push_only, split_script},
+    signature_verification::{decode_scriptsig, get_witness_program, is_p2sh},
+    utils::decode_witness,
+    ValidationResult,
+};
+use crate::parsing::transaction_structs::Transaction;
+
+// Signature operation counting as done by bitcoin core (GetTransactionSigOpCost).
+// Legacy and p2sh sigops are scaled by the witness scale factor, witness sigops count once.
+
+pub const MAX_BLOCK_SIGOPS_COST: u64 = 80000;
+// standardness limit of a single transaction (a fifth of the block limit)
+pub const MAX_STANDARD_TX_SIGOPS_COST: u64 = MAX_BLOCK_SIGOPS_COST / 5;
+// weight units a sigop is worth when calculating the sigop-adjusted vsize
+const DEFAULT_BYTES_PER_SIGOP: u64 = 20;
+const WITNESS_SCALE_FACTOR: u64 = 4;
+const MAX_PUBKEYS_PER_MULTISIG: u64 = 20;
+
+// counts OP_CHECKSIG(VERIFY) as 1 and OP_CHECKMULTISIG(VERIFY) as 20 sigops. If accurate is
+// true a multisig preceded by OP_1 - OP_16 counts as the number of pubkeys instead.
+// returns: sigop count of the script
+pub fn count_script_sigops(script: &[u8], accurate: bool) -> u64 {
+    let mut sigops: u64 = 0;
+    let mut last_opcode: u8 = 0xff; // OP_INVALIDOPCODE
+
+    for operation in split_script(script) {
+        let opcode = operation[0];
+        match opcode {
+            0xac | 0xad => sigops += 1,
+            0xae | 0xaf => {
+                if accurate && (0x51..=0x60).contains(&last_opcode) {
+                    sigops += (last_opcode - 0x50) as u64;
+                } else {
+                    sigops += MAX_PUBKEYS_PER_MULTISIG;
+                }
+            }
+            _ => {}
+        }
+        last_opcode = opcode;
+    }
+    sigops
+}
+
+// returns: sigops of all scriptsigs and output scriptpubkeys (inaccurate counting)
+// or Err(reason) if a script hex is invalid
+pub fn get_legacy_sigop_count(tx: &Transaction) -> Result<u64, String> {
+    let mut sigops: u64 = 0;
+
+    for txin in &tx.vin {
+        sigops += count_script_sigops(&decode_scriptsig(txin)?, false);
+    }
+    for txout in &tx.vout {
+        let scriptpubkey = hex::decode(txout.scriptpubkey.as_deref().unwrap_or_default())
+            .map_err(|err| format!("Scriptpubkey hex invalid: {}", err))?;
+        sigops += count_script_sigops(&scriptpubkey, false);
+    }
+    Ok(sigops)
+}
+
+// returns: sigops of the redeem script (last scriptsig push) of a p2sh spend, 0 otherwise
+fn get_p2sh_sigop_count(scriptsig: &[u8], scriptpubkey: &[u8]) -> u64 {
+    if !is_p2sh(scriptpubkey) || is_push_only(scriptsig) != Ok(true) {
+        return 0;
+    }
+    match get_last_push(scriptsig) {
+        Some(redeem_script) => count_script_sigops(&redeem_script, true),
+        None => 0,
+    }
+}
+
+// returns: sigops of a v0 witness program spend (1 for p2wpkh, the witness script sigops
+// for p2wsh), also for witness programs nested in p2sh. Taproot sigops are limited by the
+// validation weight instead and count 0.
+fn get_witness_sigop_count(scriptsig: &[u8], scriptpubkey: &[u8], witness: &[Vec<u8>]) -> u64 {
+    let redeem_script;
+    let program_script = if is_p2sh(scriptpubkey) {
+        if is_push_only(scriptsig) != Ok(true) {
+            return 0;
+        }
+        redeem_script = get_last_push(scriptsig).unwrap_or_default();
+        &redeem_script
+    } else {
+        scriptpubkey
+    };
+
+    match get_witness_program(program_script) {
+        Some((0, program)) if program.len() == 20 => 1,
+        Some((0, program)) if program.len() == 32 => match witness.last() {
+            Some(witness_script) => count_script_sigops(witness_script, true),
+            None => 0,
+        },
+        _ => 0,
+    }
+}
+
+// calculates the sigop cost of the transaction like bitcoin core with p2sh and witness
+// sigops counted (the prevouts of the inputs have to be set)
+// returns: sigop cost as u64 or Err(reason) if a script or witness hex is invalid
+pub fn get_transaction_sigop_cost(tx: &Transaction) -> Result<u64, String> {
+    let mut sigop_cost = get_legacy_sigop_count(tx)? * WITNESS_SCALE_FACTOR;
+
+    if tx.vin.iter().any(|txin| txin.is_coinbase) {
+        return Ok(sigop_cost);
+    }
+    for txin in &tx.vin {
+        let scriptsig = decode_scriptsig(txin)?;
+        let scriptpubkey = hex::decode(&txin.prevout.scriptpubkey)
+            .map_err(|err| format!("Scriptpubkey hex invalid: {}", err))?;
+        let witness = decode_witness(txin)?;
+        sigop_cost += get_p2sh_sigop_count(&scriptsig, &scriptpubkey) * WITNESS_SCALE_FACTOR;
+        sigop_cost += get_witness_sigop_count(&scriptsig, &scriptpubkey, &witness);
+    }
+    Ok(sigop_cost)
+}
+
+// calculates the sigop cost and checks it against the standardness limit
+// returns: ValidationResult::Valid or ::Invalid(reason String)
+pub fn validate_and_set_sigops_cost(tx: &mut Transaction) -> ValidationResult {
+    let sigops_cost = match get_transaction_sigop_cost(tx) {
+        Ok(sigops_cost) => sigops_cost,
+        Err(reason) => return ValidationResult::Invalid(reason),
+    };
+    if sigops_cost > MAX_STANDARD_TX_SIGOPS_COST {
+        return ValidationResult::Invalid("Transaction sigop cost too high!".to_string());
+    }
+    tx.meta.sigops_cost = sigops_cost;
+    ValidationResult::Valid
+}
+
+// virtual size like GetVirtualTransactionSize of bitcoin core: transactions with many sigops
+// compared to their weight count as larger, so they rank lower by feerate
+// returns: sigop-adjusted vsize in vbytes
+pub fn get_sigop_adjusted_vsize(weight: u64, sigops_cost: u64) -> u64 {
+    weight
+        .max(sigops_cost * DEFAULT_BYTES_PER_SIGOP)
+        .div_ceil(WITNESS_SCALE_FACTOR)
+}
@@ -0,0 +1,239 @@
+use super::{
+    script::{
+        contains_op_success, evaluate_script, ScriptContext, SigVersion, TapscriptData,
+        MAX_SCRIPT_ELEMENT_SIZE,
+    },
+    script_error::ScriptError,
+    sighash::{taproot_sighash, SIGHASH_DEFAULT},
+    utils::{tagged_hash, varint},
+    verify_flags::VerifyFlags,
+};
+use crate::parsing::transaction_structs::Transaction;
+use secp256k1::{schnorr::Signature, Message, Parity, Scalar, XOnlyPublicKey, SECP256K1};
+use std::collections::VecDeque;
+
+// Implementation of taproot (segwit v1) verification according to BIP340/341.
+// Entry is fn verify_taproot()
+
+const ANNEX_TAG: u8 = 0x50;
+const TAPSCRIPT_LEAF_VERSION: u8 = 0xc0;
+const CONTROL_BLOCK_BASE_SIZE: usize = 33;
+const CONTROL_BLOCK_NODE_SIZE: usize = 32;
+const CONTROL_BLOCK_MAX_NODES: usize = 128;
+const VALIDATION_WEIGHT_OFFSET: i64 = 50;
+
+// splits the annex off the witness stack. The annex is the last witness element
+// if there are at least two elements and it starts with 0x50.
+// returns: (remaining witness stack, Option<annex>)
+pub fn split_annex(witness: &[Vec<u8>]) -> (&[Vec<u8>], Option<&[u8]>) {
+    if witness.len() >= 2 {
+        if let Some(last) = witness.last() {
+            if last.first() == Some(&ANNEX_TAG) {
+                return (&witness[..witness.len() - 1], Some(last));
+            }
+        }
+    }
+    (witness, None)
+}
+
+// splits a 64 or 65 byte BIP340 signature into signature and hash type.
+// A 65 byte signature with explicit SIGHASH_DEFAULT (0x00) is invalid.
+// returns: (64 byte signature, hash type) or ScriptError
+pub fn split_schnorr_signature(signature: &[u8]) -> Result<(&[u8], u8), ScriptError> {
+    match signature.len() {
+        64 => Ok((signature, SIGHASH_DEFAULT)),
+        65 if signature[64] != SIGHASH_DEFAULT => Ok((&signature[..64], signature[64])),
+        65 => Err(ScriptError::SchnorrSigHashtype),
+        _ => Err(ScriptError::SchnorrSigSize),
+    }
+}
+
+// Verifies a BIP340 schnorr signature against the 32 byte message and x-only pubkey
+// returns: Ok(()) if the signature is valid, Err(ScriptError::SchnorrSig) if not
+pub fn verify_schnorr(msg: &[u8], pubkey: &[u8], sig: &[u8]) -> Result<(), ScriptError> {
+    let sig = Signature::from_slice(sig).map_err(|_| ScriptError::SchnorrSig)?;
+    let pubkey = XOnlyPublicKey::from_slice(pubkey).map_err(|_| ScriptError::SchnorrSig)?;
+    let msg: [u8; 32] = match msg.try_into() {
+        Ok(msg) => msg,
+        Err(_) => {
+            return Err(ScriptError::Sighash(
+                "Taproot sighash is not 32 byte".to_string(),
+            ))
+        }
+    };
+    SECP256K1
+        .verify_schnorr(&sig, &Message::from_digest(msg), &pubkey)
+        .map_err(|_| ScriptError::SchnorrSig)
+}
+
+// Verifies a key path spend: the single witness element is a schnorr signature
+// of the BIP341 sighash, checked against the output key (witness program).
+// returns: Ok(()) or the ScriptError of the failed check
+fn verify_key_path(
+    tx: &Transaction,
+    input_index: usize,
+    output_key: &[u8],
+    signature: &[u8],
+    annex: Option<&[u8]>,
+) -> Result<(), ScriptError> {
+    let (signature, hash_type) = split_schnorr_signature(signature)?;
+    let sighash = taproot_sighash(tx, input_index, hash_type, annex, None)
+        .map_err(|_| ScriptError::SchnorrSigHashtype)?;
+    verify_schnorr(&sighash, output_key, signature)
+}
+
+// returns: TapLeaf tagged hash of the leaf version and the length prefixed script
+fn get_tapleaf_hash(leaf_version: u8, script: &[u8]) -> Vec<u8> {
+    let mut leaf: Vec<u8> = vec![leaf_version];
+    leaf.extend(varint(script.len() as u128));
+    leaf.extend(script);
+    tagged_hash("TapLeaf", &leaf)
+}
+
+// Parses the control block, computes the merkle root from the tapleaf hash and the
+// merkle path and checks that the output key is the internal key tweaked with this root.
+// returns: Ok(()) if the script is committed to in the output key
+fn verify_taproot_commitment(
+    control_block: &[u8],
+    output_key: &[u8],
+    tapleaf_hash: &[u8],
+) -> Result<(), String> {
+    let mut node: Vec<u8> = tapleaf_hash.to_vec();
+
+    for path_element in control_block[CONTROL_BLOCK_BASE_SIZE..].chunks(CONTROL_BLOCK_NODE_SIZE) {
+        let mut branch: Vec<u8> = Vec::with_capacity(64);
+        if node.as_slice() < path_element {
+            branch.extend(&node);
+            branch.extend(path_element);
+        } else {
+            branch.extend(path_element);
+            branch.extend(&node);
+        }
+        node = tagged_hash("TapBranch", &branch);
+    }
+    let internal_key = &control_block[1..CONTROL_BLOCK_BASE_SIZE];
+    let mut tweak_preimage: Vec<u8> = internal_key.to_vec();
+    tweak_preimage.extend(&node);
+    let tweak: [u8; 32] = tagged_hash("TapTweak", &tweak_preimage)
+        .try_into()
+        .expect("Tagged hash is not 32 byte!");
+
+    let internal_key = match XOnlyPublicKey::from_slice(internal_key) {
+        Ok(key) => key,
+        Err(err) => return Err(format!("Invalid internal key in control block: {}", err)),
+    };
+    let output_key = match XOnlyPublicKey::from_slice(output_key) {
+        Ok(key) => key,
+        Err(err) => return Err(format!("Invalid taproot output key: {}", err)),
+    };
+    let tweak = match Scalar::from_be_bytes(tweak) {
+        Ok(tweak) => tweak,
+        Err(_) => return Err("Taproot tweak out of range".to_string()),
+    };
+    let parity = Parity::from_u8(control_block[0] & 0x01).expect("Parity bit is 0 or 1");
+    if internal_key.tweak_add_check(SECP256K1, &output_key, parity, tweak) {
+        Ok(())
+    } else {
+        Err("Taproot commitment to script does not match output key".to_string())
+    }
+}
+
+// returns: serialized size of the witness (element count, element lengths and elements)
+fn get_witness_size(witness: &[Vec<u8>]) -> usize {
+    let mut size = varint(witness.len() as u128).len();
+    for element in witness {
+        size += varint(element.len() as u128).len() + element.len();
+    }
+    size
+}
+
+// Verifies a script path spend: checks the control block and the commitment of the
+// leaf script in the output key, then executes the leaf script as tapscript (BIP342)
+// with the remaining witness elements as initial stack. Unknown leaf versions and
+// scripts containing OP_SUCCESSx are valid unless discouraged by the flags.
+// returns: Ok(()) or the ScriptError of the failed check
+fn verify_script_path(
+    tx: &Transaction,
+    input_index: usize,
+    output_key: &[u8],
+    stack: &[Vec<u8>],
+    annex: Option<&[u8]>,
+    witness_size: usize,
+    flags: VerifyFlags,
+) -> Result<(), ScriptError> {
+    let control_block = &stack[stack.len() - 1];
+    let script = &stack[stack.len() - 2];
+    let initial_stack = &stack[..stack.len() - 2];
+
+    if control_block.len() < CONTROL_BLOCK_BASE_SIZE
+        || control_block.len()
+            > CONTROL_BLOCK_BASE_SIZE + CONTROL_BLOCK_NODE_SIZE * CONTROL_BLOCK_MAX_NODES
+        || !(control_block.len() - CONTROL_BLOCK_BASE_SIZE).is_multiple_of(CONTROL_BLOCK_NODE_SIZE)
+    {
+        return Err(ScriptError::TaprootWrongControlSize);
+    }
+    let leaf_version = control_block[0] & 0xfe;
+    let tapleaf_hash = get_tapleaf_hash(leaf_version, script);
+    if verify_taproot_commitment(control_block, output_key, &tapleaf_hash).is_err() {
+        return Err(ScriptError::WitnessProgramMismatch);
+    }
+    if leaf_version != TAPSCRIPT_LEAF_VERSION {
+        // unknown leaf versions are unencumbered (future upgrades)
+        if flags.contains(VerifyFlags::DISCOURAGE_UPGRADABLE_TAPROOT_VERSION) {
+            return Err(ScriptError::DiscourageUpgradableTaprootVersion);
+        }
+        return Ok(());
+    }
+    if contains_op_success(script)? {
+        if flags.contains(VerifyFlags::DISCOURAGE_OP_SUCCESS) {
+            return Err(ScriptError::DiscourageOpSuccess);
+        }
+        return Ok(());
+    }
+    if initial_stack
+        .iter()
+        .any(|element| element.len() > MAX_SCRIPT_ELEMENT_SIZE)
+    {
+        return Err(ScriptError::PushSize);
+    }
+    let mut ctx = ScriptContext::new(tx, input_index, SigVersion::Tapscript, flags);
+    ctx.tapscript = Some(TapscriptData {
+        leaf_hash: tapleaf_hash,
+        annex: annex.map(|annex| annex.to_vec()),
+        codesep_pos: u32::MAX,
+        validation_weight_left: witness_size as i64 + VALIDATION_WEIGHT_OFFSET,
+    });
+    let initial_stack: VecDeque<Vec<u8>> = initial_stack.iter().cloned().collect();
+    evaluate_script(script, initial_stack, &mut ctx)
+}
+
+// Verifies the witness of a segwit v1 (32 byte program) input: removes the annex and
+// verifies the spend as key path (one witness element) or script path spend.
+// returns: Ok(()) or the ScriptError of the failed check
+pub fn verify_taproot(
+    tx: &Transaction,
+    input_index: usize,
+    witness: &[Vec<u8>],
+    output_key: &[u8],
+    flags: VerifyFlags,
+) -> Result<(), ScriptError> {
+    if witness.is_empty() {
+        return Err(ScriptError::WitnessProgramWitnessEmpty);
+    }
+    let (stack, annex) = split_annex(witness);
+
+    if stack.len() == 1 {
+        verify_key_path(tx, input_index, output_key, &stack[0], annex)
+    } else {
+        let witness_size = get_witness_size(witness);
+        verify_script_path(
+            tx,
+            input_index,
+            output_key,
+            stack,
+            annex,
+            witness_size,
+            flags,
+        )
+    }
+}
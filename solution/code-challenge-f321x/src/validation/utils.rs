@@ -1,18 +1,19 @@
 use crate::parsing::transaction_structs::TxIn;
-use num_traits::ToPrimitive;
 use ripemd::Ripemd160;
+use sha1::Sha1;
 use sha2::{Digest, Sha256};
 
 // returns: outpoint (rev txid bytes + index) of TxIn as serialized byte Vec<u8>
-pub fn get_outpoint(input: &TxIn) -> Vec<u8> {
+// or Err(reason) if the txid hex is invalid
+pub fn get_outpoint(input: &TxIn) -> Result<Vec<u8>, String> {
     let mut outpoint: Vec<u8> = hex::decode(&input.txid)
-        .expect("Failed to decode transaction ID")
+        .map_err(|err| format!("Outpoint txid hex invalid: {}", err))?
         .into_iter()
         .rev()
         .collect();
     let outpoint_index = input.vout.to_le_bytes();
     outpoint.extend_from_slice(&outpoint_index);
-    outpoint
+    Ok(outpoint)
 }
 
 // returns: sha256 digest of passed byte slice as Vec<u8>
@@ -35,13 +36,74 @@ pub fn double_hash(preimage: &[u8]) -> Vec<u8> {
     digest
 }
 
+// BIP340 tagged hash: sha256(sha256(tag) || sha256(tag) || msg)
+// returns: 32 byte digest as Vec<u8>
+pub fn tagged_hash(tag: &str, msg: &[u8]) -> Vec<u8> {
+    let tag_hash = hash_sha256(tag.as_bytes());
+    let mut preimage: Vec<u8> = Vec::with_capacity(64 + msg.len());
+
+    preimage.extend(&tag_hash);
+    preimage.extend(&tag_hash);
+    preimage.extend(msg);
+    hash_sha256(&preimage)
+}
+
+// returns: ripemd160 digest of passed byte slice as Vec<u8>
+pub fn hash_ripemd160(preimage: &[u8]) -> Vec<u8> {
+    let mut hasher = Ripemd160::new();
+    hasher.update(preimage);
+    hasher.finalize().to_vec()
+}
+
+// returns: sha1 digest of passed byte slice as Vec<u8>
+pub fn hash_sha1(preimage: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha1::new();
+    hasher.update(preimage);
+    hasher.finalize().to_vec()
+}
+
 // applies sha256 and ripemd160 hash on passed byte slice
 // returns: 20 byte hash as Vec<u8>
 pub fn hash160(preimage: &[u8]) -> Vec<u8> {
-    let preimage = hash_sha256(preimage);
-    let mut hasher = Ripemd160::new();
-    hasher.update(&preimage);
-    hasher.finalize().to_vec()
+    hash_ripemd160(&hash_sha256(preimage))
+}
+
+// hex decodes all witness elements of the passed TxIn
+// returns: Vec of witness stack elements as Vec<u8> (empty if there is no witness)
+pub fn decode_witness(txin: &TxIn) -> Result<Vec<Vec<u8>>, String> {
+    let mut witness_stack: Vec<Vec<u8>> = Vec::new();
+
+    if let Some(witness) = &txin.witness {
+        for element in witness {
+            match hex::decode(element) {
+                Ok(bytes) => witness_stack.push(bytes),
+                Err(err) => return Err(format!("Witness hex decoding failed: {}", err)),
+            }
+        }
+    }
+    Ok(witness_stack)
+}
+
+// serializes data as script push using the smallest push opcode
+// (OP_0, OP_PUSHBYTES_X, OP_PUSHDATA1/2/4)
+// returns: push opcode followed by the data as Vec<u8>
+pub fn serialize_push(data: &[u8]) -> Vec<u8> {
+    let mut push: Vec<u8> = Vec::new();
+
+    if data.len() < 0x4c {
+        push.push(data.len() as u8);
+    } else if data.len() <= 0xff {
+        push.push(0x4c);
+        push.push(data.len() as u8);
+    } else if data.len() <= 0xffff {
+        push.push(0x4d);
+        push.extend((data.len() as u16).to_le_bytes());
+    } else {
+        push.push(0x4e);
+        push.extend((data.len() as u32).to_le_bytes());
+    }
+    push.extend(data);
+    push
 }
 
 // converts a given u128 integer to a little endian Vec<u8>
@@ -66,12 +128,85 @@ pub fn varint(n: u128) -> Vec<u8> {
     }
 }
 
+// decodes the varint at the start of the bytes (inverse of varint()).
+// Non canonical encodings (value fits in a shorter varint) are rejected like in bitcoin core.
+// returns: (decoded value, length of the varint in bytes) or Err(reason)
+pub fn decode_varint(bytes: &[u8]) -> Result<(u64, usize), String> {
+    let prefix = match bytes.first() {
+        Some(prefix) => *prefix,
+        None => return Err("Varint: no bytes to decode".to_string()),
+    };
+    let (len, min_value): (usize, u64) = match prefix {
+        0xfd => (2, 0xfd),
+        0xfe => (4, 0x10000),
+        0xff => (8, 0x100000000),
+        _ => return Ok((prefix as u64, 1)),
+    };
+    if bytes.len() < len + 1 {
+        return Err("Varint: not enough bytes to decode".to_string());
+    }
+    let mut value_bytes = [0u8; 8];
+    value_bytes[..len].copy_from_slice(&bytes[1..len + 1]);
+    let value = u64::from_le_bytes(value_bytes);
+    if value < min_value {
+        return Err("Varint: non canonical encoding".to_string());
+    }
+    Ok((value, len + 1))
+}
+
 // When used as numbers, byte vectors are interpreted as little-endian variable-length integers with the most significant
 // bit determining the sign of the integer. Thus 0x81 represents -1. 0x80 is another representation of zero
 // (so called negative 0). Positive 0 is represented by a null-length vector.
 // Byte vectors are interpreted as Booleans where
 // False is represented by any representation of zero and True is represented by any representation of non-zero.
-pub fn decode_num(number: &[u8]) -> i128 {
-    let number = num_bigint::BigInt::from_signed_bytes_le(number);
-    number.to_i128().expect("number outside of i128 scope")
+// Decodes such a script number with at most max_len bytes as used by the arithmetic opcodes.
+// returns: Ok(i64) or Err(reason) if the number is too long
+pub fn decode_script_num(number: &[u8], max_len: usize) -> Result<i64, &'static str> {
+    if number.len() > max_len {
+        return Err("Script number overflow");
+    }
+    if number.is_empty() {
+        return Ok(0);
+    }
+    let mut result: i64 = 0;
+    for (i, byte) in number.iter().enumerate() {
+        result |= (*byte as i64) << (8 * i);
+    }
+    let sign_bit: i64 = 0x80 << (8 * (number.len() - 1));
+    if result & sign_bit != 0 {
+        return Ok(-(result & !sign_bit));
+    }
+    Ok(result)
+}
+
+// encodes an integer as minimally encoded script number
+// returns: Vec<u8> (empty Vec for 0)
+pub fn encode_script_num(number: i64) -> Vec<u8> {
+    let mut encoded: Vec<u8> = Vec::new();
+    let negative = number < 0;
+    let mut absolute = number.unsigned_abs();
+
+    while absolute > 0 {
+        encoded.push((absolute & 0xff) as u8);
+        absolute >>= 8;
+    }
+    if let Some(last) = encoded.last_mut() {
+        if *last & 0x80 != 0 {
+            encoded.push(if negative { 0x80 } else { 0x00 });
+        } else if negative {
+            *last |= 0x80;
+        }
+    }
+    encoded
+}
+
+// interprets a stack element as boolean. Any representation of zero
+// (including negative zero 0x80) is false, everything else is true.
+pub fn cast_to_bool(element: &[u8]) -> bool {
+    for (i, byte) in element.iter().enumerate() {
+        if *byte != 0 {
+            return !(i == element.len() - 1 && *byte == 0x80);
+        }
+    }
+    false
 }
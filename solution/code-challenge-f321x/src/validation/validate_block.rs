@@ -0,0 +1,366 @@
+use super::{
+    script::get_last_push,
+    signature_verification::{decode_scriptsig, verify_input},
+    sigops::{get_transaction_sigop_cost, MAX_BLOCK_SIGOPS_COST},
+    utils::{decode_script_num, decode_witness, double_hash, varint},
+    validate_parsing::{
+        assemble_txid_preimage, serialize_transaction, validate_txid_hash_filename,
+    },
+    validate_values::{check_transaction, validate_values_and_set_fee},
+    verify_flags::VerifyFlags,
+    ValidationResult,
+};
+use crate::mining::{compact_target::CompactTarget, get_merkle_root};
+use crate::parsing::{
+    parse_transactions_from_dir,
+    raw_transaction::{deserialize_block, deserialize_transaction, new_txout, UtxoSet},
+    transaction_structs::Transaction,
+};
+use hex_literal::hex as hexlit;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+
+// Independent validation of a mined block (the inverse of mining::mine_block).
+// Checks the block like a node would with the outputs spent by the mempool as utxo set:
+// proof of work, merkle root, witness commitment, coinbase reward, weight and sigops
+// limits, transaction order (parents before children), double spends and the scripts.
+// Entry is fn validate_block() or validate_block_file() for block files.
+
+pub const MAX_BLOCK_WEIGHT: u64 = 4000000;
+const WITNESS_COMMITMENT_HEADER: [u8; 6] = hexlit!("6a24aa21a9ed");
+
+// calculates the block subsidy of the block height (50 btc halving every 210000 blocks)
+// returns: subsidy in satoshi
+pub fn get_block_subsidy(height: u64) -> u64 {
+    let halvings = height / 210000;
+    if halvings >= 64 {
+        return 0;
+    }
+    5000000000 >> halvings
+}
+
+// returns: true if the HASH256 of the header is below or equal the target of its nBits
+fn check_proof_of_work(header: &[u8]) -> bool {
+    let bits = u32::from_le_bytes(header[72..76].try_into().expect("Header has 80 bytes"));
+    CompactTarget(bits).is_met_by(&double_hash(header))
+}
+
+// returns: txids or wtxids of the transactions in natural byte order (as hashed in the tree)
+fn get_natural_ids(transactions: &[Transaction], witness: bool) -> Vec<Vec<u8>> {
+    let mut ids: Vec<Vec<u8>> = Vec::new();
+
+    for tx in transactions {
+        let id_hex = if witness {
+            &tx.meta.wtxid_hex
+        } else {
+            &tx.meta.txid_hex
+        };
+        let id: Vec<u8> = hex::decode(id_hex).expect("txid hex decode failed");
+        ids.push(id.into_iter().rev().collect());
+    }
+    ids
+}
+
+// checks the witness commitment (last coinbase output starting with the commitment header)
+// against the wtxid merkle root and the witness reserved value of the coinbase.
+// Blocks without commitment must not contain witness data.
+// returns: ValidationResult
+fn check_witness_commitment(transactions: &[Transaction]) -> ValidationResult {
+    let coinbase = &transactions[0];
+    let commitment = coinbase.vout.iter().rev().find_map(|txout| {
+        let scriptpubkey = hex::decode(txout.scriptpubkey.as_deref().unwrap_or_default()).ok()?;
+        (scriptpubkey.len() >= 38 && scriptpubkey.starts_with(&WITNESS_COMMITMENT_HEADER))
+            .then(|| scriptpubkey[6..38].to_vec())
+    });
+    let commitment = match commitment {
+        Some(commitment) => commitment,
+        None if transactions
+            .iter()
+            .any(|tx| tx.vin.iter().any(|txin| txin.witness.is_some())) =>
+        {
+            return ValidationResult::Invalid("unexpected-witness".to_string())
+        }
+        None => return ValidationResult::Valid,
+    };
+    let reserved_value = match decode_witness(&coinbase.vin[0]) {
+        Ok(witness) if witness.len() == 1 && witness[0].len() == 32 => witness[0].clone(),
+        _ => return ValidationResult::Invalid("bad-witness-nonce-size".to_string()),
+    };
+    let mut wtxids = get_natural_ids(transactions, true);
+    wtxids[0] = vec![0; 32]; // the coinbase wtxid is replaced by zeros
+    let mut preimage = get_merkle_root(&wtxids);
+    preimage.extend(reserved_value);
+    if double_hash(&preimage) != commitment {
+        return ValidationResult::Invalid("bad-witness-merkle-match".to_string());
+    }
+    ValidationResult::Valid
+}
+
+// decodes the block height the coinbase scriptsig starts with (BIP34)
+// returns: height or None if the scriptsig does not start with a height push
+fn get_coinbase_height(coinbase: &Transaction) -> Option<u64> {
+    let scriptsig = decode_scriptsig(&coinbase.vin[0]).ok()?;
+    let first_opcode = *scriptsig.first()?;
+
+    if first_opcode == 0x00 {
+        return Some(0);
+    }
+    if (0x51..=0x60).contains(&first_opcode) {
+        return Some((first_opcode - 0x50) as u64);
+    }
+    if first_opcode > 0x4b {
+        return None;
+    }
+    let push = get_last_push(scriptsig.get(..first_opcode as usize + 1)?)?;
+    u64::try_from(decode_script_num(&push, 8).ok()?).ok()
+}
+
+// checks that no transaction is included twice, no outpoint is spent twice and the
+// coinbase outputs are not spent in the same block (coinbase maturity)
+// returns: ValidationResult
+fn check_duplicates_and_double_spends(transactions: &[Transaction]) -> ValidationResult {
+    let mut txids: HashSet<&str> = HashSet::new();
+    let mut spent_outpoints: HashSet<(&str, u32)> = HashSet::new();
+    let coinbase_txid = &transactions[0].meta.txid_hex;
+
+    for tx in transactions {
+        if !txids.insert(&tx.meta.txid_hex) {
+            return ValidationResult::Invalid("bad-txns-duplicate".to_string());
+        }
+    }
+    for tx in transactions.iter().skip(1) {
+        for txin in &tx.vin {
+            if !spent_outpoints.insert((&txin.txid, txin.vout)) {
+                return ValidationResult::Invalid(format!(
+                    "bad-txns-inputs-missingorspent {}:{} spent twice",
+                    txin.txid, txin.vout
+                ));
+            }
+            if &txin.txid == coinbase_txid {
+                return ValidationResult::Invalid(
+                    "bad-txns-premature-spend-of-coinbase".to_string(),
+                );
+            }
+        }
+    }
+    ValidationResult::Valid
+}
+
+// checks that each transaction comes after the block transactions it spends (parents
+// before children), a transaction spending its own or a later output can't be connected
+// returns: ValidationResult
+fn check_transaction_order(transactions: &[Transaction]) -> ValidationResult {
+    let positions: HashMap<&str, usize> = transactions
+        .iter()
+        .enumerate()
+        .map(|(position, tx)| (tx.meta.txid_hex.as_str(), position))
+        .collect();
+
+    for (position, tx) in transactions.iter().enumerate() {
+        for txin in &tx.vin {
+            if positions
+                .get(txin.txid.as_str())
+                .is_some_and(|&parent_position| parent_position >= position)
+            {
+                return ValidationResult::Invalid(format!(
+                    "bad-txns-child-before-parent {} spends {}",
+                    tx.meta.txid_hex, txin.txid
+                ));
+            }
+        }
+    }
+    ValidationResult::Valid
+}
+
+// checks all transactions context free (CheckTransaction), the values and scripts of all
+// non coinbase transactions and sums up their fees
+// returns: Ok(fees in satoshi) or Err(reason)
+fn check_transactions(transactions: &mut [Transaction]) -> Result<u64, String> {
+    let mut fees: u64 = 0;
+
+    for tx in transactions.iter() {
+        if let ValidationResult::Invalid(reason) = check_transaction(tx) {
+            return Err(format!("{} {}", reason, tx.meta.txid_hex));
+        }
+    }
+    for tx in transactions.iter_mut().skip(1) {
+        if tx.vin.iter().any(|txin| txin.is_coinbase) {
+            return Err("bad-cb-multiple".to_string());
+        }
+        if let ValidationResult::Invalid(reason) = validate_values_and_set_fee(tx) {
+            return Err(format!("{} {}", reason, tx.meta.txid_hex));
+        }
+        fees += tx.meta.fee;
+        for input_index in 0..tx.vin.len() {
+            if let ValidationResult::Invalid(reason) =
+                verify_input(tx, input_index, VerifyFlags::CONSENSUS)
+            {
+                return Err(format!(
+                    "Script verification of {} input {} failed: {}",
+                    tx.meta.txid_hex, input_index, reason
+                ));
+            }
+        }
+    }
+    Ok(fees)
+}
+
+// returns: block weight (stripped size * 3 + total size) or Err(reason)
+fn calculate_block_weight(raw_block: &[u8], transactions: &[Transaction]) -> Result<u64, String> {
+    let mut stripped_size = 80 + varint(transactions.len() as u128).len();
+
+    for tx in transactions {
+        stripped_size += assemble_txid_preimage(tx, false)?.len();
+    }
+    Ok((stripped_size * 3 + raw_block.len()) as u64)
+}
+
+// Validates the serialized block. The prevouts of all inputs have to be in the utxo set
+// or be created by a transaction earlier in the block.
+// returns: ValidationResult enum either ::Valid or ::Invalid(reason String)
+pub fn validate_block(raw_block: &[u8], utxos: &UtxoSet) -> ValidationResult {
+    let (header, mut transactions) = match deserialize_block(raw_block, utxos) {
+        Ok(block) => block,
+        Err(err) => return ValidationResult::Invalid(format!("Block decoding failed: {}", err)),
+    };
+    if !check_proof_of_work(&header) {
+        return ValidationResult::Invalid("high-hash".to_string());
+    }
+    match transactions.first() {
+        Some(coinbase) if coinbase.vin.len() == 1 && coinbase.vin[0].is_coinbase => {}
+        _ => return ValidationResult::Invalid("bad-cb-missing".to_string()),
+    }
+    if get_merkle_root(&get_natural_ids(&transactions, false)) != header[36..68] {
+        return ValidationResult::Invalid("bad-txnmrklroot".to_string());
+    }
+    if let ValidationResult::Invalid(reason) = check_witness_commitment(&transactions) {
+        return ValidationResult::Invalid(reason);
+    }
+    match calculate_block_weight(raw_block, &transactions) {
+        Ok(weight) if weight > MAX_BLOCK_WEIGHT => {
+            return ValidationResult::Invalid("bad-blk-weight".to_string())
+        }
+        Ok(_) => {}
+        Err(reason) => return ValidationResult::Invalid(reason),
+    }
+    let sigop_cost: u64 = match transactions.iter().map(get_transaction_sigop_cost).sum() {
+        Ok(sigop_cost) => sigop_cost,
+        Err(reason) => return ValidationResult::Invalid(reason),
+    };
+    if sigop_cost > MAX_BLOCK_SIGOPS_COST {
+        return ValidationResult::Invalid("bad-blk-sigops".to_string());
+    }
+    if let ValidationResult::Invalid(reason) = check_duplicates_and_double_spends(&transactions) {
+        return ValidationResult::Invalid(reason);
+    }
+    if let ValidationResult::Invalid(reason) = check_transaction_order(&transactions) {
+        return ValidationResult::Invalid(reason);
+    }
+    let fees = match check_transactions(&mut transactions) {
+        Ok(fees) => fees,
+        Err(reason) => return ValidationResult::Invalid(reason),
+    };
+    let height = match get_coinbase_height(&transactions[0]) {
+        Some(height) => height,
+        None => return ValidationResult::Invalid("bad-cb-height".to_string()),
+    };
+    let coinbase_value: u64 = transactions[0].vout.iter().map(|txout| txout.value).sum();
+    if coinbase_value > get_block_subsidy(height) + fees {
+        return ValidationResult::Invalid("bad-cb-amount".to_string());
+    }
+    ValidationResult::Valid
+}
+
+// loads the mempool transactions with txids and the utxo set of the confirmed outputs
+// they spend (outputs of mempool transactions have to be created in the block)
+// returns: (HashMap txid hex -> Transaction, UtxoSet) or Err(reason) if a prevout is invalid
+fn load_mempool(mempool_dir: &str) -> Result<(HashMap<String, Transaction>, UtxoSet), String> {
+    let mut mempool: HashMap<String, Transaction> = HashMap::new();
+    let mut utxos = UtxoSet::new();
+
+    for mut tx in parse_transactions_from_dir(mempool_dir) {
+        validate_txid_hash_filename(&mut tx)?;
+        mempool.insert(tx.meta.txid_hex.clone(), tx);
+    }
+    for tx in mempool.values() {
+        for txin in tx
+            .vin
+            .iter()
+            .filter(|txin| !mempool.contains_key(&txin.txid))
+        {
+            let scriptpubkey = hex::decode(&txin.prevout.scriptpubkey).map_err(|err| {
+                format!(
+                    "Prevout scriptpubkey hex of {}:{} invalid: {}",
+                    txin.txid, txin.vout, err
+                )
+            })?;
+            utxos.insert(
+                (txin.txid.clone(), txin.vout),
+                new_txout(&scriptpubkey, txin.prevout.value),
+            );
+        }
+    }
+    Ok((mempool, utxos))
+}
+
+// assembles the raw block of an output.txt (header, coinbase, txids starting with the
+// coinbase txid) with the transactions of the mempool
+// returns: serialized block or Err(reason)
+fn assemble_block_from_output(
+    output: &str,
+    mempool: &HashMap<String, Transaction>,
+) -> Result<Vec<u8>, String> {
+    let mut lines = output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty());
+    let header = lines.next().ok_or("Missing block header")?;
+    let coinbase = lines.next().ok_or("Missing coinbase transaction")?;
+    let txids: Vec<&str> = lines.collect();
+    if txids.is_empty() {
+        return Err("Missing coinbase txid".to_string());
+    }
+
+    let coinbase = hex::decode(coinbase).map_err(|err| format!("Coinbase hex: {}", err))?;
+    let mut coinbase_tx = deserialize_transaction(&coinbase, &UtxoSet::new())?;
+    validate_txid_hash_filename(&mut coinbase_tx)?;
+    if coinbase_tx.meta.txid_hex != txids[0] {
+        return Err("First txid is not the txid of the coinbase".to_string());
+    }
+
+    let mut raw_block = hex::decode(header).map_err(|err| format!("Header hex: {}", err))?;
+    raw_block.extend(varint(txids.len() as u128));
+    raw_block.extend(coinbase);
+    for txid in &txids[1..] {
+        match mempool.get(*txid) {
+            Some(tx) => raw_block.extend(serialize_transaction(tx)?),
+            None => return Err(format!("Transaction {} not in mempool", txid)),
+        }
+    }
+    Ok(raw_block)
+}
+
+// validates a block file with the mempool directory as utxo set. The file can be an
+// output.txt (.txt), the hex serialized block (.hex) or the binary block (e.g. block.bin).
+// For an output.txt the first txid has to be the txid of the coinbase.
+// returns: ValidationResult
+pub fn validate_block_file(block_path: &str, mempool_dir: &str) -> ValidationResult {
+    let (mempool, utxos) = match load_mempool(mempool_dir) {
+        Ok(mempool) => mempool,
+        Err(err) => return ValidationResult::Invalid(format!("Loading mempool failed: {}", err)),
+    };
+    let raw_block = match fs::read(block_path) {
+        Ok(content) if block_path.ends_with(".txt") => {
+            assemble_block_from_output(&String::from_utf8_lossy(&content), &mempool)
+        }
+        Ok(content) if block_path.ends_with(".hex") => {
+            hex::decode(String::from_utf8_lossy(&content).trim()).map_err(|err| err.to_string())
+        }
+        Ok(content) => Ok(content),
+        Err(err) => Err(format!("Reading {} failed: {}", block_path, err)),
+    };
+    match raw_block {
+        Ok(raw_block) => validate_block(&raw_block, &utxos),
+        Err(err) => ValidationResult::Invalid(err),
+    }
+}
@@ -1,3 +1,4 @@
+use super::signature_verification::decode_scriptsig;
 use super::utils::*;
 use super::weight_calculation::is_segwit;
 use crate::parsing::transaction_structs::{Transaction, TxIn, TxOut};
@@ -22,59 +23,49 @@ fn hash_txid(txid: Vec<u8>) -> String {
 
 // serialize given &TxIn to a byte-Vec<u8> for later use in assembling the full transaction
 // used for calculation of txid
-// returns: Vec<u8> of the byte serialized &TxIn
-pub fn serialize_input(input: &TxIn) -> Vec<u8> {
-    let mut serialized_input = get_outpoint(input);
-    let scriptsig_len = match &input.scriptsig {
-        Some(s) => hex::decode(s).expect("Hex decode ss len failed").len(),
-        None => 0,
-    };
-    let scriptsig_len = varint(scriptsig_len as u128);
-    let scriptsig_bytes = match &input.scriptsig {
-        Some(s) => hex::decode(s).expect("Hex decode ss bytes failed!"),
-        None => Vec::new(),
-    };
+// returns: Vec<u8> of the byte serialized &TxIn or Err(reason) if a hex field is invalid
+pub fn serialize_input(input: &TxIn) -> Result<Vec<u8>, String> {
+    let mut serialized_input = get_outpoint(input)?;
+    let scriptsig_bytes = decode_scriptsig(input)?;
+    let scriptsig_len = varint(scriptsig_bytes.len() as u128);
     let sequence_bytes = input.sequence.to_le_bytes();
     serialized_input.extend(scriptsig_len);
     serialized_input.extend(scriptsig_bytes);
     serialized_input.extend_from_slice(&sequence_bytes);
-    serialized_input
+    Ok(serialized_input)
 }
 
 // serialize given &TxOut to a byte-Vec<u8> for later use in assembling the full transaction
 // used for calculation of the txid
-// returns: Vec<u8> of the byte serialized &TxOut
-pub fn serialize_output(output: &TxOut) -> Vec<u8> {
+// returns: Vec<u8> of the byte serialized &TxOut or Err(reason) if the scriptpubkey hex
+// is invalid
+pub fn serialize_output(output: &TxOut) -> Result<Vec<u8>, String> {
     let mut serialized_output: Vec<u8> = Vec::new();
     let value = output.value.to_le_bytes();
-    let pubkey_script_len = match &output.scriptpubkey {
-        Some(s) => hex::decode(s)
-            .expect("hex decode output s len failed!")
-            .len(),
-        None => 0,
-    };
-    let pubkey_script_len = varint(pubkey_script_len as u128);
     let pubkey_script_bytes = match &output.scriptpubkey {
-        Some(s) => hex::decode(s).expect("Hex decode output s failed!"),
+        Some(s) => {
+            hex::decode(s).map_err(|err| format!("Output scriptpubkey hex invalid: {}", err))?
+        }
         None => Vec::new(),
     };
+    let pubkey_script_len = varint(pubkey_script_bytes.len() as u128);
     serialized_output.extend_from_slice(&value);
     serialized_output.extend(pubkey_script_len);
     serialized_output.extend(pubkey_script_bytes);
-    serialized_output
+    Ok(serialized_output)
 }
 
 // byte-serializes all witnesses in the given &Transaction
 // returns: Vec<u8> of the byte representation of all witnesses in the transaction
-fn serialize_witnesses_with_amount(tx: &Transaction) -> Vec<u8> {
+// or Err(reason) if a witness hex is invalid
+fn serialize_witnesses_with_amount(tx: &Transaction) -> Result<Vec<u8>, String> {
     let mut witnesses: Vec<u8> = Vec::new();
 
     for input in &tx.vin {
-        if let Some(witnesses_hex) = input.witness.as_ref() {
-            witnesses.extend(varint(witnesses_hex.len() as u128));
-            for witness_element in witnesses_hex {
-                let witness_element_bytes: Vec<u8> =
-                    hex::decode(witness_element).expect("decoding witness hex failed");
+        if input.witness.is_some() {
+            let witness_stack = decode_witness(input)?;
+            witnesses.extend(varint(witness_stack.len() as u128));
+            for witness_element_bytes in witness_stack {
                 witnesses.extend(varint(witness_element_bytes.len() as u128));
                 witnesses.extend(witness_element_bytes);
             }
@@ -82,26 +73,27 @@ fn serialize_witnesses_with_amount(tx: &Transaction) -> Vec<u8> {
             witnesses.extend(hexlit!("00").to_vec()); // non witness inputs
         };
     }
-    witnesses
+    Ok(witnesses)
 }
 
 // assembles/serializes the transaction according to the reference.
 // includes witness, marker and flag if argument witness is true for wtxid calculation
 // if witness argument is false the returned bytes represent the data to hash for the txid
-// returns: Vec<u8> of assembled transaction byte, either for txid or wtxid
-fn assemble_txid_preimage(tx: &Transaction, witness: bool) -> Vec<u8> {
+// returns: Vec<u8> of assembled transaction byte, either for txid or wtxid,
+// or Err(reason) if a hex field is invalid
+pub fn assemble_txid_preimage(tx: &Transaction, witness: bool) -> Result<Vec<u8>, String> {
     let mut preimage: Vec<u8> = Vec::new();
     let version: [u8; 4] = tx.version.to_le_bytes();
 
     let len_inputs: Vec<u8> = varint(tx.vin.len() as u128);
     let mut all_input_bytes: Vec<u8> = Vec::new();
     for tx_in in &tx.vin {
-        all_input_bytes.append(&mut serialize_input(tx_in));
+        all_input_bytes.append(&mut serialize_input(tx_in)?);
     }
     let len_outputs = varint(tx.vout.len() as u128);
     let mut all_output_bytes: Vec<u8> = Vec::new();
     for tx_out in &tx.vout {
-        all_output_bytes.append(&mut serialize_output(tx_out));
+        all_output_bytes.append(&mut serialize_output(tx_out)?);
     }
     let locktime = tx.locktime.to_le_bytes();
     preimage.extend_from_slice(&version);
@@ -113,22 +105,31 @@ fn assemble_txid_preimage(tx: &Transaction, witness: bool) -> Vec<u8> {
     preimage.extend_from_slice(&len_outputs);
     preimage.extend(all_output_bytes);
     if witness {
-        preimage.extend(&serialize_witnesses_with_amount(tx));
+        preimage.extend(&serialize_witnesses_with_amount(tx)?);
     };
     preimage.extend_from_slice(&locktime);
-    preimage
+    Ok(preimage)
+}
+
+// serializes the transaction as it is included in a block (with marker, flag and
+// witnesses if the transaction has witness inputs)
+// returns: Vec<u8> of the full transaction bytes or Err(reason) if a hex field is invalid
+pub fn serialize_transaction(tx: &Transaction) -> Result<Vec<u8>, String> {
+    assemble_txid_preimage(tx, is_segwit(tx))
 }
 
 // calculates txid and wtxid of the passed Transaction. Compares hash of txid
 // against json filename to validate correct parsing and re-serialization.
 // stores wtxid and txid in the &mut Transaction for further use.
 // returns: true if triple hash of transaction data (without witness) equals json filename
-pub fn validate_txid_hash_filename(tx: &mut Transaction) -> bool {
-    let tx_preimage = assemble_txid_preimage(tx, false);
+// (always true for transactions not loaded from a json file, e.g. raw transactions)
+// or Err(reason) if the transaction can't be serialized
+pub fn validate_txid_hash_filename(tx: &mut Transaction) -> Result<bool, String> {
+    let tx_preimage = assemble_txid_preimage(tx, false)?;
     let txid_bytes = get_txid(&tx_preimage);
 
     let wtxid_bytes: Vec<u8> = if is_segwit(tx) {
-        let wtx_preimage = assemble_txid_preimage(tx, true);
+        let wtx_preimage = assemble_txid_preimage(tx, true)?;
         get_txid(&wtx_preimage)
     } else {
         txid_bytes.clone()
@@ -136,13 +137,15 @@ pub fn validate_txid_hash_filename(tx: &mut Transaction) -> bool {
     tx.meta.txid_hex = hex::encode(&txid_bytes);
     tx.meta.wtxid_hex = hex::encode(wtxid_bytes);
     let triple_hashed = hash_txid(txid_bytes);
-    if let Some(json_path) = tx.meta.json_path.as_ref() {
-        let path = Path::new(json_path);
-        if let Some(filename) = path.file_stem() {
-            if let Some(filename_str) = filename.to_str() {
-                return filename_str == triple_hashed;
-            }
+    let json_path = match tx.meta.json_path.as_ref() {
+        Some(json_path) => json_path,
+        None => return Ok(true),
+    };
+    let path = Path::new(json_path);
+    if let Some(filename) = path.file_stem() {
+        if let Some(filename_str) = filename.to_str() {
+            return Ok(filename_str == triple_hashed);
         }
     }
-    false
+    Ok(false)
 }
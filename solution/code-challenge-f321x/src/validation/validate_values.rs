@@ -1,33 +1,95 @@
-use crate::parsing::transaction_structs::Transaction;
+use super::ValidationResult;
+use crate::parsing::transaction_structs::{Transaction, TxIn};
+use std::collections::HashSet;
 
-// checks the input sum of the passed &mut Transaction against the output sum
-// to prevent money creation. Also checks if there are inputs and outputs.
-// Sets the delta between input and output as fee (in satoshi) in the &mut Transaction.
-// returns: true if valid
-pub fn validate_values_and_set_fee(tx: &mut Transaction) -> bool {
-    let mut input_sum = 0;
-    let mut output_sum = 0;
+// amount of satoshi that will ever exist (MAX_MONEY of bitcoin core)
+pub const MAX_MONEY: u64 = 21_000_000 * 100_000_000;
 
-    if tx.vin.is_empty() || tx.vout.is_empty() {
-        // no in or outputs
-        return false;
+// returns: true if the amount is in the valid range of 0 - MAX_MONEY (MoneyRange)
+fn money_range(value: u64) -> bool {
+    value <= MAX_MONEY
+}
+
+// sums up the values, failing on overflow or if a value or the sum leaves the money range
+// returns: Some(sum) if all values and the sum are in range
+fn checked_value_sum(mut values: impl Iterator<Item = u64>) -> Option<u64> {
+    values.try_fold(0u64, |sum, value| {
+        sum.checked_add(value)
+            .filter(|sum| money_range(value) && money_range(*sum))
+    })
+}
+
+// decodes the outpoint spent by the input, so outpoints written in different hex letter case
+// compare equal
+// returns: (32 byte txid, vout) or None if the txid isn't 32 bytes hex
+pub fn decode_prevout(txin: &TxIn) -> Option<([u8; 32], u32)> {
+    let txid: [u8; 32] = hex::decode(&txin.txid).ok()?.try_into().ok()?;
+    Some((txid, txin.vout))
+}
+
+// returns: true if the outpoint is the null outpoint (zero txid and index 0xffffffff)
+fn is_null_prevout(prevout: &([u8; 32], u32)) -> bool {
+    *prevout == ([0; 32], u32::MAX)
+}
+
+// context free checks like CheckTransaction of bitcoin core: inputs and outputs exist,
+// output values and their sum are in the money range, no outpoint is spent twice
+// (CVE-2018-17144), the scriptsig of a coinbase has 2 - 100 bytes and other transactions
+// don't spend the null outpoint
+// returns: ValidationResult with the reject reason of bitcoin core
+pub fn check_transaction(tx: &Transaction) -> ValidationResult {
+    if tx.vin.is_empty() {
+        return ValidationResult::Invalid("bad-txns-vin-empty".to_string());
     }
-    for txin in &tx.vin {
-        input_sum += txin.prevout.value;
+    if tx.vout.is_empty() {
+        return ValidationResult::Invalid("bad-txns-vout-empty".to_string());
     }
-    for txout in &tx.vout {
-        output_sum += txout.value;
+    if tx.vout.iter().any(|txout| !money_range(txout.value)) {
+        return ValidationResult::Invalid("bad-txns-vout-toolarge".to_string());
     }
+    if checked_value_sum(tx.vout.iter().map(|txout| txout.value)).is_none() {
+        return ValidationResult::Invalid("bad-txns-txouttotal-toolarge".to_string());
+    }
+
+    let prevouts: Vec<([u8; 32], u32)> = match tx.vin.iter().map(decode_prevout).collect() {
+        Some(prevouts) => prevouts,
+        None => return ValidationResult::Invalid("Prevout txid hex invalid".to_string()),
+    };
+    let mut outpoints: HashSet<&([u8; 32], u32)> = HashSet::new();
+    if !prevouts.iter().all(|prevout| outpoints.insert(prevout)) {
+        return ValidationResult::Invalid("bad-txns-inputs-duplicate".to_string());
+    }
+
+    if prevouts.len() == 1 && is_null_prevout(&prevouts[0]) {
+        let scriptsig_len = tx.vin[0].scriptsig.as_deref().unwrap_or_default().len() / 2;
+        if !(2..=100).contains(&scriptsig_len) {
+            return ValidationResult::Invalid("bad-cb-length".to_string());
+        }
+    } else if prevouts.iter().any(is_null_prevout) {
+        return ValidationResult::Invalid("bad-txns-prevout-null".to_string());
+    }
+    ValidationResult::Valid
+}
+
+// checks the input sum of the passed &mut Transaction against the output sum
+// to prevent money creation, all values and sums have to be in the money range.
+// Sets the delta between input and output as fee (in satoshi) in the &mut Transaction.
+// returns: ValidationResult with the reject reason of bitcoin core
+pub fn validate_values_and_set_fee(tx: &mut Transaction) -> ValidationResult {
+    let input_sum = match checked_value_sum(tx.vin.iter().map(|txin| txin.prevout.value)) {
+        Some(input_sum) => input_sum,
+        None => return ValidationResult::Invalid("bad-txns-inputvalues-outofrange".to_string()),
+    };
+    let output_sum = match checked_value_sum(tx.vout.iter().map(|txout| txout.value)) {
+        Some(output_sum) => output_sum,
+        None => return ValidationResult::Invalid("bad-txns-txouttotal-toolarge".to_string()),
+    };
     if input_sum < output_sum {
         // no inflation!
-        return false;
+        return ValidationResult::Invalid("bad-txns-in-belowout".to_string());
     }
-    if input_sum > (20999999 * 100000000) || output_sum > (20999999 * 100000000) {
-        // this is unrealistic
-        return false;
-    };
     tx.meta.fee = input_sum - output_sum;
-    true
+    ValidationResult::Valid
 }
 
 // checks if feerate is below 1sat/vbyte which is not being relayed (standard)
@@ -0,0 +1,174 @@
+use std::ops::BitOr;
+
+// Script verification flags as used by bitcoin core. Every flag enables one consensus
+// or standardness (policy) rule of the script interpreter, so rules can be turned on
+// individually. VerifyFlags::default() are the standard flags of bitcoin core.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VerifyFlags(u32);
+
+impl VerifyFlags {
+    pub const NONE: VerifyFlags = VerifyFlags(0);
+    // evaluate P2SH subscripts (BIP16)
+    pub const P2SH: VerifyFlags = VerifyFlags(1 << 0);
+    // strict signature (hash type) and pubkey encoding
+    pub const STRICTENC: VerifyFlags = VerifyFlags(1 << 1);
+    // strict DER signature encoding (BIP66)
+    pub const DERSIG: VerifyFlags = VerifyFlags(1 << 2);
+    // signature S value has to be in the lower half of the curve order
+    pub const LOW_S: VerifyFlags = VerifyFlags(1 << 3);
+    // dummy element of OP_CHECKMULTISIG has to be empty (BIP147)
+    pub const NULLDUMMY: VerifyFlags = VerifyFlags(1 << 4);
+    // scriptsig has to be push only
+    pub const SIGPUSHONLY: VerifyFlags = VerifyFlags(1 << 5);
+    // pushes and script numbers have to be minimally encoded
+    pub const MINIMALDATA: VerifyFlags = VerifyFlags(1 << 6);
+    // fail on the upgradable OP_NOPx opcodes
+    pub const DISCOURAGE_UPGRADABLE_NOPS: VerifyFlags = VerifyFlags(1 << 7);
+    // exactly one element has to be left on the stack after evaluation
+    pub const CLEANSTACK: VerifyFlags = VerifyFlags(1 << 8);
+    // enable OP_CHECKLOCKTIMEVERIFY (BIP65)
+    pub const CHECKLOCKTIMEVERIFY: VerifyFlags = VerifyFlags(1 << 9);
+    // enable OP_CHECKSEQUENCEVERIFY (BIP112)
+    pub const CHECKSEQUENCEVERIFY: VerifyFlags = VerifyFlags(1 << 10);
+    // verify witness programs (BIP141/143)
+    pub const WITNESS: VerifyFlags = VerifyFlags(1 << 11);
+    // fail on witness programs of unknown versions
+    pub const DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM: VerifyFlags = VerifyFlags(1 << 12);
+    // OP_IF/OP_NOTIF argument in witness v0 scripts has to be empty or 0x01
+    pub const MINIMALIF: VerifyFlags = VerifyFlags(1 << 13);
+    // failed signature checks require empty signatures (BIP146)
+    pub const NULLFAIL: VerifyFlags = VerifyFlags(1 << 14);
+    // only compressed pubkeys in witness v0 scripts
+    pub const WITNESS_PUBKEYTYPE: VerifyFlags = VerifyFlags(1 << 15);
+    // no OP_CODESEPARATOR and FindAndDelete in legacy scripts
+    pub const CONST_SCRIPTCODE: VerifyFlags = VerifyFlags(1 << 16);
+    // verify taproot spends (BIP341/342)
+    pub const TAPROOT: VerifyFlags = VerifyFlags(1 << 17);
+    // fail on unknown tapleaf versions
+    pub const DISCOURAGE_UPGRADABLE_TAPROOT_VERSION: VerifyFlags = VerifyFlags(1 << 18);
+    // fail on OP_SUCCESSx opcodes in tapscript
+    pub const DISCOURAGE_OP_SUCCESS: VerifyFlags = VerifyFlags(1 << 19);
+    // fail on unknown pubkey types in tapscript
+    pub const DISCOURAGE_UPGRADABLE_PUBKEYTYPE: VerifyFlags = VerifyFlags(1 << 20);
+
+    // rules enforced by consensus (mandatory script verify flags of bitcoin core)
+    pub const CONSENSUS: VerifyFlags = VerifyFlags(
+        Self::P2SH.0
+            | Self::DERSIG.0
+            | Self::NULLDUMMY.0
+            | Self::CHECKLOCKTIMEVERIFY.0
+            | Self::CHECKSEQUENCEVERIFY.0
+            | Self::WITNESS.0
+            | Self::TAPROOT.0,
+    );
+
+    // consensus rules and the policy rules of bitcoin core (standard script verify flags)
+    pub const STANDARD: VerifyFlags = VerifyFlags(
+        Self::CONSENSUS.0
+            | Self::STRICTENC.0
+            | Self::MINIMALDATA.0
+            | Self::DISCOURAGE_UPGRADABLE_NOPS.0
+            | Self::CLEANSTACK.0
+            | Self::MINIMALIF.0
+            | Self::NULLFAIL.0
+            | Self::LOW_S.0
+            | Self::DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM.0
+            | Self::WITNESS_PUBKEYTYPE.0
+            | Self::CONST_SCRIPTCODE.0
+            | Self::DISCOURAGE_UPGRADABLE_TAPROOT_VERSION.0
+            | Self::DISCOURAGE_OP_SUCCESS.0
+            | Self::DISCOURAGE_UPGRADABLE_PUBKEYTYPE.0,
+    );
+
+    // flag names as used by bitcoin core (e.g. in the script test vectors)
+    pub const NAMES: [(&'static str, VerifyFlags); 21] = [
+        ("P2SH", Self::P2SH),
+        ("STRICTENC", Self::STRICTENC),
+        ("DERSIG", Self::DERSIG),
+        ("LOW_S", Self::LOW_S),
+        ("NULLDUMMY", Self::NULLDUMMY),
+        ("SIGPUSHONLY", Self::SIGPUSHONLY),
+        ("MINIMALDATA", Self::MINIMALDATA),
+        (
+            "DISCOURAGE_UPGRADABLE_NOPS",
+            Self::DISCOURAGE_UPGRADABLE_NOPS,
+        ),
+        ("CLEANSTACK", Self::CLEANSTACK),
+        ("CHECKLOCKTIMEVERIFY", Self::CHECKLOCKTIMEVERIFY),
+        ("CHECKSEQUENCEVERIFY", Self::CHECKSEQUENCEVERIFY),
+        ("WITNESS", Self::WITNESS),
+        (
+            "DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM",
+            Self::DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM,
+        ),
+        ("MINIMALIF", Self::MINIMALIF),
+        ("NULLFAIL", Self::NULLFAIL),
+        ("WITNESS_PUBKEYTYPE", Self::WITNESS_PUBKEYTYPE),
+        ("CONST_SCRIPTCODE", Self::CONST_SCRIPTCODE),
+        ("TAPROOT", Self::TAPROOT),
+        (
+            "DISCOURAGE_UPGRADABLE_TAPROOT_VERSION",
+            Self::DISCOURAGE_UPGRADABLE_TAPROOT_VERSION,
+        ),
+        ("DISCOURAGE_OP_SUCCESS", Self::DISCOURAGE_OP_SUCCESS),
+        (
+            "DISCOURAGE_UPGRADABLE_PUBKEYTYPE",
+            Self::DISCOURAGE_UPGRADABLE_PUBKEYTYPE,
+        ),
+    ];
+
+    // parses a comma separated list of flag names like "P2SH,STRICTENC" ("" or "NONE" for no flags)
+    // returns: VerifyFlags or Err(reason) if a name is unknown
+    pub fn from_names(names: &str) -> Result<VerifyFlags, String> {
+        let mut flags = VerifyFlags::NONE;
+
+        for name in names.split(',').map(str::trim) {
+            if name.is_empty() || name == "NONE" {
+                continue;
+            }
+            match Self::NAMES.iter().find(|(flag_name, _)| *flag_name == name) {
+                Some((_, flag)) => flags = flags | *flag,
+                None => return Err(format!("Unknown script verify flag: {}", name)),
+            }
+        }
+        Ok(flags)
+    }
+
+    // returns: names of all set flags
+    pub fn names(self) -> Vec<&'static str> {
+        Self::NAMES
+            .iter()
+            .filter(|(_, flag)| self.contains(*flag))
+            .map(|(name, _)| *name)
+            .collect()
+    }
+
+    // returns: true if all flags of other are set
+    pub fn contains(self, other: VerifyFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    // returns: true if at least one flag of other is set
+    pub fn intersects(self, other: VerifyFlags) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    // returns: the flags without the flags of other
+    pub fn remove(self, other: VerifyFlags) -> VerifyFlags {
+        VerifyFlags(self.0 & !other.0)
+    }
+}
+
+impl Default for VerifyFlags {
+    fn default() -> Self {
+        VerifyFlags::STANDARD
+    }
+}
+
+impl BitOr for VerifyFlags {
+    type Output = VerifyFlags;
+
+    fn bitor(self, other: VerifyFlags) -> VerifyFlags {
+        VerifyFlags(self.0 | other.0)
+    }
+}
@@ -1,5 +1,6 @@
+use super::ValidationResult;
 use crate::parsing::transaction_structs::Transaction;
-use crate::validation::utils::varint;
+use crate::validation::utils::{decode_witness, varint};
 use crate::validation::validate_parsing::{serialize_input, serialize_output};
 
 // Weight multipliers for calculation of weight units from bytes:
@@ -23,64 +24,67 @@ pub fn is_segwit(tx: &Transaction) -> bool {
     false
 }
 
-// returns: size of the complete input part of the transaction as u32
-fn input_weight_sum(tx: &Transaction) -> u32 {
+// returns: size of the complete input part of the transaction as u32 or Err(reason)
+fn input_weight_sum(tx: &Transaction) -> Result<u32, String> {
     let mut input_weight_sum: u32 = 0;
     input_weight_sum += varint(tx.vin.len() as u128).len() as u32;
     for txin in &tx.vin {
-        input_weight_sum += serialize_input(txin).len() as u32;
+        input_weight_sum += serialize_input(txin)?.len() as u32;
     }
-    input_weight_sum
+    Ok(input_weight_sum)
 }
 
-// returns: size of the complete output part of the transaction as u32
-fn output_weight_sum(tx: &Transaction) -> u32 {
+// returns: size of the complete output part of the transaction as u32 or Err(reason)
+fn output_weight_sum(tx: &Transaction) -> Result<u32, String> {
     let mut output_weight_sum: u32 = 0;
     output_weight_sum += varint(tx.vout.len() as u128).len() as u32;
     for txout in &tx.vout {
-        output_weight_sum += serialize_output(txout).len() as u32;
+        output_weight_sum += serialize_output(txout)?.len() as u32;
     }
-    output_weight_sum
+    Ok(output_weight_sum)
 }
 
-// returns: size in bytes of all witnesses contained in a transaction as u32
-fn witness_weight_sum(tx: &Transaction) -> u32 {
+// returns: size in bytes of all serialized witnesses contained in a transaction as u32
+// (stack item count and item lengths as varint, inputs without witness count as empty stack)
+// or Err(reason) if a witness hex is invalid
+fn witness_weight_sum(tx: &Transaction) -> Result<u32, String> {
     let mut witness_weight_sum: u32 = 0;
     for txin in &tx.vin {
-        if let Some(hex_witness_vec) = &txin.witness {
-            for witness in hex_witness_vec {
-                witness_weight_sum += hex::decode(witness)
-                    .expect("witness weight calculation hex decode failed")
-                    .len() as u32;
-            }
-        };
+        let witness_stack = decode_witness(txin)?;
+        witness_weight_sum += varint(witness_stack.len() as u128).len() as u32;
+        for witness in witness_stack {
+            witness_weight_sum += (varint(witness.len() as u128).len() + witness.len()) as u32;
+        }
     }
-    witness_weight_sum
+    Ok(witness_weight_sum)
 }
 
 // calls the functions to calculate the weight of the different components
 // of the transactions. Multiplies and sums them.
-// returns: tx weight as u32
-fn calculate_weight(tx: &Transaction) -> u32 {
+// returns: tx weight as u32 or Err(reason) if a hex field is invalid
+fn calculate_weight(tx: &Transaction) -> Result<u32, String> {
     let mut weight: u32 = 4 * 4; // Version: 4 bytes x 4
     if is_segwit(tx) {
         weight += 2; // marker 1 byte + flag 1 byte
-        weight += witness_weight_sum(tx); // weight of all witnesses in tx
+        weight += witness_weight_sum(tx)?; // weight of all witnesses in tx
     };
-    weight += input_weight_sum(tx) * 4; // sum of all inputs * 4
-    weight += output_weight_sum(tx) * 4; // sum of all outputs * 4
+    weight += input_weight_sum(tx)? * 4; // sum of all inputs * 4
+    weight += output_weight_sum(tx)? * 4; // sum of all outputs * 4
     weight += 4 * 4; // 4 bytes locktime * 4
-    weight
+    Ok(weight)
 }
 
 // calculates tx weight and checks if the weight is invalid (> blocksize)
-// returns: true if valid
-pub fn validate_and_set_weight(tx: &mut Transaction) -> bool {
-    let weight = calculate_weight(tx);
+// returns: ValidationResult
+pub fn validate_and_set_weight(tx: &mut Transaction) -> ValidationResult {
+    let weight = match calculate_weight(tx) {
+        Ok(weight) => weight,
+        Err(reason) => return ValidationResult::Invalid(reason),
+    };
     if weight > (4000000 - (400 + 320)) {
         // leave some space for header and coinbase tx
-        return false;
+        return ValidationResult::Invalid("Transaction weight too high!".to_string());
     };
     tx.meta.weight = weight as u64;
-    true
+    ValidationResult::Valid
 }
@@ -0,0 +1,1057 @@
+import hashlib
+import json
+import os
+
+# Generates script and transaction test vectors in the format of bitcoin core's
+# src/test/data/script_tests.json, tx_valid.json and tx_invalid.json into tests/data/.
+# The signatures are created with the independent (pure python) secp256k1, sighash and
+# script parser implementations below, so the rust interpreter is not involved in
+# creating the vectors it is tested against.
+# These are not the upstream core files: the plain script vectors are a hand-picked subset
+# of core's script_tests.json, all signed and transaction vectors are generated here.
+# The upstream core files have the same format and can replace the files in tests/data/.
+
+DATA_DIR = os.path.join(os.path.dirname(os.path.abspath(__file__)), "..", "tests", "data")
+
+# ----------------------------------------------------------------- secp256k1
+
+P = 2**256 - 2**32 - 977
+N = 0xFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFEBAAEDCE6AF48A03BBFD25E8CD0364141
+G = (0x79BE667EF9DCBBAC55A06295CE870B07029BFCDB2DCE28D959F2815B16F81798,
+     0x483ADA7726A3C4655DA4FBFC0E1108A8FD17B448A68554199C47D08FFB10D4B8)
+
+def point_add(p1, p2):
+    if p1 is None:
+        return p2
+    if p2 is None:
+        return p1
+    if p1[0] == p2[0] and p1[1] != p2[1]:
+        return None
+    if p1 == p2:
+        lam = 3 * p1[0] * p1[0] * pow(2 * p1[1], P - 2, P) % P
+    else:
+        lam = (p2[1] - p1[1]) * pow(p2[0] - p1[0], P - 2, P) % P
+    x = (lam * lam - p1[0] - p2[0]) % P
+    return (x, (lam * (p1[0] - x) - p1[1]) % P)
+
+def point_mul(point, n):
+    result = None
+    for i in range(256):
+        if (n >> i) & 1:
+            result = point_add(result, point)
+        point = point_add(point, point)
+    return result
+
+def sha256(data: bytes) -> bytes:
+    return hashlib.sha256(data).digest()
+
+def hash256(data: bytes) -> bytes:
+    return sha256(sha256(data))
+
+def hash160(data: bytes) -> bytes:
+    return hashlib.new("ripemd160", sha256(data)).digest()
+
+def tagged_hash(tag: str, data: bytes) -> bytes:
+    tag_hash = sha256(tag.encode())
+    return sha256(tag_hash + tag_hash + data)
+
+def privkey(name: str) -> int:
+    return int.from_bytes(sha256(name.encode()), "big") % N
+
+def pubkey(priv: int, compressed=True, hybrid=False) -> bytes:
+    x, y = point_mul(G, priv)
+    if compressed:
+        return bytes([2 + (y & 1)]) + x.to_bytes(32, "big")
+    prefix = 6 + (y & 1) if hybrid else 4
+    return bytes([prefix]) + x.to_bytes(32, "big") + y.to_bytes(32, "big")
+
+def xonly(priv: int) -> bytes:
+    return point_mul(G, priv)[0].to_bytes(32, "big")
+
+def der_int(value: int, pad=False) -> bytes:
+    data = value.to_bytes(32, "big").lstrip(b"\x00")
+    if data[0] & 0x80:
+        data = b"\x00" + data
+    if pad:
+        data = b"\x00" + data  # non canonical (BIP66 invalid) zero padding
+    return b"\x02" + bytes([len(data)]) + data
+
+# deterministic ECDSA signature, DER encoded with appended hash type byte
+def sign_ecdsa(priv: int, msg: bytes, hash_type: int, high_s=False, pad_r=False) -> bytes:
+    k = int.from_bytes(sha256(priv.to_bytes(32, "big") + msg), "big") % N
+    r = point_mul(G, k)[0] % N
+    s = pow(k, N - 2, N) * (int.from_bytes(msg, "big") + r * priv) % N
+    if (s > N // 2) != high_s:
+        s = N - s
+    body = der_int(r, pad_r) + der_int(s)
+    return b"\x30" + bytes([len(body)]) + body + bytes([hash_type])
+
+# BIP340 signature with zero auxiliary randomness
+def sign_schnorr(priv: int, msg: bytes) -> bytes:
+    point = point_mul(G, priv)
+    d = priv if point[1] % 2 == 0 else N - priv
+    t = (d ^ int.from_bytes(tagged_hash("BIP0340/aux", bytes(32)), "big")).to_bytes(32, "big")
+    px = point[0].to_bytes(32, "big")
+    k0 = int.from_bytes(tagged_hash("BIP0340/nonce", t + px + msg), "big") % N
+    r_point = point_mul(G, k0)
+    k = k0 if r_point[1] % 2 == 0 else N - k0
+    rx = r_point[0].to_bytes(32, "big")
+    e = int.from_bytes(tagged_hash("BIP0340/challenge", rx + px + msg), "big") % N
+    return rx + ((k + e * d) % N).to_bytes(32, "big")
+
+# returns: (tweaked private key, output key, output key parity) of a taproot output
+def taproot_tweak(priv: int, merkle_root: bytes):
+    point = point_mul(G, priv)
+    d = priv if point[1] % 2 == 0 else N - priv
+    tweak = int.from_bytes(tagged_hash("TapTweak", xonly(priv) + merkle_root), "big")
+    tweaked = (d + tweak) % N
+    output = point_mul(G, tweaked)
+    return tweaked, output[0].to_bytes(32, "big"), output[1] & 1
+
+# ----------------------------------------------------------------- script
+
+OPCODES = {
+    "0": 0x00, "FALSE": 0x00, "PUSHDATA1": 0x4c, "PUSHDATA2": 0x4d, "PUSHDATA4": 0x4e,
+    "1NEGATE": 0x4f, "RESERVED": 0x50, "TRUE": 0x51, "NOP": 0x61, "VER": 0x62, "IF": 0x63,
+    "NOTIF": 0x64, "VERIF": 0x65, "VERNOTIF": 0x66, "ELSE": 0x67, "ENDIF": 0x68, "VERIFY": 0x69,
+    "RETURN": 0x6a, "TOALTSTACK": 0x6b, "FROMALTSTACK": 0x6c, "2DROP": 0x6d, "2DUP": 0x6e,
+    "3DUP": 0x6f, "2OVER": 0x70, "2ROT": 0x71, "2SWAP": 0x72, "IFDUP": 0x73, "DEPTH": 0x74,
+    "DROP": 0x75, "DUP": 0x76, "NIP": 0x77, "OVER": 0x78, "PICK": 0x79, "ROLL": 0x7a,
+    "ROT": 0x7b, "SWAP": 0x7c, "TUCK": 0x7d, "CAT": 0x7e, "SUBSTR": 0x7f, "LEFT": 0x80,
+    "RIGHT": 0x81, "SIZE": 0x82, "INVERT": 0x83, "AND": 0x84, "OR": 0x85, "XOR": 0x86,
+    "EQUAL": 0x87, "EQUALVERIFY": 0x88, "RESERVED1": 0x89, "RESERVED2": 0x8a, "1ADD": 0x8b,
+    "1SUB": 0x8c, "2MUL": 0x8d, "2DIV": 0x8e, "NEGATE": 0x8f, "ABS": 0x90, "NOT": 0x91,
+    "0NOTEQUAL": 0x92, "ADD": 0x93, "SUB": 0x94, "MUL": 0x95, "DIV": 0x96, "MOD": 0x97,
+    "LSHIFT": 0x98, "RSHIFT": 0x99, "BOOLAND": 0x9a, "BOOLOR": 0x9b, "NUMEQUAL": 0x9c,
+    "NUMEQUALVERIFY": 0x9d, "NUMNOTEQUAL": 0x9e, "LESSTHAN": 0x9f, "GREATERTHAN": 0xa0,
+    "LESSTHANOREQUAL": 0xa1, "GREATERTHANOREQUAL": 0xa2, "MIN": 0xa3, "MAX": 0xa4,
+    "WITHIN": 0xa5, "RIPEMD160": 0xa6, "SHA1": 0xa7, "SHA256": 0xa8, "HASH160": 0xa9,
+    "HASH256": 0xaa, "CODESEPARATOR": 0xab, "CHECKSIG": 0xac, "CHECKSIGVERIFY": 0xad,
+    "CHECKMULTISIG": 0xae, "CHECKMULTISIGVERIFY": 0xaf, "NOP1": 0xb0,
+    "CHECKLOCKTIMEVERIFY": 0xb1, "NOP2": 0xb1, "CHECKSEQUENCEVERIFY": 0xb2, "NOP3": 0xb2,
+    "NOP4": 0xb3, "NOP5": 0xb4, "NOP6": 0xb5, "NOP7": 0xb6, "NOP8": 0xb7, "NOP9": 0xb8,
+    "NOP10": 0xb9, "CHECKSIGADD": 0xba,
+}
+
+def script_num(value: int) -> bytes:
+    if value == 0:
+        return b""
+    result = bytearray()
+    negative = value < 0
+    value = abs(value)
+    while value:
+        result.append(value & 0xff)
+        value >>= 8
+    if result[-1] & 0x80:
+        result.append(0x80 if negative else 0x00)
+    elif negative:
+        result[-1] |= 0x80
+    return bytes(result)
+
+def push(data: bytes) -> bytes:
+    if len(data) < 0x4c:
+        return bytes([len(data)]) + data
+    if len(data) <= 0xff:
+        return b"\x4c" + bytes([len(data)]) + data
+    if len(data) <= 0xffff:
+        return b"\x4d" + len(data).to_bytes(2, "little") + data
+    return b"\x4e" + len(data).to_bytes(4, "little") + data
+
+# parses script asm the same way as ParseScript() of bitcoin core's test framework
+def parse_script(asm: str) -> bytes:
+    result = b""
+    for token in asm.split():
+        if token.lstrip("-").isdigit():
+            value = int(token)
+            if value == -1 or 1 <= value <= 16:
+                result += bytes([value + 0x50])
+            elif value == 0:
+                result += b"\x00"
+            else:
+                result += push(script_num(value))
+        elif token.startswith("0x") and len(token) > 2:
+            result += bytes.fromhex(token[2:])
+        elif len(token) >= 2 and token[0] == "'" and token[-1] == "'":
+            result += push(token[1:-1].encode())
+        else:
+            name = token[3:] if token.startswith("OP_") else token
+            result += bytes([OPCODES[name]])
+    return result
+
+# asm token of a raw data push (push opcode and data as hex)
+def asm_push(data: bytes) -> str:
+    encoded = push(data)
+    header = len(encoded) - len(data)
+    if len(data) == 0:
+        return "0"
+    return "0x" + encoded[:header].hex() + " 0x" + data.hex()
+
+# ----------------------------------------------------------------- transactions
+
+def varint(n: int) -> bytes:
+    if n < 0xfd:
+        return bytes([n])
+    if n <= 0xffff:
+        return b"\xfd" + n.to_bytes(2, "little")
+    if n <= 0xffffffff:
+        return b"\xfe" + n.to_bytes(4, "little")
+    return b"\xff" + n.to_bytes(8, "little")
+
+def ser_string(data: bytes) -> bytes:
+    return varint(len(data)) + data
+
+# tx: {"version", "locktime", "vin": [{"txid" (internal byte order), "vout", "scriptsig",
+# "sequence", "witness"}], "vout": [{"value", "spk"}]}
+def serialize_tx(tx, with_witness=True) -> bytes:
+    has_witness = with_witness and any(txin["witness"] for txin in tx["vin"])
+    result = tx["version"].to_bytes(4, "little")
+    if has_witness:
+        result += b"\x00\x01"
+    result += varint(len(tx["vin"]))
+    for txin in tx["vin"]:
+        result += txin["txid"] + txin["vout"].to_bytes(4, "little")
+        result += ser_string(txin["scriptsig"]) + txin["sequence"].to_bytes(4, "little")
+    result += varint(len(tx["vout"]))
+    for txout in tx["vout"]:
+        result += txout["value"].to_bytes(8, "little") + ser_string(txout["spk"])
+    if has_witness:
+        for txin in tx["vin"]:
+            result += varint(len(txin["witness"]))
+            for element in txin["witness"]:
+                result += ser_string(element)
+    return result + tx["locktime"].to_bytes(4, "little")
+
+def txid(tx) -> bytes:
+    return hash256(serialize_tx(tx, False))
+
+def new_input(prev_txid: bytes, vout: int, sequence=0xffffffff):
+    return {"txid": prev_txid, "vout": vout, "scriptsig": b"", "sequence": sequence, "witness": []}
+
+# crediting transaction of the script tests (coinbase like with a single output)
+def credit_tx(spk: bytes, amount: int):
+    txin = new_input(bytes(32), 0xffffffff)
+    txin["scriptsig"] = b"\x00\x00"
+    return {"version": 1, "locktime": 0, "vin": [txin], "vout": [{"value": amount, "spk": spk}]}
+
+# spending transaction of the script tests with a single input and an empty output script
+def spend_tx(credit):
+    return {"version": 1, "locktime": 0, "vin": [new_input(txid(credit), 0)],
+            "vout": [{"value": credit["vout"][0]["value"], "spk": b""}]}
+
+# ----------------------------------------------------------------- sighash
+
+SIGHASH_ALL, SIGHASH_NONE, SIGHASH_SINGLE, SIGHASH_ANYONECANPAY = 1, 2, 3, 0x80
+
+def remove_codeseparators(script: bytes) -> bytes:
+    result = b""
+    i = 0
+    while i < len(script):
+        opcode = script[i]
+        size = 0
+        header = 1
+        if 0 < opcode < 0x4c:
+            size = opcode
+        elif opcode == 0x4c:
+            size, header = script[i + 1], 2
+        elif opcode == 0x4d:
+            size, header = int.from_bytes(script[i + 1:i + 3], "little"), 3
+        elif opcode == 0x4e:
+            size, header = int.from_bytes(script[i + 1:i + 5], "little"), 5
+        if opcode != 0xab:
+            result += script[i:i + header + size]
+        i += header + size
+    return result
+
+# legacy sighash (SignatureHash for SigVersion::BASE)
+def sighash_legacy(tx, index: int, script_code: bytes, hash_type: int) -> bytes:
+    base = hash_type & 0x1f
+    if base == SIGHASH_SINGLE and index >= len(tx["vout"]):
+        return (1).to_bytes(32, "little")
+    script_code = remove_codeseparators(script_code)
+    vin = []
+    for i, txin in enumerate(tx["vin"]):
+        if hash_type & SIGHASH_ANYONECANPAY and i != index:
+            continue
+        copy = dict(txin, witness=[], scriptsig=script_code if i == index else b"")
+        if i != index and base in (SIGHASH_NONE, SIGHASH_SINGLE):
+            copy["sequence"] = 0
+        vin.append(copy)
+    vout = tx["vout"]
+    if base == SIGHASH_NONE:
+        vout = []
+    elif base == SIGHASH_SINGLE:
+        vout = [{"value": 0xffffffffffffffff, "spk": b""}] * index + [vout[index]]
+    copy = dict(tx, vin=vin, vout=vout)
+    return hash256(serialize_tx(copy, False) + hash_type.to_bytes(4, "little"))
+
+# BIP143 sighash of witness v0 inputs
+def sighash_v0(tx, index: int, script_code: bytes, amount: int, hash_type: int) -> bytes:
+    base = hash_type & 0x1f
+    anyonecanpay = hash_type & SIGHASH_ANYONECANPAY
+    hash_prevouts = hash_sequence = hash_outputs = bytes(32)
+    if not anyonecanpay:
+        hash_prevouts = hash256(b"".join(i["txid"] + i["vout"].to_bytes(4, "little") for i in tx["vin"]))
+    if not anyonecanpay and base not in (SIGHASH_SINGLE, SIGHASH_NONE):
+        hash_sequence = hash256(b"".join(i["sequence"].to_bytes(4, "little") for i in tx["vin"]))
+    if base not in (SIGHASH_SINGLE, SIGHASH_NONE):
+        hash_outputs = hash256(b"".join(o["value"].to_bytes(8, "little") + ser_string(o["spk"]) for o in tx["vout"]))
+    elif base == SIGHASH_SINGLE and index < len(tx["vout"]):
+        output = tx["vout"][index]
+        hash_outputs = hash256(output["value"].to_bytes(8, "little") + ser_string(output["spk"]))
+    txin = tx["vin"][index]
+    preimage = tx["version"].to_bytes(4, "little") + hash_prevouts + hash_sequence
+    preimage += txin["txid"] + txin["vout"].to_bytes(4, "little") + ser_string(script_code)
+    preimage += amount.to_bytes(8, "little") + txin["sequence"].to_bytes(4, "little")
+    preimage += hash_outputs + tx["locktime"].to_bytes(4, "little") + hash_type.to_bytes(4, "little")
+    return hash256(preimage)
+
+# BIP341/342 sighash, spent: [(amount, scriptpubkey)] of all inputs,
+# leaf: (tapleaf hash, codeseparator position) for script path spends
+def sighash_taproot(tx, index: int, spent, hash_type: int, annex=None, leaf=None) -> bytes:
+    base = hash_type & 0x03
+    anyonecanpay = hash_type & SIGHASH_ANYONECANPAY
+    data = bytes([0, hash_type]) + tx["version"].to_bytes(4, "little") + tx["locktime"].to_bytes(4, "little")
+    if not anyonecanpay:
+        data += sha256(b"".join(i["txid"] + i["vout"].to_bytes(4, "little") for i in tx["vin"]))
+        data += sha256(b"".join(amount.to_bytes(8, "little") for amount, _ in spent))
+        data += sha256(b"".join(ser_string(spk) for _, spk in spent))
+        data += sha256(b"".join(i["sequence"].to_bytes(4, "little") for i in tx["vin"]))
+    if base not in (SIGHASH_NONE, SIGHASH_SINGLE):
+        data += sha256(b"".join(o["value"].to_bytes(8, "little") + ser_string(o["spk"]) for o in tx["vout"]))
+    data += bytes([(2 if leaf else 0) + (1 if annex else 0)])
+    txin = tx["vin"][index]
+    if anyonecanpay:
+        data += txin["txid"] + txin["vout"].to_bytes(4, "little")
+        data += spent[index][0].to_bytes(8, "little") + ser_string(spent[index][1])
+        data += txin["sequence"].to_bytes(4, "little")
+    else:
+        data += index.to_bytes(4, "little")
+    if annex:
+        data += sha256(ser_string(annex))
+    if base == SIGHASH_SINGLE:
+        output = tx["vout"][index]
+        data += sha256(output["value"].to_bytes(8, "little") + ser_string(output["spk"]))
+    if leaf:
+        data += leaf[0] + b"\x00" + leaf[1].to_bytes(4, "little", signed=False)
+    return tagged_hash("TapSighash", data)
+
+def tapleaf_hash(script: bytes, leaf_version=0xc0) -> bytes:
+    return tagged_hash("TapLeaf", bytes([leaf_version]) + ser_string(script))
+
+# returns: (scriptpubkey, control block) of a taproot output committing to a single leaf
+def taproot_single_leaf(internal: int, script: bytes, leaf_version=0xc0):
+    _, output_key, parity = taproot_tweak(internal, tapleaf_hash(script, leaf_version))
+    control = bytes([leaf_version + parity]) + xonly(internal)
+    return b"\x51\x20" + output_key, control
+
+def p2sh(redeem: bytes) -> bytes:
+    return b"\xa9\x14" + hash160(redeem) + b"\x87"
+
+def p2wsh(script: bytes) -> bytes:
+    return b"\x00\x20" + sha256(script)
+
+def p2wpkh(pub: bytes) -> bytes:
+    return b"\x00\x14" + hash160(pub)
+
+def p2pkh_code(pub: bytes) -> bytes:
+    return b"\x76\xa9\x14" + hash160(pub) + b"\x88\xac"
+
+# ----------------------------------------------------------------- script tests
+
+def btc(amount: int) -> float:
+    return amount / 100_000_000
+
+# plain script tests without signatures [scriptSig, scriptPubKey, flags, expected, comment]
+PLAIN_TESTS = [
+    ["Format: [[wit..., amount]?, scriptSig, scriptPubKey, flags, expected_scripterror, ... comments]"],
+    ["Generated by test_scripts/gen_core_vectors.py, not the upstream file: a subset of bitcoin core's script_tests.json and generated signed vectors"],
+    ["", "DEPTH 0 EQUAL", "P2SH,STRICTENC", "OK", "Test the test: we should have an empty stack after scriptSig evaluation"],
+    ["  ", "DEPTH 0 EQUAL", "P2SH,STRICTENC", "OK", "and multiple spaces should not change that."],
+    ["1 2", "2 EQUALVERIFY 1 EQUAL", "P2SH,STRICTENC", "OK", "Similarly whitespace around and between symbols"],
+    ["0x01 0x0b", "11 EQUAL", "P2SH,STRICTENC", "OK", "push 1 byte"],
+    ["0x02 0x417a", "'Az' EQUAL", "P2SH,STRICTENC", "OK"],
+    ["0x4b 0x" + "11" * 75, "0x4b 0x" + "11" * 75 + " EQUAL", "P2SH,STRICTENC", "OK", "push 75 bytes"],
+    ["0x4c 0x01 0x07", "7 EQUAL", "P2SH,STRICTENC", "OK", "0x4c is OP_PUSHDATA1"],
+    ["0x4d 0x0100 0x08", "8 EQUAL", "P2SH,STRICTENC", "OK", "0x4d is OP_PUSHDATA2"],
+    ["0x4e 0x01000000 0x09", "9 EQUAL", "P2SH,STRICTENC", "OK", "0x4e is OP_PUSHDATA4"],
+    ["0x4c 0x00", "0 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["0x4d 0x0000", "0 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["0x4e 0x00000000", "0 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["0x4f 1000 ADD", "999 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["0", "IF 0x50 ENDIF 1", "P2SH,STRICTENC", "OK", "0x50 is reserved (ok if not executed)"],
+    ["0x51", "0x5f ADD 0x60 EQUAL", "P2SH,STRICTENC", "OK", "0x51 through 0x60 push 1 through 16 onto stack"],
+    ["1", "NOP", "P2SH,STRICTENC", "OK"],
+    ["0", "IF VER ELSE 1 ENDIF", "P2SH,STRICTENC", "OK", "VER non-functional (ok if not executed)"],
+    ["0", "IF RESERVED RESERVED1 RESERVED2 ELSE 1 ENDIF", "P2SH,STRICTENC", "OK", "RESERVED ok in un-executed IF"],
+    ["1", "DUP IF ENDIF", "P2SH,STRICTENC", "OK"],
+    ["1", "IF 1 ENDIF", "P2SH,STRICTENC", "OK"],
+    ["1", "DUP IF ELSE ENDIF", "P2SH,STRICTENC", "OK"],
+    ["1", "IF 1 ELSE ENDIF", "P2SH,STRICTENC", "OK"],
+    ["0", "IF ELSE 1 ENDIF", "P2SH,STRICTENC", "OK"],
+    ["1 1", "IF IF 1 ELSE 0 ENDIF ENDIF", "P2SH,STRICTENC", "OK"],
+    ["0 0", "IF IF 1 ELSE 0 ENDIF ELSE IF 0 ELSE 1 ENDIF ENDIF", "P2SH,STRICTENC", "OK"],
+    ["0", "NOTIF 1 ENDIF", "P2SH,STRICTENC", "OK"],
+    ["1 1", "NOTIF IF 1 ELSE 0 ENDIF ELSE 1 ENDIF", "P2SH,STRICTENC", "OK"],
+    ["0", "IF 0 ELSE 1 ELSE 0 ENDIF", "P2SH,STRICTENC", "OK", "Multiple ELSE's are valid and executed inverts on each ELSE encountered"],
+    ["1", "IF 1 ELSE 0 ELSE ENDIF", "P2SH,STRICTENC", "OK"],
+    ["1", "IF ELSE 0 ELSE 1 ENDIF", "P2SH,STRICTENC", "OK"],
+    ["'' 1", "IF SHA1 ENDIF 0x14 0xda39a3ee5e6b4b0d3255bfef95601890afd80709 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["0", "IF RETURN ENDIF 1", "P2SH,STRICTENC", "OK", "RETURN only works if executed"],
+    ["1 1", "VERIFY", "P2SH,STRICTENC", "OK"],
+    ["1 0x05 0x01 0x00 0x00 0x00 0x00", "VERIFY", "P2SH,STRICTENC", "OK", "values >4 bytes can be cast to boolean"],
+    ["1 0x01 0x80", "IF 0 ENDIF", "P2SH,STRICTENC", "OK", "negative 0 is false"],
+    ["10 0 11 TOALTSTACK DROP FROMALTSTACK", "ADD 21 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["'gavin_was_here' TOALTSTACK 11 FROMALTSTACK", "'gavin_was_here' EQUALVERIFY 11 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["0 IFDUP", "DEPTH 1 EQUALVERIFY 0 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["1 IFDUP", "DEPTH 2 EQUALVERIFY 1 EQUALVERIFY 1 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["0 DROP", "DEPTH 0 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["0", "DUP 1 ADD 1 EQUALVERIFY 0 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["0 1", "NIP", "P2SH,STRICTENC", "OK"],
+    ["1 0", "OVER DEPTH 3 EQUALVERIFY", "P2SH,STRICTENC", "OK"],
+    ["22 21 20", "0 PICK 20 EQUALVERIFY DEPTH 3 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["22 21 20", "1 PICK 21 EQUALVERIFY DEPTH 3 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["22 21 20", "2 PICK 22 EQUALVERIFY DEPTH 3 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["22 21 20", "0 ROLL 20 EQUALVERIFY DEPTH 2 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["22 21 20", "1 ROLL 21 EQUALVERIFY DEPTH 2 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["22 21 20", "2 ROLL 22 EQUALVERIFY DEPTH 2 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["22 21 20", "ROT 22 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["22 21 20", "ROT DROP 20 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["22 21 20", "ROT DROP DROP 21 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["25 24 23 22 21 20", "2ROT 24 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["25 24 23 22 21 20", "2ROT DROP 25 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["25 24 23 22 21 20", "2ROT 2DROP 20 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["1 0", "SWAP 1 EQUALVERIFY 0 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["0 1", "TUCK DEPTH 3 EQUALVERIFY SWAP 2DROP", "P2SH,STRICTENC", "OK"],
+    ["13 14", "2DUP ROT EQUALVERIFY EQUAL", "P2SH,STRICTENC", "OK"],
+    ["-1 0 1 2", "3DUP DEPTH 7 EQUALVERIFY ADD ADD 3 EQUALVERIFY 2DROP 0 EQUALVERIFY", "P2SH,STRICTENC", "OK"],
+    ["1 2 3 5", "2OVER ADD ADD 8 EQUALVERIFY ADD ADD 6 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["1 3 5 7", "2SWAP ADD 4 EQUALVERIFY ADD 12 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["0", "SIZE 0 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["1", "SIZE 1 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["127", "SIZE 1 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["128", "SIZE 2 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["-1", "SIZE 1 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["'abcdefghijklmnopqrstuvwxyz'", "SIZE 26 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["42", "SIZE 1 EQUALVERIFY 42 EQUAL", "P2SH,STRICTENC", "OK", "SIZE does not consume argument"],
+    ["2 -2 ADD", "0 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["2147483647 -2147483647 ADD", "0 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["-1 -1 ADD", "-2 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["0 0", "EQUAL", "P2SH,STRICTENC", "OK"],
+    ["1 1 ADD", "2 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["1 1ADD", "2 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["111 1SUB", "110 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["111 1 ADD 12 SUB", "100 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["0 ABS", "0 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["16 ABS", "16 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["-16 ABS", "-16 NEGATE EQUAL", "P2SH,STRICTENC", "OK"],
+    ["0 NOT", "NOP", "P2SH,STRICTENC", "OK"],
+    ["1 NOT", "0 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["11 NOT", "0 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["0 0NOTEQUAL", "0 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["1 0NOTEQUAL", "1 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["111 0NOTEQUAL", "1 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["-111 0NOTEQUAL", "1 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["1 1", "BOOLAND", "P2SH,STRICTENC", "OK"],
+    ["1 0", "BOOLOR", "P2SH,STRICTENC", "OK"],
+    ["11 10", "BOOLAND", "P2SH,STRICTENC", "OK"],
+    ["0 0", "BOOLOR NOT", "P2SH,STRICTENC", "OK"],
+    ["16 17", "BOOLOR", "P2SH,STRICTENC", "OK"],
+    ["11 10 1", "ADD NUMEQUAL", "P2SH,STRICTENC", "OK"],
+    ["11 10 1", "ADD NUMEQUALVERIFY 1", "P2SH,STRICTENC", "OK"],
+    ["11 10 1", "ADD NUMNOTEQUAL NOT", "P2SH,STRICTENC", "OK"],
+    ["111 10 1", "ADD NUMNOTEQUAL", "P2SH,STRICTENC", "OK"],
+    ["11 10", "LESSTHAN NOT", "P2SH,STRICTENC", "OK"],
+    ["4 4", "LESSTHAN NOT", "P2SH,STRICTENC", "OK"],
+    ["10 11", "LESSTHAN", "P2SH,STRICTENC", "OK"],
+    ["-11 11", "LESSTHAN", "P2SH,STRICTENC", "OK"],
+    ["11 10", "GREATERTHAN", "P2SH,STRICTENC", "OK"],
+    ["4 4", "GREATERTHAN NOT", "P2SH,STRICTENC", "OK"],
+    ["11 10", "LESSTHANOREQUAL NOT", "P2SH,STRICTENC", "OK"],
+    ["4 4", "LESSTHANOREQUAL", "P2SH,STRICTENC", "OK"],
+    ["11 10", "GREATERTHANOREQUAL", "P2SH,STRICTENC", "OK"],
+    ["10 11", "GREATERTHANOREQUAL NOT", "P2SH,STRICTENC", "OK"],
+    ["1 0", "MIN 0 NUMEQUAL", "P2SH,STRICTENC", "OK"],
+    ["-1 0", "MIN -1 NUMEQUAL", "P2SH,STRICTENC", "OK"],
+    ["1 0", "MAX 1 NUMEQUAL", "P2SH,STRICTENC", "OK"],
+    ["-2147483647 0", "MAX 0 NUMEQUAL", "P2SH,STRICTENC", "OK"],
+    ["0 0 1", "WITHIN", "P2SH,STRICTENC", "OK"],
+    ["1 0 1", "WITHIN NOT", "P2SH,STRICTENC", "OK"],
+    ["0 -2147483647 2147483647", "WITHIN", "P2SH,STRICTENC", "OK"],
+    ["-1 -100 100", "WITHIN", "P2SH,STRICTENC", "OK"],
+    ["11 -100 100", "WITHIN", "P2SH,STRICTENC", "OK"],
+    ["-2147483647 -100 100", "WITHIN NOT", "P2SH,STRICTENC", "OK"],
+    ["2147483647 DUP ADD", "4294967294 EQUAL", "P2SH,STRICTENC", "OK", "arithmetic operands must be in range, results may exceed 4 bytes"],
+    ["''", "RIPEMD160 0x14 0x9c1185a5c5e9fc54612808977ee8f548b2258d31 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["'a'", "RIPEMD160 0x14 0x0bdc9d2d256b3ee9daae347be6f4dc835a467ffe EQUAL", "P2SH,STRICTENC", "OK"],
+    ["''", "SHA1 0x14 0xda39a3ee5e6b4b0d3255bfef95601890afd80709 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["'a'", "SHA1 0x14 0x86f7e437faa5a7fce15d1ddcb9eaeaea377667b8 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["''", "SHA256 0x20 0xe3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["'a'", "SHA256 0x20 0xca978112ca1bbdcafac231b39a23dc4da786eff8147c4e72b9807785afee48bb EQUAL", "P2SH,STRICTENC", "OK"],
+    ["''", "DUP HASH160 SWAP SHA256 RIPEMD160 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["''", "DUP HASH256 SWAP SHA256 SHA256 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["''", "NOP HASH160 0x14 0xb472a266d0bd89c13706a4132ccfb16f7c3b9fcb EQUAL", "P2SH,STRICTENC", "OK"],
+    ["''", "HASH256 0x20 0x5df6e0e2761359d30a8275058e299fcc0381534545f55cf43e41983f5d4c9456 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["1", "NOP1 CHECKLOCKTIMEVERIFY CHECKSEQUENCEVERIFY NOP4 NOP5 NOP6 NOP7 NOP8 NOP9 NOP10 1 EQUAL", "P2SH,STRICTENC", "OK"],
+    ["'NOP_1_to_10' NOP1 CHECKLOCKTIMEVERIFY CHECKSEQUENCEVERIFY NOP4 NOP5 NOP6 NOP7 NOP8 NOP9 NOP10", "'NOP_1_to_10' EQUAL", "P2SH,STRICTENC", "OK"],
+    ["1", "NOP10", "P2SH,STRICTENC", "OK", "Discouraged NOPs are allowed without DISCOURAGE_UPGRADABLE_NOPS"],
+    ["0", "IF NOP10 ENDIF 1", "P2SH,STRICTENC,DISCOURAGE_UPGRADABLE_NOPS", "OK", "Discouraged NOP10 in skipped branch"],
+    ["0x01 0x51", "HASH160 0x14 0xda1745e9b549bd0bfa1a569971c77eba30cd5a4b EQUAL", "P2SH,STRICTENC", "OK", "P2SH with redeem script 1"],
+    ["NOP 0x01 0x51", "HASH160 0x14 0xda1745e9b549bd0bfa1a569971c77eba30cd5a4b EQUAL", "NONE", "OK", "Non push-only scriptSig is fine without P2SH"],
+    ["0x01 0x11", "DROP 1", "MINIMALDATA", "OK", "17 is not a small integer, PUSHBYTES_1 is minimal"],
+    ["0x02 0x0000", "NOT DROP 1", "NONE", "OK", "non-minimal zero without MINIMALDATA"],
+    ["0x02 0x8000", "1ADD DROP 1", "MINIMALDATA", "OK", "0x8000 (128) is minimally encoded"],
+    ["0", "IF 0x01 0x05 ENDIF 1", "MINIMALDATA", "OK", "non-minimal push in unexecuted branch"],
+    ["", "1", "P2SH,WITNESS,CLEANSTACK", "OK"],
+    ["1 NOP", "1", "NONE", "OK", "Non push-only scriptSig without SIGPUSHONLY"],
+
+    ["", "DEPTH", "P2SH,STRICTENC", "EVAL_FALSE", "Test the test: we should have an empty stack after scriptSig evaluation"],
+    ["  ", "DEPTH", "P2SH,STRICTENC", "EVAL_FALSE", "and multiple spaces should not change that."],
+    ["0", "DUP IF ENDIF", "P2SH,STRICTENC", "EVAL_FALSE"],
+    ["0", "IF 1 ENDIF", "P2SH,STRICTENC", "EVAL_FALSE"],
+    ["1", "IF 0 ENDIF", "P2SH,STRICTENC", "EVAL_FALSE"],
+    ["0x01 0x80", "DUP IF ENDIF", "P2SH,STRICTENC", "EVAL_FALSE", "negative 0 is false"],
+    ["1 0", "BOOLAND", "P2SH,STRICTENC", "EVAL_FALSE"],
+    ["0", "VERIFY 1", "P2SH,STRICTENC", "VERIFY"],
+    ["1", "VERIFY", "P2SH,STRICTENC", "EVAL_FALSE"],
+    ["1", "RETURN", "P2SH,STRICTENC", "OP_RETURN"],
+    ["1", "DUP IF RETURN ENDIF", "P2SH,STRICTENC", "OP_RETURN"],
+    ["1", "IF 0x50 ENDIF 1", "P2SH,STRICTENC", "BAD_OPCODE", "0x50 is reserved"],
+    ["1", "IF VER ELSE 1 ENDIF", "P2SH,STRICTENC", "BAD_OPCODE", "VER non-functional"],
+    ["0", "IF VERIF ELSE 1 ENDIF", "P2SH,STRICTENC", "BAD_OPCODE", "VERIF illegal everywhere"],
+    ["0", "IF ELSE 1 ELSE VERIF ENDIF", "P2SH,STRICTENC", "BAD_OPCODE", "VERIF illegal everywhere"],
+    ["0", "IF VERNOTIF ELSE 1 ENDIF", "P2SH,STRICTENC", "BAD_OPCODE", "VERNOTIF illegal everywhere"],
+    ["1", "RESERVED1", "P2SH,STRICTENC", "BAD_OPCODE"],
+    ["1", "RESERVED2", "P2SH,STRICTENC", "BAD_OPCODE"],
+    ["0x4c 0x01", "0x01 NOP", "P2SH,STRICTENC", "BAD_OPCODE", "PUSHDATA1 with not enough bytes"],
+    ["0x4d 0x0200 0xff", "0x01 NOP", "P2SH,STRICTENC", "BAD_OPCODE", "PUSHDATA2 with not enough bytes"],
+    ["1", "IF", "P2SH,STRICTENC", "UNBALANCED_CONDITIONAL"],
+    ["1", "ENDIF", "P2SH,STRICTENC", "UNBALANCED_CONDITIONAL"],
+    ["1", "ELSE", "P2SH,STRICTENC", "UNBALANCED_CONDITIONAL"],
+    ["1 1", "IF ELSE ELSE ENDIF ENDIF", "P2SH,STRICTENC", "UNBALANCED_CONDITIONAL"],
+    ["1 IF", "1 ENDIF", "P2SH,STRICTENC", "UNBALANCED_CONDITIONAL", "IF/ENDIF can't span scriptSig/scriptPubKey"],
+    ["", "IF 1 ENDIF", "P2SH,STRICTENC", "UNBALANCED_CONDITIONAL", "The following tests check the if(stack.size() < N) tests in each opcode"],
+    ["", "NOTIF 1 ENDIF", "P2SH,STRICTENC", "UNBALANCED_CONDITIONAL"],
+    ["'a' 'b'", "CAT", "P2SH,STRICTENC", "DISABLED_OPCODE", "CAT disabled"],
+    ["0", "IF CAT ELSE 1 ENDIF", "P2SH,STRICTENC", "DISABLED_OPCODE", "CAT disabled in unexecuted branch"],
+    ["'abc' 1 1", "SUBSTR", "P2SH,STRICTENC", "DISABLED_OPCODE"],
+    ["'abc' 2 0", "IF LEFT ELSE 1 ENDIF", "P2SH,STRICTENC", "DISABLED_OPCODE"],
+    ["'abc' 2 0", "IF RIGHT ELSE 1 ENDIF", "P2SH,STRICTENC", "DISABLED_OPCODE"],
+    ["'abc'", "IF INVERT ELSE 1 ENDIF", "P2SH,STRICTENC", "DISABLED_OPCODE"],
+    ["1 2 0", "IF AND ELSE 1 ENDIF", "P2SH,STRICTENC", "DISABLED_OPCODE"],
+    ["1 2 0", "IF OR ELSE 1 ENDIF", "P2SH,STRICTENC", "DISABLED_OPCODE"],
+    ["1 2 0", "IF XOR ELSE 1 ENDIF", "P2SH,STRICTENC", "DISABLED_OPCODE"],
+    ["2 0", "IF 2MUL ELSE 1 ENDIF", "P2SH,STRICTENC", "DISABLED_OPCODE"],
+    ["2 0", "IF 2DIV ELSE 1 ENDIF", "P2SH,STRICTENC", "DISABLED_OPCODE"],
+    ["2 2 0", "IF MUL ELSE 1 ENDIF", "P2SH,STRICTENC", "DISABLED_OPCODE"],
+    ["2 2 0", "IF DIV ELSE 1 ENDIF", "P2SH,STRICTENC", "DISABLED_OPCODE"],
+    ["2 2 0", "IF MOD ELSE 1 ENDIF", "P2SH,STRICTENC", "DISABLED_OPCODE"],
+    ["2 2 0", "IF LSHIFT ELSE 1 ENDIF", "P2SH,STRICTENC", "DISABLED_OPCODE"],
+    ["2 2 0", "IF RSHIFT ELSE 1 ENDIF", "P2SH,STRICTENC", "DISABLED_OPCODE"],
+    ["", "DUP", "P2SH,STRICTENC", "INVALID_STACK_OPERATION"],
+    ["1", "DROP DROP 1", "P2SH,STRICTENC", "INVALID_STACK_OPERATION"],
+    ["1", "2DUP", "P2SH,STRICTENC", "INVALID_STACK_OPERATION"],
+    ["1 1", "3DUP", "P2SH,STRICTENC", "INVALID_STACK_OPERATION"],
+    ["1 1 1", "2OVER", "P2SH,STRICTENC", "INVALID_STACK_OPERATION"],
+    ["1 1 1 1 1", "2ROT", "P2SH,STRICTENC", "INVALID_STACK_OPERATION"],
+    ["1 1 1", "2SWAP", "P2SH,STRICTENC", "INVALID_STACK_OPERATION"],
+    ["1", "NIP", "P2SH,STRICTENC", "INVALID_STACK_OPERATION"],
+    ["1", "OVER", "P2SH,STRICTENC", "INVALID_STACK_OPERATION"],
+    ["1 2 3", "3 PICK", "P2SH,STRICTENC", "INVALID_STACK_OPERATION"],
+    ["1 2 3", "-1 PICK", "P2SH,STRICTENC", "INVALID_STACK_OPERATION"],
+    ["1 2 3", "3 ROLL", "P2SH,STRICTENC", "INVALID_STACK_OPERATION"],
+    ["1 1", "ROT", "P2SH,STRICTENC", "INVALID_STACK_OPERATION"],
+    ["1", "SWAP", "P2SH,STRICTENC", "INVALID_STACK_OPERATION"],
+    ["1", "TUCK", "P2SH,STRICTENC", "INVALID_STACK_OPERATION"],
+    ["", "SIZE 1", "P2SH,STRICTENC", "INVALID_STACK_OPERATION"],
+    ["1", "EQUAL 1", "P2SH,STRICTENC", "INVALID_STACK_OPERATION"],
+    ["", "1ADD 1", "P2SH,STRICTENC", "INVALID_STACK_OPERATION"],
+    ["1", "ADD 1", "P2SH,STRICTENC", "INVALID_STACK_OPERATION"],
+    ["1 1", "WITHIN 1", "P2SH,STRICTENC", "INVALID_STACK_OPERATION"],
+    ["", "SHA256 1", "P2SH,STRICTENC", "INVALID_STACK_OPERATION"],
+    ["1", "TOALTSTACK FROMALTSTACK FROMALTSTACK 1", "P2SH,STRICTENC", "INVALID_ALTSTACK_OPERATION"],
+    ["", "FROMALTSTACK 1", "P2SH,STRICTENC", "INVALID_ALTSTACK_OPERATION"],
+    ["1", "NOP1", "P2SH,DISCOURAGE_UPGRADABLE_NOPS", "DISCOURAGE_UPGRADABLE_NOPS"],
+    ["1", "NOP10", "P2SH,DISCOURAGE_UPGRADABLE_NOPS", "DISCOURAGE_UPGRADABLE_NOPS"],
+    ["2147483648 0 ADD", "NOP", "P2SH,STRICTENC", "UNKNOWN_ERROR", "arithmetic operands must be in range [-2^31 +1...2^31 -1]"],
+    ["-2147483648 0 ADD", "NOP", "P2SH,STRICTENC", "UNKNOWN_ERROR", "arithmetic operands must be in range [-2^31 +1...2^31 -1]"],
+    ["2147483647 DUP ADD", "4294967294 NUMEQUAL", "P2SH,STRICTENC", "UNKNOWN_ERROR", "NUMEQUAL must be in numeric range"],
+    ["'abcdef' NOT", "0 EQUAL", "P2SH,STRICTENC", "UNKNOWN_ERROR", "NOT is an arithmetic operand"],
+    ["0x01 0x01", "DROP 1", "MINIMALDATA", "MINIMALDATA", "PUSHBYTES_1 of 0x01 should be OP_1"],
+    ["0x01 0x81", "DROP 1", "MINIMALDATA", "MINIMALDATA", "PUSHBYTES_1 of 0x81 should be OP_1NEGATE"],
+    ["0x01 0x00", "DROP 1", "MINIMALDATA", "OK", "PUSHBYTES_1 of 0x00 is minimal (OP_0 pushes an empty vector)"],
+    ["0x4c 0x00", "DROP 1", "MINIMALDATA", "MINIMALDATA", "empty PUSHDATA1 should be OP_0"],
+    ["0x4c 0x01 0x11", "DROP 1", "MINIMALDATA", "MINIMALDATA", "PUSHDATA1 of 1 byte should be PUSHBYTES_1"],
+    ["0x4d 0x0100 0x11", "DROP 1", "MINIMALDATA", "MINIMALDATA", "PUSHDATA2 of 1 byte should be PUSHBYTES_1"],
+    ["0x02 0x0000", "NOT DROP 1", "MINIMALDATA", "UNKNOWN_ERROR", "non-minimal zero as script number"],
+    ["0x02 0x0100", "1ADD DROP 1", "MINIMALDATA", "UNKNOWN_ERROR", "non-minimal 1 as script number"],
+    ["0x01 0x80", "NOT DROP 1", "MINIMALDATA", "UNKNOWN_ERROR", "negative zero as script number"],
+    ["1 0x02 0x0000", "PICK DROP", "MINIMALDATA", "UNKNOWN_ERROR", "non-minimal PICK argument"],
+    ["1 1", "1", "P2SH,WITNESS,CLEANSTACK", "CLEANSTACK"],
+    ["1 NOP", "1", "SIGPUSHONLY", "SIG_PUSHONLY"],
+    ["NOP 0x01 0x51", "HASH160 0x14 0xda1745e9b549bd0bfa1a569971c77eba30cd5a4b EQUAL", "P2SH,STRICTENC", "SIG_PUSHONLY", "P2SH requires a push-only scriptSig"],
+    ["0x01 0x50", "HASH160 0x14 0x" + hash160(b"\x50").hex() + " EQUAL", "P2SH,STRICTENC", "BAD_OPCODE", "OP_RESERVED in P2SH should fail"],
+    ["0x01 0x00", "HASH160 0x14 0x" + hash160(b"\x00").hex() + " EQUAL", "P2SH,STRICTENC", "EVAL_FALSE", "P2SH redeem script leaving false on the stack"],
+    ["0x4d 0x0902 0x" + "00" * 521, "DROP 1", "P2SH,STRICTENC", "PUSH_SIZE", "521 byte push"],
+    ["", "0 0x02 0x0102", "P2SH,WITNESS", "WITNESS_PROGRAM_WRONG_LENGTH"],
+    ["", "0 0x20 0x" + "11" * 32, "P2SH,WITNESS", "WITNESS_PROGRAM_WITNESS_EMPTY"],
+    ["", "1 0x02 0x0102", "DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM,P2SH,WITNESS", "DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM"],
+]
+
+# script test with a signature: builds the crediting and spending transactions like
+# bitcoin core's test framework, signs with sign(spending tx, scriptpubkey, amount)
+# sign returns (scriptSig bytes, witness list)
+def signed_test(spk: bytes, sign, flags: str, expected: str, comment: str, amount=0, witness_test=False):
+    credit = credit_tx(spk, amount)
+    spend = spend_tx(credit)
+    scriptsig, witness = sign(spend, spk, amount)
+    scriptsig_asm = " ".join(asm_push(e) for e in scriptsig) if isinstance(scriptsig, list) else scriptsig
+    spk_asm = "0x" + spk.hex() if spk else ""
+    entry = [scriptsig_asm, spk_asm, flags, expected, comment]
+    if witness or witness_test:
+        entry = [[element.hex() for element in witness] + [btc(amount)]] + entry
+    return entry
+
+def signature_tests():
+    tests = []
+    k1, k2, k3 = privkey("key1"), privkey("key2"), privkey("key3")
+    pk1, pk2, pk3 = pubkey(k1), pubkey(k2), pubkey(k3)
+    pk1u = pubkey(k1, compressed=False)
+    pk1h = pubkey(k1, compressed=False, hybrid=True)
+
+    def p2pk_test(pub, flags, expected, comment, hash_type=SIGHASH_ALL, corrupt=False, **kwargs):
+        spk = push(pub) + b"\xac"
+        def sign(tx, spk, amount):
+            sig = sign_ecdsa(k1, sighash_legacy(tx, 0, spk, hash_type), hash_type, **kwargs)
+            if corrupt:
+                sig = sig[:10] + bytes([sig[10] ^ 1]) + sig[11:]
+            return [sig], []
+        return signed_test(spk, sign, flags, expected, comment)
+
+    tests.append(p2pk_test(pk1, "P2SH,STRICTENC", "OK", "P2PK"))
+    tests.append(p2pk_test(pk1, "", "EVAL_FALSE", "P2PK, bad sig", corrupt=True))
+    tests.append(p2pk_test(pk1, "NULLFAIL", "NULLFAIL", "P2PK, bad sig with NULLFAIL", corrupt=True))
+    tests.append(p2pk_test(pk1u, "P2SH,STRICTENC", "OK", "P2PK with uncompressed pubkey"))
+    tests.append(p2pk_test(pk1h, "", "OK", "P2PK with hybrid pubkey but no STRICTENC"))
+    tests.append(p2pk_test(pk1h, "STRICTENC", "PUBKEYTYPE", "P2PK with hybrid pubkey"))
+    tests.append(p2pk_test(pk1, "", "OK", "P2PK with high S but no LOW_S", high_s=True))
+    tests.append(p2pk_test(pk1, "LOW_S", "SIG_HIGH_S", "P2PK with high S", high_s=True))
+    tests.append(p2pk_test(pk1, "", "OK", "P2PK with too much R padding but no DERSIG", pad_r=True))
+    tests.append(p2pk_test(pk1, "DERSIG", "SIG_DER", "P2PK with too much R padding", pad_r=True))
+    tests.append(p2pk_test(pk1, "", "OK", "P2PK with undefined hashtype but no STRICTENC", hash_type=0x21))
+    tests.append(p2pk_test(pk1, "STRICTENC", "SIG_HASHTYPE", "P2PK with undefined hashtype", hash_type=0x21))
+    tests.append(p2pk_test(pk1, "P2SH,STRICTENC", "OK", "P2PK with SIGHASH_NONE", hash_type=SIGHASH_NONE))
+    tests.append(p2pk_test(pk1, "P2SH,STRICTENC", "OK", "P2PK with SIGHASH_SINGLE", hash_type=SIGHASH_SINGLE))
+    tests.append(p2pk_test(pk1, "P2SH,STRICTENC", "OK", "P2PK with SIGHASH_ALL|ANYONECANPAY", hash_type=0x81))
+    tests.append(p2pk_test(pk1, "P2SH,STRICTENC", "OK", "P2PK with SIGHASH_SINGLE|ANYONECANPAY", hash_type=0x83))
+
+    spk = push(pk1) + b"\xac\x91"
+    tests.append(["0", "0x" + spk.hex(), "NULLFAIL", "OK", "P2PK NOT with empty signature"])
+    tests.append(["0", "0x" + (push(pk1) + b"\xac").hex(), "NULLFAIL", "EVAL_FALSE", "P2PK with empty signature"])
+
+    def sign_checksigverify_bad(tx, spk, amount):
+        sig = sign_ecdsa(k1, sighash_legacy(tx, 0, spk, SIGHASH_ALL), SIGHASH_ALL)
+        return [sig[:-2] + bytes([sig[-2] ^ 1, sig[-1]])], []
+    tests.append(signed_test(push(pk1) + b"\xad\x51", sign_checksigverify_bad, "", "CHECKSIGVERIFY", "CHECKSIGVERIFY with bad sig"))
+
+    p2pkh_spk = p2pkh_code(pk1)
+    def sign_p2pkh(pub):
+        def sign(tx, spk, amount):
+            return [sign_ecdsa(k1, sighash_legacy(tx, 0, spk, SIGHASH_ALL), SIGHASH_ALL), pub], []
+        return sign
+    tests.append(signed_test(p2pkh_spk, sign_p2pkh(pk1), "P2SH,STRICTENC", "OK", "P2PKH"))
+    tests.append(signed_test(p2pkh_spk, sign_p2pkh(pk2), "P2SH,STRICTENC", "EQUALVERIFY", "P2PKH with wrong pubkey"))
+
+    multisig = b"\x52" + push(pk1) + push(pk2) + push(pk3) + b"\x53\xae"
+    def sign_multisig(dummy, keys, code=None):
+        def sign(tx, spk, amount):
+            script_code = code if code is not None else spk
+            sigs = [sign_ecdsa(k, sighash_legacy(tx, 0, script_code, SIGHASH_ALL), SIGHASH_ALL) for k in keys]
+            elements = [dummy] + sigs
+            if code is not None:
+                elements.append(code)
+            return elements, []
+        return sign
+    tests.append(signed_test(multisig, sign_multisig(b"", [k1, k2]), "P2SH,STRICTENC", "OK", "2-of-3 bare multisig"))
+    tests.append(signed_test(multisig, sign_multisig(b"", [k1, k3]), "P2SH,STRICTENC", "OK", "2-of-3 bare multisig, skipping a key"))
+    tests.append(signed_test(multisig, sign_multisig(b"", [k2, k1]), "P2SH,STRICTENC", "EVAL_FALSE", "2-of-3 with signatures in wrong order"))
+    tests.append(signed_test(multisig, sign_multisig(b"", [k2, k1]), "NULLFAIL", "NULLFAIL", "2-of-3 with signatures in wrong order and NULLFAIL"))
+    tests.append(signed_test(multisig, sign_multisig(b"\x01", [k1, k2]), "", "OK", "2-of-3 with non-null dummy but no NULLDUMMY"))
+    tests.append(signed_test(multisig, sign_multisig(b"\x01", [k1, k2]), "NULLDUMMY", "SIG_NULLDUMMY", "2-of-3 with non-null dummy"))
+    def sign_multisig_nodummy(tx, spk, amount):
+        return [sign_ecdsa(k, sighash_legacy(tx, 0, spk, SIGHASH_ALL), SIGHASH_ALL) for k in (k1, k2)], []
+    tests.append(signed_test(multisig, sign_multisig_nodummy, "", "INVALID_STACK_OPERATION", "2-of-3 without dummy element"))
+    tests.append(signed_test(p2sh(multisig), sign_multisig(b"", [k1, k2], multisig), "P2SH,STRICTENC", "OK", "P2SH(2-of-3)"))
+    tests.append(signed_test(p2sh(multisig), sign_multisig(b"", [k2, k1], multisig), "P2SH,STRICTENC", "EVAL_FALSE", "P2SH(2-of-3) with wrong order"))
+    tests.append(signed_test(p2sh(multisig), sign_multisig(b"", [k2, k1], multisig), "", "OK", "P2SH(2-of-3) with wrong order but no P2SH"))
+
+    # FindAndDelete: the checked signature is also pushed in the scriptPubKey. A valid signature
+    # can't be part of the scriptPubKey (it commits to the crediting txid), so the CHECKSIG fails.
+    other_sig = sign_ecdsa(k1, sha256(b"not the sighash"), SIGHASH_ALL)
+    find_and_delete = push(other_sig) + b"\x75" + push(pk1) + b"\xac\x91"
+    tests.append([asm_push(other_sig), "0x" + find_and_delete.hex(), "", "OK", "FindAndDelete of the checked signature"])
+    tests.append([asm_push(other_sig), "0x" + find_and_delete.hex(), "CONST_SCRIPTCODE", "SIG_FINDANDDELETE", "FindAndDelete with CONST_SCRIPTCODE"])
+    tests.append([asm_push(other_sig), "0x" + find_and_delete.hex(), "NULLFAIL", "NULLFAIL", "FindAndDelete of a failing signature with NULLFAIL"])
+
+    codesep = b"\xab" + push(pk1) + b"\xac"
+    def sign_codesep(tx, spk, amount):
+        return [sign_ecdsa(k1, sighash_legacy(tx, 0, spk[1:], SIGHASH_ALL), SIGHASH_ALL)], []
+    tests.append(signed_test(codesep, sign_codesep, "", "OK", "CODESEPARATOR before CHECKSIG"))
+    tests.append(signed_test(codesep, sign_codesep, "CONST_SCRIPTCODE", "OP_CODESEPARATOR", "CODESEPARATOR with CONST_SCRIPTCODE"))
+    two_sigs = push(pk1) + b"\xad\xab" + push(pk2) + b"\xac"
+    def sign_two_sigs(tx, spk, amount):
+        sig1 = sign_ecdsa(k1, sighash_legacy(tx, 0, spk, SIGHASH_ALL), SIGHASH_ALL)
+        sig2 = sign_ecdsa(k2, sighash_legacy(tx, 0, push(pk2) + b"\xac", SIGHASH_ALL), SIGHASH_ALL)
+        return [sig2, sig1], []
+    tests.append(signed_test(two_sigs, sign_two_sigs, "", "OK", "CODESEPARATOR between two CHECKSIGs"))
+    codesep_skipped = b"\x00\x63\xab\x68" + push(pk1) + b"\xac"
+    def sign_codesep_skipped(tx, spk, amount):
+        return [sign_ecdsa(k1, sighash_legacy(tx, 0, spk, SIGHASH_ALL), SIGHASH_ALL)], []
+    tests.append(signed_test(codesep_skipped, sign_codesep_skipped, "", "OK", "unexecuted CODESEPARATOR does not change the script code"))
+    tests.append(signed_test(codesep_skipped, sign_codesep_skipped, "CONST_SCRIPTCODE", "OP_CODESEPARATOR", "unexecuted CODESEPARATOR with CONST_SCRIPTCODE"))
+
+    # segwit v0
+    amount = 100_000
+    def sign_p2wpkh(pub, sign_amount=None, extra=None):
+        def sign(tx, spk, amount):
+            msg = sighash_v0(tx, 0, p2pkh_code(pub), amount if sign_amount is None else sign_amount, SIGHASH_ALL)
+            witness = [sign_ecdsa(k1, msg, SIGHASH_ALL), pub] + (extra or [])
+            return "", witness
+        return sign
+    tests.append(signed_test(p2wpkh(pk1), sign_p2wpkh(pk1), "P2SH,WITNESS", "OK", "P2WPKH", amount))
+    tests.append(signed_test(p2wpkh(pk1), sign_p2wpkh(pk1, amount + 1), "P2SH,WITNESS", "EVAL_FALSE", "P2WPKH with wrong amount", amount))
+    tests.append(signed_test(p2wpkh(pk1u), sign_p2wpkh(pk1u), "P2SH,WITNESS", "OK", "P2WPKH with uncompressed key", amount))
+    tests.append(signed_test(p2wpkh(pk1u), sign_p2wpkh(pk1u), "P2SH,WITNESS,WITNESS_PUBKEYTYPE", "WITNESS_PUBKEYTYPE", "P2WPKH with uncompressed key and WITNESS_PUBKEYTYPE", amount))
+    tests.append(signed_test(p2wpkh(pk1), sign_p2wpkh(pk1, extra=[b"\x01"]), "P2SH,WITNESS", "WITNESS_PROGRAM_MISMATCH", "P2WPKH with 3 witness elements", amount))
+    tests.append(signed_test(p2wpkh(pk1), sign_p2wpkh(pk1), "P2SH", "OK", "P2WPKH without WITNESS flag", amount))
+    def sign_p2wpkh_malleated(tx, spk, amount):
+        _, witness = sign_p2wpkh(pk1)(tx, spk, amount)
+        return "1", witness
+    tests.append(signed_test(p2wpkh(pk1), sign_p2wpkh_malleated, "P2SH,WITNESS", "WITNESS_MALLEATED", "P2WPKH with non-empty scriptSig", amount))
+
+    def sign_p2wsh(script, keys, dummy=None, sign_script=None):
+        def sign(tx, spk, amount):
+            msg = sighash_v0(tx, 0, sign_script or script, amount, SIGHASH_ALL)
+            sigs = [sign_ecdsa(k, msg, SIGHASH_ALL) for k in keys]
+            return "", ([dummy] if dummy is not None else []) + sigs + [script]
+        return sign
+    checksig = push(pk1) + b"\xac"
+    tests.append(signed_test(p2wsh(checksig), sign_p2wsh(checksig, [k1]), "P2SH,WITNESS", "OK", "P2WSH(P2PK)", amount))
+    tests.append(signed_test(p2wsh(push(pk2) + b"\xac"), sign_p2wsh(checksig, [k1]), "P2SH,WITNESS", "WITNESS_PROGRAM_MISMATCH", "P2WSH with wrong witness script", amount))
+    tests.append(signed_test(p2wsh(multisig), sign_p2wsh(multisig, [k1, k3], b""), "P2SH,WITNESS", "OK", "P2WSH(2-of-3)", amount))
+    tests.append(signed_test(p2wsh(multisig), sign_p2wsh(multisig, [k1, k3], b"\x01"), "P2SH,WITNESS,NULLDUMMY", "SIG_NULLDUMMY", "P2WSH(2-of-3) with non-null dummy", amount))
+    checksig_u = push(pk1u) + b"\xac"
+    tests.append(signed_test(p2wsh(checksig_u), sign_p2wsh(checksig_u, [k1]), "P2SH,WITNESS,WITNESS_PUBKEYTYPE", "WITNESS_PUBKEYTYPE", "P2WSH(P2PK) with uncompressed key", amount))
+    witness_codesep = b"\xab" + checksig
+    tests.append(signed_test(p2wsh(witness_codesep), sign_p2wsh(witness_codesep, [k1], sign_script=checksig), "P2SH,WITNESS,CONST_SCRIPTCODE", "OK", "CODESEPARATOR in P2WSH is not affected by CONST_SCRIPTCODE", amount))
+    minimal_if = b"\x63\x51\x67\x00\x68"
+    tests.append([["02", minimal_if.hex(), btc(0)], "", "0x" + p2wsh(minimal_if).hex(), "P2SH,WITNESS", "OK", "P2WSH IF with non-minimal argument but no MINIMALIF"])
+    tests.append([["02", minimal_if.hex(), btc(0)], "", "0x" + p2wsh(minimal_if).hex(), "P2SH,WITNESS,MINIMALIF", "MINIMALIF", "P2WSH IF with non-minimal argument"])
+    tests.append([["01", minimal_if.hex(), btc(0)], "", "0x" + p2wsh(minimal_if).hex(), "P2SH,WITNESS,MINIMALIF", "OK", "P2WSH IF with minimal argument"])
+    tests.append([["01", "51", btc(0)], "", "0x" + p2wsh(b"\x51").hex(), "P2SH,WITNESS", "CLEANSTACK", "P2WSH requires a clean stack"])
+    tests.append([["", btc(0)], "", "0x" + p2wsh(b"\x51").hex(), "P2SH,WITNESS", "WITNESS_PROGRAM_MISMATCH", "P2WSH with empty witness script"])
+    tests.append([["00" * 521, "75" + "51", btc(0)], "", "0x" + p2wsh(b"\x75\x51").hex(), "P2SH,WITNESS", "PUSH_SIZE", "P2WSH with 521 byte witness element"])
+
+    def sign_nested(redeem, inner_sign):
+        def sign(tx, spk, amount):
+            _, witness = inner_sign(tx, redeem, amount)
+            return [redeem], witness
+        return sign
+    tests.append(signed_test(p2sh(p2wpkh(pk1)), sign_nested(p2wpkh(pk1), sign_p2wpkh(pk1)), "P2SH,WITNESS", "OK", "P2SH(P2WPKH)", amount))
+    tests.append(signed_test(p2sh(p2wsh(checksig)), sign_nested(p2wsh(checksig), sign_p2wsh(checksig, [k1])), "P2SH,WITNESS", "OK", "P2SH(P2WSH)", amount))
+    def sign_nested_malleated(tx, spk, amount):
+        _, witness = sign_p2wpkh(pk1)(tx, spk, amount)
+        return [b"", p2wpkh(pk1)], witness
+    tests.append(signed_test(p2sh(p2wpkh(pk1)), sign_nested_malleated, "P2SH,WITNESS", "WITNESS_MALLEATED_P2SH", "P2SH(P2WPKH) with extra scriptSig push", amount))
+    tests.append(signed_test(p2sh(p2wpkh(pk1)), sign_nested(p2wpkh(pk1), sign_p2wpkh(pk1)), "P2SH", "OK", "P2SH(P2WPKH) without WITNESS flag", amount))
+    def sign_p2pkh_with_witness(tx, spk, amount):
+        scriptsig, _ = sign_p2pkh(pk1)(tx, spk, amount)
+        return scriptsig, [b"\x01"]
+    tests.append(signed_test(p2pkh_spk, sign_p2pkh_with_witness, "P2SH,WITNESS", "WITNESS_UNEXPECTED", "P2PKH with witness"))
+
+    tests.append([["00", btc(0)], "", "0x5102" + "0102", "P2SH,WITNESS", "OK", "Unknown witness version is unencumbered"])
+    tests.append([["00", btc(0)], "", "0x6002" + "0102", "P2SH,WITNESS,DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM", "DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM", "Discouraged witness version 16"])
+
+    # taproot
+    tests.extend(taproot_tests())
+    return tests
+
+def taproot_tests():
+    tests = []
+    amount = 50_000
+    internal = privkey("taproot internal")
+    leaf_key = privkey("taproot leaf")
+    leaf_key2 = privkey("taproot leaf 2")
+    flags = "P2SH,WITNESS,TAPROOT"
+
+    tweaked, output_key, _ = taproot_tweak(internal, b"")
+    key_spk = b"\x51\x20" + output_key
+
+    def key_path(hash_type=0, corrupt=False, size=None, sign_amount=None, annex=None):
+        def sign(tx, spk, amount):
+            spent = [(amount if sign_amount is None else sign_amount, spk)]
+            sig = sign_schnorr(tweaked, sighash_taproot(tx, 0, spent, hash_type, annex))
+            if hash_type:
+                sig += bytes([hash_type])
+            if corrupt:
+                sig = bytes([sig[0] ^ 1]) + sig[1:]
+            if size is not None:
+                sig = (sig + bytes(2))[:size]
+            return "", [sig] + ([annex] if annex else [])
+        return sign
+    tests.append(signed_test(key_spk, key_path(), flags, "OK", "Taproot key path spend", amount))
+    tests.append(signed_test(key_spk, key_path(SIGHASH_ALL), flags, "OK", "Taproot key path spend with SIGHASH_ALL", amount))
+    tests.append(signed_test(key_spk, key_path(SIGHASH_NONE | SIGHASH_ANYONECANPAY), flags, "OK", "Taproot key path spend with SIGHASH_NONE|ANYONECANPAY", amount))
+    tests.append(signed_test(key_spk, key_path(SIGHASH_SINGLE), flags, "OK", "Taproot key path spend with SIGHASH_SINGLE", amount))
+    tests.append(signed_test(key_spk, key_path(annex=b"\x50\x01\x02"), flags, "OK", "Taproot key path spend with annex", amount))
+    tests.append(signed_test(key_spk, key_path(corrupt=True), flags, "SCHNORR_SIG", "Taproot key path spend with invalid signature", amount))
+    tests.append(signed_test(key_spk, key_path(sign_amount=amount - 1), flags, "SCHNORR_SIG", "Taproot key path spend with wrong amount", amount))
+    tests.append(signed_test(key_spk, key_path(corrupt=True), "P2SH,WITNESS", "OK", "Taproot key path spend with invalid signature but no TAPROOT", amount))
+    tests.append(signed_test(key_spk, key_path(corrupt=True), "P2SH,WITNESS,DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM", "OK", "Taproot without TAPROOT succeeds before the upgradable witness program check", amount))
+    tests.append(signed_test(key_spk, key_path(size=63), flags, "SCHNORR_SIG_SIZE", "Taproot key path spend with 63 byte signature", amount))
+    tests.append(signed_test(key_spk, key_path(SIGHASH_ALL, size=66), flags, "SCHNORR_SIG_SIZE", "Taproot key path spend with 66 byte signature", amount))
+    def sign_explicit_default(tx, spk, amount):
+        sig = sign_schnorr(tweaked, sighash_taproot(tx, 0, [(amount, spk)], 0))
+        return "", [sig + b"\x00"]
+    tests.append(signed_test(key_spk, sign_explicit_default, flags, "SCHNORR_SIG_HASHTYPE", "Taproot key path spend with explicit SIGHASH_DEFAULT", amount))
+    tests.append(signed_test(key_spk, key_path(0x04), flags, "SCHNORR_SIG_HASHTYPE", "Taproot key path spend with undefined hash type", amount))
+    tests.append(signed_test(key_spk, lambda tx, spk, amount: ("", []), flags, "WITNESS_PROGRAM_WITNESS_EMPTY", "Taproot spend with empty witness", amount, witness_test=True))
+    tests.append(signed_test(p2sh(key_spk), lambda tx, spk, amount: ([key_spk], [b"\x01"]), flags, "OK", "P2SH wrapped taproot is unencumbered", amount))
+
+    def script_path(script, keys, leaf_version=0xc0, stack=None, control_change=None, codesep_pos=0xffffffff, annex=None, hash_type=0, internal_key=internal):
+        spk, control = taproot_single_leaf(internal_key, script, leaf_version)
+        if control_change:
+            control = control_change(control)
+        def sign(tx, spk, amount):
+            leaf = (tapleaf_hash(script, leaf_version), codesep_pos)
+            msg = sighash_taproot(tx, 0, [(amount, spk)], hash_type, annex, leaf)
+            sigs = [sign_schnorr(k, msg) + (bytes([hash_type]) if hash_type else b"") for k in keys]
+            initial = stack if stack is not None else sigs
+            return "", initial + [script, control] + ([annex] if annex else [])
+        return spk, sign
+    checksig = push(xonly(leaf_key)) + b"\xac"
+    tests.append(signed_test(*script_path(checksig, [leaf_key]), flags, "OK", "Tapscript CHECKSIG", amount))
+    tests.append(signed_test(*script_path(checksig, [leaf_key], hash_type=SIGHASH_SINGLE | SIGHASH_ANYONECANPAY), flags, "OK", "Tapscript CHECKSIG with SIGHASH_SINGLE|ANYONECANPAY", amount))
+    tests.append(signed_test(*script_path(checksig, [leaf_key], annex=b"\x50"), flags, "OK", "Tapscript CHECKSIG with annex", amount))
+    tests.append(signed_test(*script_path(checksig, [leaf_key2]), flags, "SCHNORR_SIG", "Tapscript CHECKSIG with signature of other key", amount))
+    tests.append(signed_test(*script_path(checksig, [leaf_key2]), "P2SH,WITNESS,TAPROOT,NULLFAIL", "SCHNORR_SIG", "Tapscript failing non-empty signature is invalid independent of NULLFAIL", amount))
+    tests.append(signed_test(*script_path(checksig + b"\x91", [], stack=[b""]), flags, "OK", "Tapscript CHECKSIG NOT with empty signature", amount))
+    codesep = b"\xab" + checksig
+    tests.append(signed_test(*script_path(codesep, [leaf_key], codesep_pos=0), flags, "OK", "Tapscript with CODESEPARATOR", amount))
+    tests.append(signed_test(*script_path(codesep, [leaf_key]), flags, "SCHNORR_SIG", "Tapscript with CODESEPARATOR not committed to", amount))
+    tests.append(signed_test(*script_path(checksig, [leaf_key], control_change=lambda c: c + b"\x00"), flags, "TAPROOT_WRONG_CONTROL_SIZE", "Tapscript with wrong control block size", amount))
+    tests.append(signed_test(*script_path(checksig, [leaf_key], control_change=lambda c: bytes([c[0]]) + xonly(leaf_key)), flags, "WITNESS_PROGRAM_MISMATCH", "Tapscript with wrong internal key", amount))
+    tests.append(signed_test(*script_path(checksig, [leaf_key], control_change=lambda c: bytes([c[0] ^ 1]) + c[1:]), flags, "WITNESS_PROGRAM_MISMATCH", "Tapscript with wrong output key parity", amount))
+    checksigadd = push(xonly(leaf_key)) + b"\xac" + push(xonly(leaf_key2)) + b"\xba\x52\x9c"
+    spk, sign = script_path(checksigadd, [])
+    def sign_2of2(tx, spk_, amount, sign=sign):
+        leaf = (tapleaf_hash(checksigadd), 0xffffffff)
+        msg = sighash_taproot(tx, 0, [(amount, spk_)], 0, None, leaf)
+        _, witness = sign(tx, spk_, amount)
+        return "", [sign_schnorr(leaf_key2, msg), sign_schnorr(leaf_key, msg)] + witness
+    tests.append(signed_test(spk, sign_2of2, flags, "OK", "Tapscript 2-of-2 with CHECKSIGADD", amount))
+    multisig = b"\x51" + push(xonly(leaf_key)) + b"\x51\xae"
+    tests.append(signed_test(*script_path(multisig, [], stack=[b"", b""]), flags, "TAPSCRIPT_CHECKMULTISIG", "CHECKMULTISIG is disabled in tapscript", amount))
+    minimal_if = b"\x63\x51\x67\x00\x68"
+    tests.append(signed_test(*script_path(minimal_if, [], stack=[b"\x02"]), flags, "TAPSCRIPT_MINIMALIF", "Tapscript IF with non-minimal argument", amount))
+    tests.append(signed_test(*script_path(minimal_if, [], stack=[b"\x01"]), flags, "OK", "Tapscript IF with minimal argument", amount))
+    op_success = b"\x50"
+    tests.append(signed_test(*script_path(op_success, [], stack=[]), flags, "OK", "Tapscript with OP_SUCCESS80", amount))
+    tests.append(signed_test(*script_path(op_success, [], stack=[]), flags + ",DISCOURAGE_OP_SUCCESS", "DISCOURAGE_OP_SUCCESS", "Tapscript with discouraged OP_SUCCESS80", amount))
+    tests.append(signed_test(*script_path(b"\x00\x63\x89\x68\x51", [], stack=[]), flags, "OK", "OP_SUCCESS in unexecuted branch", amount))
+    tests.append(signed_test(*script_path(b"\x6a", [], stack=[], leaf_version=0xc2), flags, "OK", "Unknown leaf version is unencumbered", amount))
+    tests.append(signed_test(*script_path(b"\x6a", [], stack=[], leaf_version=0xc2), flags + ",DISCOURAGE_UPGRADABLE_TAPROOT_VERSION", "DISCOURAGE_UPGRADABLE_TAPROOT_VERSION", "Discouraged unknown leaf version", amount))
+    unknown_key = push(b"\x02" + xonly(leaf_key)) + b"\xac"
+    tests.append(signed_test(*script_path(unknown_key, [], stack=[b"\x01"]), flags, "OK", "Tapscript unknown pubkey type", amount))
+    tests.append(signed_test(*script_path(unknown_key, [], stack=[b"\x01"]), flags + ",DISCOURAGE_UPGRADABLE_PUBKEYTYPE", "DISCOURAGE_UPGRADABLE_PUBKEYTYPE", "Tapscript discouraged unknown pubkey type", amount))
+    tests.append(signed_test(*script_path(b"\x00\xac", [], stack=[b"\x01"]), flags, "PUBKEYTYPE", "Tapscript with empty pubkey", amount))
+    tests.append(signed_test(*script_path(b"\x75\x51", [], stack=[b"\x00" * 521]), flags, "PUSH_SIZE", "Tapscript with 521 byte witness element", amount))
+    tests.append(signed_test(*script_path(b"\x51\x51", [], stack=[]), flags, "CLEANSTACK", "Tapscript requires a clean stack", amount))
+    heavy = (b"\x76" + push(xonly(leaf_key)) + b"\xad") * 30 + checksig
+    tests.append(signed_test(*script_path(heavy, [leaf_key]), flags, "TAPSCRIPT_VALIDATION_WEIGHT", "Tapscript exceeding the validation weight budget", amount))
+    light = (b"\x76" + push(xonly(leaf_key)) + b"\xad") * 2 + checksig
+    tests.append(signed_test(*script_path(light, [leaf_key]), flags, "OK", "Tapscript within the validation weight budget", amount))
+    return tests
+
+# ----------------------------------------------------------------- tx tests
+
+# prevout entry of tx_valid.json/tx_invalid.json: [prevout hash, index, scriptPubKey asm, amount]
+def prevout_entry(txin, spk: bytes, amount: int):
+    return [txin["txid"][::-1].hex(), txin["vout"], "0x" + spk.hex() if spk else "", amount]
+
+def funding_txid(name: str) -> bytes:
+    return sha256(name.encode())
+
+def tx_tests():
+    valid = [
+        ["The following are deserialized transactions which are valid."],
+        ["They are in the form"],
+        ["[[[prevout hash, prevout index, prevout scriptPubKey, amount?], [input 2], ...],"],
+        ["serializedTransaction, excluded verifyFlags]"],
+        ["Generated by test_scripts/gen_core_vectors.py"],
+    ]
+    invalid = [
+        ["The following are deserialized transactions which are invalid."],
+        ["They are in the form"],
+        ["[[[prevout hash, prevout index, prevout scriptPubKey, amount?], [input 2], ...],"],
+        ["serializedTransaction, verifyFlags]"],
+        ["Generated by test_scripts/gen_core_vectors.py"],
+    ]
+    k1, k2 = privkey("key1"), privkey("key2")
+    pk1, pk2 = pubkey(k1), pubkey(k2)
+    out_spk = p2wpkh(pk2)
+
+    def new_tx(inputs, outputs=None, version=1, locktime=0):
+        return {"version": version, "locktime": locktime, "vin": inputs,
+                "vout": outputs or [{"value": 10_000, "spk": out_spk}]}
+
+    def entry(tx, spent, flags, comment):
+        prevouts = [prevout_entry(txin, spk, amount) for txin, (amount, spk) in zip(tx["vin"], spent)]
+        return [comment], [prevouts, serialize_tx(tx).hex(), flags]
+
+    # P2PKH + P2WPKH
+    spent = [(50_000, p2pkh_code(pk1)), (60_000, p2wpkh(pk1))]
+    tx = new_tx([new_input(funding_txid("a"), 0), new_input(funding_txid("b"), 3)])
+    tx["vin"][0]["scriptsig"] = push(sign_ecdsa(k1, sighash_legacy(tx, 0, spent[0][1], SIGHASH_ALL), SIGHASH_ALL)) + push(pk1)
+    tx["vin"][1]["witness"] = [sign_ecdsa(k1, sighash_v0(tx, 1, p2pkh_code(pk1), 60_000, SIGHASH_ALL), SIGHASH_ALL), pk1]
+    valid.extend(entry(tx, spent, "", "P2PKH and P2WPKH inputs"))
+    swapped = dict(tx, vin=[dict(tx["vin"][0]), dict(tx["vin"][1])])
+    swapped["vin"][1]["witness"] = [sign_ecdsa(k1, sighash_v0(tx, 1, p2pkh_code(pk1), 60_001, SIGHASH_ALL), SIGHASH_ALL), pk1]
+    invalid.extend(entry(swapped, spent, "P2SH,WITNESS", "P2WPKH input signed with wrong amount"))
+
+    # SIGHASH_SINGLE with input index out of range of the outputs signs the number one
+    spent = [(20_000, push(pk1) + b"\xac"), (20_000, push(pk1) + b"\xac")]
+    tx = new_tx([new_input(funding_txid("c"), 0), new_input(funding_txid("c"), 1)])
+    for i in range(2):
+        hash_type = SIGHASH_ALL if i == 0 else SIGHASH_SINGLE
+        tx["vin"][i]["scriptsig"] = push(sign_ecdsa(k1, sighash_legacy(tx, i, spent[i][1], hash_type), hash_type))
+    valid.extend(entry(tx, spent, "", "SIGHASH_SINGLE bug: input without corresponding output signs 1"))
+
+    # the SIGHASH_SINGLE bug signature does not commit to the tx, so it can be part of the
+    # spent scriptPubKey and is removed from the script code by FindAndDelete
+    sig = sign_ecdsa(k1, (1).to_bytes(32, "little"), SIGHASH_SINGLE)
+    spent = [(20_000, push(pk1) + b"\xac"), (20_000, push(sig) + b"\x75" + push(pk1) + b"\xac")]
+    tx = new_tx([new_input(funding_txid("n"), 0), new_input(funding_txid("n"), 1)])
+    tx["vin"][0]["scriptsig"] = push(sign_ecdsa(k1, sighash_legacy(tx, 0, spent[0][1], SIGHASH_ALL), SIGHASH_ALL))
+    tx["vin"][1]["scriptsig"] = push(sig)
+    valid.extend(entry(tx, spent, "CONST_SCRIPTCODE", "FindAndDelete of a SIGHASH_SINGLE bug signature"))
+    invalid.extend(entry(tx, spent, "CONST_SCRIPTCODE", "FindAndDelete of a SIGHASH_SINGLE bug signature with CONST_SCRIPTCODE"))
+
+    # multisig with non-null dummy: valid with NULLDUMMY excluded, invalid with it
+    multisig = b"\x51" + push(pk1) + push(pk2) + b"\x52\xae"
+    spent = [(30_000, p2sh(multisig))]
+    tx = new_tx([new_input(funding_txid("d"), 1)])
+    sig = sign_ecdsa(k1, sighash_legacy(tx, 0, multisig, SIGHASH_ALL), SIGHASH_ALL)
+    tx["vin"][0]["scriptsig"] = b"\x51" + push(sig) + push(multisig)
+    valid.extend(entry(tx, spent, "NULLDUMMY", "P2SH 1-of-2 multisig with non-null dummy, NULLDUMMY excluded"))
+    invalid.extend(entry(tx, spent, "P2SH,NULLDUMMY", "P2SH 1-of-2 multisig with non-null dummy"))
+
+    # high S signature
+    spent = [(40_000, p2pkh_code(pk1))]
+    tx = new_tx([new_input(funding_txid("e"), 0)])
+    sig = sign_ecdsa(k1, sighash_legacy(tx, 0, spent[0][1], SIGHASH_ALL), SIGHASH_ALL, high_s=True)
+    tx["vin"][0]["scriptsig"] = push(sig) + push(pk1)
+    valid.extend(entry(tx, spent, "LOW_S", "High S signature with LOW_S excluded"))
+    invalid.extend(entry(tx, spent, "LOW_S", "High S signature"))
+
+    # P2SH(P2WSH) and P2WSH multisig with different hash types
+    multisig = b"\x52" + push(pk1) + push(pk2) + b"\x52\xae"
+    spent = [(70_000, p2sh(p2wsh(multisig))), (80_000, p2wsh(multisig))]
+    tx = new_tx([new_input(funding_txid("f"), 0, 0xfffffffe), new_input(funding_txid("g"), 2, 0xfffffffe)],
+                [{"value": 10_000, "spk": out_spk}, {"value": 20_000, "spk": p2wpkh(pk1)}], 2, 100)
+    tx["vin"][0]["scriptsig"] = push(p2wsh(multisig))
+    for i, hash_types in enumerate([(SIGHASH_ALL, SIGHASH_NONE | SIGHASH_ANYONECANPAY), (SIGHASH_SINGLE, 0x81)]):
+        sigs = [sign_ecdsa(k, sighash_v0(tx, i, multisig, spent[i][0], h), h) for k, h in zip((k1, k2), hash_types)]
+        tx["vin"][i]["witness"] = [b""] + sigs + [multisig]
+    valid.extend(entry(tx, spent, "", "P2SH(P2WSH) and P2WSH 2-of-2 multisig with mixed hash types"))
+
+    # taproot key path spends commit to the amounts and scripts of all inputs
+    internal = privkey("taproot internal")
+    tweaked, output_key, _ = taproot_tweak(internal, b"")
+    tr_spk = b"\x51\x20" + output_key
+    spent = [(90_000, tr_spk), (25_000, p2wpkh(pk1)), (35_000, tr_spk)]
+    tx = new_tx([new_input(funding_txid("h"), 0), new_input(funding_txid("i"), 1), new_input(funding_txid("j"), 7)], version=2)
+    tx["vin"][0]["witness"] = [sign_schnorr(tweaked, sighash_taproot(tx, 0, spent, 0))]
+    tx["vin"][1]["witness"] = [sign_ecdsa(k1, sighash_v0(tx, 1, p2pkh_code(pk1), 25_000, SIGHASH_ALL), SIGHASH_ALL), pk1]
+    tx["vin"][2]["witness"] = [sign_schnorr(tweaked, sighash_taproot(tx, 2, spent, SIGHASH_ALL)) + b"\x01"]
+    valid.extend(entry(tx, spent, "", "Taproot key path spends mixed with a P2WPKH input"))
+    wrong_spent = [spent[0], (25_001, spent[1][1]), spent[2]]
+    prevouts, tx_hex, _ = entry(tx, wrong_spent, "", "")[1]
+    invalid.extend([["Taproot key path spend committing to the amounts of all inputs"], [prevouts, tx_hex, "P2SH,WITNESS,TAPROOT"]])
+
+    # taproot script path spend with annex
+    leaf_key = privkey("taproot leaf")
+    script = push(xonly(leaf_key)) + b"\xac"
+    spk, control = taproot_single_leaf(internal, script)
+    spent = [(45_000, spk)]
+    tx = new_tx([new_input(funding_txid("k"), 0)], version=2)
+    annex = b"\x50\xaa"
+    msg = sighash_taproot(tx, 0, spent, 0, annex, (tapleaf_hash(script), 0xffffffff))
+    tx["vin"][0]["witness"] = [sign_schnorr(leaf_key, msg), script, control, annex]
+    valid.extend(entry(tx, spent, "", "Taproot script path spend with annex"))
+
+    # CHECKLOCKTIMEVERIFY
+    def cltv_tx(value, locktime, sequence=0xfffffffe, name="l"):
+        spk = push(script_num(value)) + b"\xb1\x75\x51" if value > 16 else bytes([0x50 + value]) + b"\xb1\x75\x51"
+        tx = new_tx([new_input(funding_txid(name), 0, sequence)], locktime=locktime)
+        return tx, [(10_000, spk)]
+    tx, spent = cltv_tx(100, 100)
+    valid.extend(entry(tx, spent, "", "CHECKLOCKTIMEVERIFY with equal locktime"))
+    tx, spent = cltv_tx(100, 499_999_999)
+    valid.extend(entry(tx, spent, "", "CHECKLOCKTIMEVERIFY with higher block height locktime"))
+    tx, spent = cltv_tx(100, 99)
+    invalid.extend(entry(tx, spent, "CHECKLOCKTIMEVERIFY", "CHECKLOCKTIMEVERIFY with lower locktime"))
+    tx, spent = cltv_tx(100, 500_000_000)
+    invalid.extend(entry(tx, spent, "CHECKLOCKTIMEVERIFY", "CHECKLOCKTIMEVERIFY comparing block height with timestamp"))
+    tx, spent = cltv_tx(100, 100, 0xffffffff)
+    invalid.extend(entry(tx, spent, "CHECKLOCKTIMEVERIFY", "CHECKLOCKTIMEVERIFY with final input sequence"))
+    valid.extend(entry(tx, spent, "CHECKLOCKTIMEVERIFY,DISCOURAGE_UPGRADABLE_NOPS", "CHECKLOCKTIMEVERIFY as NOP2 without the flag"))
+
+    # CHECKSEQUENCEVERIFY
+    def csv_tx(value, sequence, version=2):
+        spk = bytes([0x50 + value]) + b"\xb2\x75\x51"
+        tx = new_tx([new_input(funding_txid("m"), 0, sequence)], version=version)
+        return tx, [(10_000, spk)]
+    tx, spent = csv_tx(10, 10)
+    valid.extend(entry(tx, spent, "", "CHECKSEQUENCEVERIFY with equal relative height"))
+    tx, spent = csv_tx(10, 9)
+    invalid.extend(entry(tx, spent, "CHECKSEQUENCEVERIFY", "CHECKSEQUENCEVERIFY with lower relative height"))
+    tx, spent = csv_tx(10, 10, 1)
+    invalid.extend(entry(tx, spent, "CHECKSEQUENCEVERIFY", "CHECKSEQUENCEVERIFY with tx version 1"))
+    tx, spent = csv_tx(10, 10 | (1 << 22))
+    invalid.extend(entry(tx, spent, "CHECKSEQUENCEVERIFY", "CHECKSEQUENCEVERIFY comparing blocks with time units"))
+    tx, spent = csv_tx(10, 10 | (1 << 31))
+    invalid.extend(entry(tx, spent, "CHECKSEQUENCEVERIFY", "CHECKSEQUENCEVERIFY with disabled input sequence"))
+
+    # context free checks (CheckTransaction), BADTX entries fail them independent of the scripts
+    max_money = 21_000_000 * 100_000_000
+    tx = new_tx([new_input(funding_txid("o"), 0)], [{"value": max_money, "spk": b"\x51"}])
+    valid.extend(entry(tx, [(max_money, b"\x51")], "", "Output value of exactly MAX_MONEY"))
+    tx = new_tx([new_input(funding_txid("o"), 0), new_input(funding_txid("o"), 0)])
+    invalid.extend(entry(tx, [(20_000, b"\x51")] * 2, "BADTX", "Duplicate inputs (CVE-2018-17144)"))
+    tx = new_tx([new_input(funding_txid("o"), 0)], [{"value": max_money + 1, "spk": b"\x51"}])
+    invalid.extend(entry(tx, [(20_000, b"\x51")], "BADTX", "Output value above MAX_MONEY"))
+    tx = new_tx([new_input(funding_txid("o"), 0)], [{"value": max_money, "spk": b"\x51"}, {"value": 1, "spk": b"\x51"}])
+    invalid.extend(entry(tx, [(20_000, b"\x51")], "BADTX", "Sum of the output values above MAX_MONEY"))
+    tx = new_tx([new_input(funding_txid("o"), 0)])
+    tx["vout"] = []
+    invalid.extend(entry(tx, [(20_000, b"\x51")], "BADTX", "No outputs"))
+    tx = new_tx([new_input(funding_txid("o"), 0), new_input(bytes(32), 0xffffffff)])
+    invalid.extend(entry(tx, [(20_000, b"\x51"), (0, b"")], "BADTX", "Null prevout in a transaction with two inputs (no coinbase)"))
+    for length in (1, 101):
+        tx = new_tx([new_input(bytes(32), 0xffffffff)])
+        tx["vin"][0]["scriptsig"] = b"\x51" * length
+        invalid.extend(entry(tx, [(0, b"")], "BADTX", "Coinbase with a %d byte scriptSig" % length))
+    return valid, invalid
+
+def write_json(name: str, entries):
+    lines = ",\n".join(json.dumps(entry) for entry in entries)
+    with open(os.path.join(DATA_DIR, name), "w") as file:
+        file.write("[\n" + lines + "\n]\n")
+
+if __name__ == "__main__":
+    os.makedirs(DATA_DIR, exist_ok=True)
+    write_json("script_tests.json", PLAIN_TESTS + signature_tests())
+    valid, invalid = tx_tests()
+    write_json("tx_valid.json", valid)
+    write_json("tx_invalid.json", invalid)
+    print("Written test vectors to " + os.path.normpath(DATA_DIR))
@@ -0,0 +1,277 @@
+// Shared helpers of the integration tests running bitcoin core's script and transaction
+// test vectors (tests/data/) against the script interpreter: the asm parser of core's
+// test framework, the crediting/spending transactions of the script tests and the
+// per-opcode and per-flag pass/fail report.
+// Not every test binary uses all helpers.
+#![allow(dead_code)]
+
+use code_challenge_f321x::parsing::transaction_structs::{Script, Transaction, TxIn, TxOut};
+use code_challenge_f321x::validation::{
+    opcodes::opcode_name,
+    script::{get_last_push, split_script},
+    utils::{encode_script_num, serialize_push, varint},
+    validate_parsing::{get_txid, serialize_input, serialize_output},
+    verify_flags::VerifyFlags,
+};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+// reads a json test vector file of tests/data/
+// returns: the entries of the top level array
+pub fn load_vectors(file_name: &str) -> Vec<Value> {
+    let path = format!("{}/tests/data/{}", env!("CARGO_MANIFEST_DIR"), file_name);
+    let content = std::fs::read_to_string(&path).expect("Unable to read test vector file");
+    match serde_json::from_str(&content).expect("Test vector file is no valid json") {
+        Value::Array(entries) => entries,
+        _ => panic!("Test vector file {} is no json array", path),
+    }
+}
+
+// returns: all flags the interpreter knows (core's tx_valid tests exclude flags from these)
+pub fn all_flags() -> VerifyFlags {
+    VerifyFlags::NAMES
+        .iter()
+        .fold(VerifyFlags::NONE, |flags, (_, flag)| flags | *flag)
+}
+
+// returns: opcode of an opcode name as written in core's test vectors (with or without OP_)
+fn parse_opcode(name: &str) -> Option<u8> {
+    let name = name.strip_prefix("OP_").unwrap_or(name);
+    match name {
+        "CHECKLOCKTIMEVERIFY" | "NOP2" => return Some(0xb1),
+        "CHECKSEQUENCEVERIFY" | "NOP3" => return Some(0xb2),
+        _ => (),
+    }
+    // like core only OP_RESERVED and the opcodes from OP_NOP on are accepted by name
+    (0x61..=0xba)
+        .chain([0x50])
+        .find(|opcode| opcode_name(*opcode).strip_prefix("OP_") == Some(name))
+}
+
+// parses script asm the same way as ParseScript() of core's test framework: decimal
+// numbers are pushed as script numbers, 0x.. is inserted as raw bytes, 'text' is pushed
+// returns: script bytes or Err(reason)
+pub fn parse_script(asm: &str) -> Result<Vec<u8>, String> {
+    let mut script: Vec<u8> = Vec::new();
+
+    for word in asm.split_whitespace() {
+        let digits = word.strip_prefix('-').unwrap_or(word);
+        if !digits.is_empty() && digits.bytes().all(|byte| byte.is_ascii_digit()) {
+            let number: i64 = word
+                .parse()
+                .map_err(|_| format!("Number out of range: {}", word))?;
+            if !(-0xffffffff..=0xffffffff).contains(&number) {
+                return Err(format!("Number out of range: {}", word));
+            }
+            match number {
+                0 => script.push(0x00),
+                -1 | 1..=16 => script.push((number + 0x50) as u8),
+                _ => script.extend(serialize_push(&encode_script_num(number))),
+            }
+        } else if let Some(hex_data) = word.strip_prefix("0x").filter(|data| !data.is_empty()) {
+            script.extend(hex::decode(hex_data).map_err(|err| format!("{}: {}", word, err))?);
+        } else if word.len() >= 2 && word.starts_with('\'') && word.ends_with('\'') {
+            script.extend(serialize_push(&word.as_bytes()[1..word.len() - 1]));
+        } else {
+            match parse_opcode(word) {
+                Some(opcode) => script.push(opcode),
+                None => return Err(format!("Unknown opcode name: {}", word)),
+            }
+        }
+    }
+    Ok(script)
+}
+
+fn new_input(txid: String, vout: u32, scriptsig: &[u8], witness: &[Vec<u8>]) -> TxIn {
+    TxIn {
+        in_type: Default::default(),
+        txid,
+        vout,
+        scriptsig: Some(hex::encode(scriptsig)).filter(|scriptsig| !scriptsig.is_empty()),
+        scriptsig_asm: None,
+        prevout: new_prevout(&[], 0),
+        witness: Some(witness.iter().map(hex::encode).collect())
+            .filter(|witness: &Vec<String>| !witness.is_empty()),
+        inner_witnessscript_asm: None,
+        inner_redeemscript_asm: None,
+        is_coinbase: false,
+        sequence: 0xffffffff,
+    }
+}
+
+fn new_prevout(scriptpubkey: &[u8], value: u64) -> Script {
+    Script {
+        scriptpubkey: hex::encode(scriptpubkey),
+        scriptpubkey_asm: String::new(),
+        scriptpubkey_type: String::new(),
+        scriptpubkey_address: None,
+        value,
+    }
+}
+
+fn new_output(scriptpubkey: &[u8], value: u64) -> TxOut {
+    TxOut {
+        scriptpubkey: Some(hex::encode(scriptpubkey)),
+        scriptpubkey_asm: String::new(),
+        scriptpubkey_type: String::new(),
+        scriptpubkey_address: None,
+        value,
+    }
+}
+
+fn new_transaction(version: i32, locktime: u32, vin: Vec<TxIn>, vout: Vec<TxOut>) -> Transaction {
+    Transaction {
+        meta: Default::default(),
+        version,
+        locktime,
+        vin,
+        vout,
+    }
+}
+
+// returns: txid (display byte order, hex) of the transaction
+pub fn txid_hex(tx: &Transaction) -> String {
+    let mut preimage: Vec<u8> = tx.version.to_le_bytes().to_vec();
+    preimage.extend(varint(tx.vin.len() as u128));
+    for txin in &tx.vin {
+        preimage.extend(serialize_input(txin).unwrap());
+    }
+    preimage.extend(varint(tx.vout.len() as u128));
+    for txout in &tx.vout {
+        preimage.extend(serialize_output(txout).unwrap());
+    }
+    preimage.extend(tx.locktime.to_le_bytes());
+    hex::encode(get_txid(&preimage))
+}
+
+// builds the crediting transaction of core's script tests: a coinbase like transaction
+// with a single output of the amount locked to the scriptpubkey
+pub fn build_credit_tx(scriptpubkey: &[u8], amount: u64) -> Transaction {
+    let mut coinbase = new_input("00".repeat(32), 0xffffffff, &[0x00, 0x00], &[]);
+    coinbase.is_coinbase = true;
+    new_transaction(1, 0, vec![coinbase], vec![new_output(scriptpubkey, amount)])
+}
+
+// builds the spending transaction of core's script tests: spends the output of the
+// crediting transaction with scriptsig and witness to an output with empty script
+pub fn build_spend_tx(credit: &Transaction, scriptsig: &[u8], witness: &[Vec<u8>]) -> Transaction {
+    let credit_output = &credit.vout[0];
+    let mut txin = new_input(txid_hex(credit), 0, scriptsig, witness);
+    txin.prevout = new_prevout(
+        &hex::decode(credit_output.scriptpubkey.as_deref().unwrap_or_default())
+            .expect("Scriptpubkey hex invalid"),
+        credit_output.value,
+    );
+    new_transaction(1, 0, vec![txin], vec![new_output(&[], credit_output.value)])
+}
+
+// returns: the witness script (p2wsh) or tapscript (p2tr script path) of a witness program
+fn get_witness_script<'a>(program: &[u8], witness: &'a [Vec<u8>]) -> Option<&'a [u8]> {
+    match program {
+        [0x00, 0x20, ..] if program.len() == 34 => witness.last().map(Vec::as_slice),
+        [0x51, 0x20, ..] if program.len() == 34 => {
+            let has_annex = witness.len() >= 2 && witness.last()?.first() == Some(&0x50);
+            let stack = &witness[..witness.len() - has_annex as usize];
+            stack
+                .len()
+                .checked_sub(2)
+                .map(|index| stack[index].as_slice())
+        }
+        _ => None,
+    }
+}
+
+// returns: the opcodes of scriptsig and scriptpubkey and of the redeem script
+// and witness/leaf script they commit to
+pub fn get_spend_opcodes(scriptsig: &[u8], scriptpubkey: &[u8], witness: &[Vec<u8>]) -> Vec<u8> {
+    let mut scripts: Vec<Vec<u8>> = vec![scriptsig.to_vec(), scriptpubkey.to_vec()];
+    let mut program = scriptpubkey.to_vec();
+
+    let is_p2sh = scriptpubkey.len() == 23 && scriptpubkey[0] == 0xa9 && scriptpubkey[22] == 0x87;
+    if is_p2sh {
+        program = get_last_push(scriptsig).unwrap_or_default();
+        scripts.push(program.clone());
+    }
+    if let Some(witness_script) = get_witness_script(&program, witness) {
+        scripts.push(witness_script.to_vec());
+    }
+    scripts
+        .iter()
+        .flat_map(|script| {
+            split_script(script)
+                .into_iter()
+                .map(|operation| operation[0])
+        })
+        .collect()
+}
+
+// returns: report row of the opcode, pushes are grouped together
+fn opcode_group(opcode: u8) -> String {
+    match opcode {
+        0x01..=0x4b => "OP_PUSHBYTES_1-75".to_string(),
+        0x51..=0x60 => "OP_PUSHNUM_1-16".to_string(),
+        _ => opcode_name(opcode),
+    }
+}
+
+// pass/fail counts of the test vectors, grouped by the opcodes they use and the flags
+// they are verified with
+#[derive(Default)]
+pub struct Report {
+    pub passed: usize,
+    pub failures: Vec<String>,
+    by_opcode: BTreeMap<String, (usize, usize)>,
+    by_flag: BTreeMap<String, (usize, usize)>,
+}
+
+impl Report {
+    // counts the result of a test vector for each (distinct) opcode and flag it covers
+    pub fn record(&mut self, opcodes: &[u8], flags: VerifyFlags, failure: Option<String>) {
+        let mut groups: Vec<String> = opcodes.iter().map(|opcode| opcode_group(*opcode)).collect();
+        groups.sort();
+        groups.dedup();
+        let mut flag_names: Vec<String> =
+            flags.names().iter().map(|name| name.to_string()).collect();
+        if flag_names.is_empty() {
+            flag_names.push("NONE".to_string());
+        }
+        let passed = failure.is_none();
+        for (table, keys) in [
+            (&mut self.by_opcode, groups),
+            (&mut self.by_flag, flag_names),
+        ] {
+            for key in keys {
+                let counts = table.entry(key).or_default();
+                if passed {
+                    counts.0 += 1;
+                } else {
+                    counts.1 += 1;
+                }
+            }
+        }
+        match failure {
+            Some(failure) => self.failures.push(failure),
+            None => self.passed += 1,
+        }
+    }
+
+    // prints the summary and the per-opcode and per-flag tables
+    // (shown with cargo test -- --nocapture)
+    pub fn print(&self, title: &str) {
+        println!(
+            "\n{}: {} passed, {} failed",
+            title,
+            self.passed,
+            self.failures.len()
+        );
+        for (header, table) in [("opcode", &self.by_opcode), ("flag", &self.by_flag)] {
+            println!("\n{:<40}{:>6}{:>6}", header, "pass", "fail");
+            for (key, (passed, failed)) in table {
+                println!("{:<40}{:>6}{:>6}", key, passed, failed);
+            }
+        }
+        for failure in &self.failures {
+            println!("FAILED: {}", failure);
+        }
+    }
+}
@@ -0,0 +1,93 @@
+// Runs the script test vectors of bitcoin core (tests/data/script_tests.json) against
+// verify_script. Each test spends the output of a crediting transaction with the
+// scriptsig (and witness) and compares the resulting script error with the expected one.
+// The report is printed with: cargo test --test script_tests -- --nocapture
+
+mod common;
+
+use code_challenge_f321x::validation::{
+    signature_verification::verify_script, verify_flags::VerifyFlags,
+};
+use common::{
+    build_credit_tx, build_spend_tx, get_spend_opcodes, load_vectors, parse_script, Report,
+};
+use serde_json::Value;
+
+// parses the optional [wit..., amount] element of a test
+// returns: (witness elements, amount in sat)
+fn parse_witness(element: &Value) -> Result<(Vec<Vec<u8>>, u64), String> {
+    let element = element.as_array().ok_or("Witness is no array")?;
+    let (amount, witness) = element.split_last().ok_or("Witness without amount")?;
+    let amount = amount.as_f64().ok_or("Amount is no number")?;
+    let witness = witness
+        .iter()
+        .map(|item| hex::decode(item.as_str().unwrap_or_default()).map_err(|err| err.to_string()))
+        .collect::<Result<Vec<Vec<u8>>, String>>()?;
+    Ok((witness, (amount * 100_000_000.0).round() as u64))
+}
+
+// runs a single test entry
+// returns: Ok((opcodes, flags, failure)) or Err(reason) if the entry can't be parsed
+fn run_test(entry: &[Value]) -> Result<(Vec<u8>, VerifyFlags, Option<String>), String> {
+    let (witness, amount, fields) = match entry.first() {
+        Some(element @ Value::Array(_)) => {
+            let (witness, amount) = parse_witness(element)?;
+            (witness, amount, &entry[1..])
+        }
+        _ => (Vec::new(), 0, entry),
+    };
+    let field = |index: usize| fields[index].as_str().ok_or("Test field is no string");
+    let scriptsig = parse_script(field(0)?)?;
+    let scriptpubkey = parse_script(field(1)?)?;
+    let flags = VerifyFlags::from_names(field(2)?)?;
+    let expected = field(3)?;
+
+    let credit_tx = build_credit_tx(&scriptpubkey, amount);
+    let spend_tx = build_spend_tx(&credit_tx, &scriptsig, &witness);
+    let result = verify_script(&spend_tx, 0, &scriptsig, &scriptpubkey, &witness, flags);
+    let actual = match &result {
+        Ok(()) => "OK",
+        Err(err) => err.core_name(),
+    };
+    let failure = (actual != expected).then(|| {
+        let detail = result
+            .err()
+            .map(|err| format!(" ({})", err))
+            .unwrap_or_default();
+        format!(
+            "{}: expected {}, got {}{}",
+            Value::from(entry.to_vec()),
+            expected,
+            actual,
+            detail
+        )
+    });
+    let opcodes = get_spend_opcodes(&scriptsig, &scriptpubkey, &witness);
+    Ok((opcodes, flags, failure))
+}
+
+#[test]
+fn core_script_tests() {
+    let mut report = Report::default();
+
+    for entry in load_vectors("script_tests.json") {
+        let entry = entry.as_array().expect("Test entry is no array");
+        let is_witness_test = entry.first().is_some_and(Value::is_array);
+        if entry.len() < 4 + is_witness_test as usize {
+            continue; // comment
+        }
+        match run_test(entry) {
+            Ok((opcodes, flags, failure)) => report.record(&opcodes, flags, failure),
+            Err(err) => report
+                .failures
+                .push(format!("{}: {}", Value::from(entry.to_vec()), err)),
+        }
+    }
+    report.print("script_tests.json");
+    assert!(report.passed > 0, "No script tests found");
+    assert!(
+        report.failures.is_empty(),
+        "{} script tests failed",
+        report.failures.len()
+    );
+}
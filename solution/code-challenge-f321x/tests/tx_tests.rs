@@ -0,0 +1,134 @@
+// Runs the transaction test vectors of bitcoin core (tests/data/tx_valid.json and
+// tx_invalid.json) against the input verification of Transaction::validate.
+// tx_valid transactions have to be valid with all flags except the listed ones,
+// tx_invalid transactions have to be invalid with the listed flags.
+// BADTX entries have to fail the context free checks (check_transaction), all others pass them.
+// The report is printed with: cargo test --test tx_tests -- --nocapture
+
+mod common;
+
+use code_challenge_f321x::parsing::raw_transaction::{deserialize_transaction, new_txout, UtxoSet};
+use code_challenge_f321x::validation::{
+    signature_verification::verify_input, utils::decode_witness,
+    validate_values::check_transaction, verify_flags::VerifyFlags, ValidationResult,
+};
+use common::{all_flags, get_spend_opcodes, load_vectors, parse_script, Report};
+use serde_json::Value;
+
+// parses the prevouts [[prevout hash, prevout index, prevout scriptPubKey, amount?], ...]
+// returns: UtxoSet (txid hex, index) -> spent output
+fn parse_prevouts(prevouts: &Value) -> Result<UtxoSet, String> {
+    let mut parsed = UtxoSet::new();
+
+    for prevout in prevouts.as_array().ok_or("Prevouts are no array")? {
+        let prevout = prevout.as_array().ok_or("Prevout is no array")?;
+        if prevout.len() < 3 {
+            return Err("Prevout needs hash, index and scriptPubKey".to_string());
+        }
+        let txid = prevout[0].as_str().ok_or("Prevout hash is no string")?;
+        let index = prevout[1].as_i64().ok_or("Prevout index is no number")? as u32;
+        let scriptpubkey = parse_script(prevout[2].as_str().ok_or("ScriptPubKey is no string")?)?;
+        let amount = prevout.get(3).and_then(Value::as_u64).unwrap_or(0);
+        parsed.insert((txid.to_string(), index), new_txout(&scriptpubkey, amount));
+    }
+    Ok(parsed)
+}
+
+// runs a single test entry, checking all inputs with the flags of the entry
+// returns: Ok((opcodes, flags, failure)) or Err(reason) if the entry can't be parsed
+fn run_test(
+    entry: &[Value],
+    expect_valid: bool,
+) -> Result<(Vec<u8>, VerifyFlags, Option<String>), String> {
+    let prevouts = parse_prevouts(&entry[0])?;
+    let raw_tx = hex::decode(entry[1].as_str().ok_or("Transaction is no string")?)
+        .map_err(|err| err.to_string())?;
+    let flag_names = entry[2].as_str().ok_or("Flags are no string")?;
+    let tx = deserialize_transaction(&raw_tx, &prevouts)?;
+
+    let failure = match (check_transaction(&tx), flag_names == "BADTX") {
+        (ValidationResult::Valid, false) => None,
+        (ValidationResult::Invalid(_), true) => return Ok((Vec::new(), VerifyFlags::NONE, None)),
+        (ValidationResult::Valid, true) => {
+            Some("expected BADTX, passed check_transaction".to_string())
+        }
+        (ValidationResult::Invalid(reason), false) => {
+            Some(format!("check_transaction failed: {}", reason))
+        }
+    };
+    if let Some(failure) = failure {
+        let failure = format!("{}: {}", Value::from(entry.to_vec()), failure);
+        return Ok((Vec::new(), VerifyFlags::NONE, Some(failure)));
+    }
+
+    let flags = VerifyFlags::from_names(flag_names)?;
+    let flags = if expect_valid {
+        all_flags().remove(flags)
+    } else {
+        flags
+    };
+
+    let mut opcodes: Vec<u8> = Vec::new();
+    let mut invalid_reason: Option<String> = None;
+    for (input_index, txin) in tx.vin.iter().enumerate() {
+        let scriptsig = hex::decode(txin.scriptsig.as_deref().unwrap_or_default()).unwrap();
+        let scriptpubkey = hex::decode(&txin.prevout.scriptpubkey).unwrap();
+        opcodes.extend(get_spend_opcodes(
+            &scriptsig,
+            &scriptpubkey,
+            &decode_witness(txin)?,
+        ));
+        if let ValidationResult::Invalid(reason) = verify_input(&tx, input_index, flags) {
+            invalid_reason.get_or_insert(format!("input {}: {}", input_index, reason));
+        }
+    }
+    let failure = match (expect_valid, invalid_reason) {
+        (true, Some(reason)) => Some(format!("expected valid, got invalid {}", reason)),
+        (false, None) => Some("expected invalid, got valid".to_string()),
+        _ => None,
+    };
+    let failure = failure.map(|failure| format!("{}: {}", Value::from(entry.to_vec()), failure));
+    Ok((opcodes, flags, failure))
+}
+
+// runs all test entries of the file, comments (entries without prevouts) are skipped
+fn run_tx_vectors(file_name: &str, expect_valid: bool) -> Report {
+    let mut report = Report::default();
+
+    for entry in load_vectors(file_name) {
+        let entry = entry.as_array().expect("Test entry is no array");
+        if !entry.first().is_some_and(Value::is_array) {
+            continue; // comment
+        }
+        match run_test(entry, expect_valid) {
+            Ok((opcodes, flags, failure)) => report.record(&opcodes, flags, failure),
+            Err(err) => report
+                .failures
+                .push(format!("{}: {}", Value::from(entry.to_vec()), err)),
+        }
+    }
+    report.print(file_name);
+    report
+}
+
+#[test]
+fn core_tx_valid() {
+    let report = run_tx_vectors("tx_valid.json", true);
+    assert!(report.passed > 0, "No tx_valid tests found");
+    assert!(
+        report.failures.is_empty(),
+        "{} tx_valid tests failed",
+        report.failures.len()
+    );
+}
+
+#[test]
+fn core_tx_invalid() {
+    let report = run_tx_vectors("tx_invalid.json", false);
+    assert!(report.passed > 0, "No tx_invalid tests found");
+    assert!(
+        report.failures.is_empty(),
+        "{} tx_invalid tests failed",
+        report.failures.len()
+    );
+}